@@ -0,0 +1,31 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ima_core::{parse, ImaExitStatus, ImaOptions, IMA};
+
+/// Representative programs, with their name in the benchmark results.
+const PROGRAMS: [(&str, &str); 4] = [
+    ("loop", include_str!("programs/loop.ass")),
+    ("fact_rec", include_str!("programs/fact_rec.ass")),
+    ("sieve", include_str!("programs/sieve.ass")),
+    ("strings", include_str!("programs/strings.ass")),
+];
+
+/// Run each program until it halts. The parsing and the creation of the machine are not measured.
+fn run_programs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("programs");
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| b.iter_batched(
+            || IMA::new(parse(source).expect("Unable to parse benchmark program"), ImaOptions::default()),
+            |mut ima| {
+                let status = ima.run(&mut std::io::empty(), &mut std::io::sink()).expect("Benchmark program failed");
+                assert_eq!(status, ImaExitStatus::Halted);
+            },
+            BatchSize::SmallInput,
+        ));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_programs);
+criterion_main!(benches);
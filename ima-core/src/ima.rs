@@ -2,15 +2,39 @@
 
 
 pub mod address_modes;
+pub mod backtrace;
+pub mod callee_saved;
+pub mod clock;
 pub mod control_flow;
 pub mod cycles;
 pub mod data_type;
+pub mod decoded;
 pub mod error;
+pub mod history;
 pub mod instructions;
+pub mod io;
+pub mod limits;
+pub mod loop_detection;
+pub mod observer;
 pub mod options;
+pub mod profiler;
+pub mod rounding;
+pub mod sanitizer;
+pub mod shadow_stack;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod stack_budget;
+pub mod step;
+pub mod trace;
+pub mod trace_diff;
+pub mod uninitialized;
+pub mod warning;
+pub mod watchpoint;
 pub mod zones;
 
 use std::{
+    collections::HashMap,
+    rc::Rc,
     time::Instant,
     io::{
         BufRead,
@@ -18,23 +42,41 @@ use std::{
     }
 };
 
-use crate::ImaRunMode;
+use crate::{ImaRunMode, instructions::Instruction};
 
 use self::{
     zones::{
-        program::{Program, ReleaseModeProgram, DebugModeProgram, RunMode},
-        memory::{Memory, StackPointer, Pointer},
+        program::{Program, CodeAddr},
+        memory::{Memory, StackPointer, Pointer, allocator::AllocatorStats},
         registers::Registers, flags::Flags,
     },
-    error::ImaError,
+    error::{ImaError, ImaExecutionError},
+    io::StreamIo,
     options::ImaOptions,
-    control_flow::ImaControlFlow, address_modes::RegisterIndex,
+    control_flow::{ImaControlFlow, ImaExitStatus}, address_modes::RegisterIndex,
+    decoded::{DecodedProgram, Op},
+    limits::{ExecutionCounters, ExecutionLimits},
+    history::{CycleOutcome, History, DEFAULT_HISTORY},
+    loop_detection::LoopDetector,
+    observer::{ImaObserver, NoObserver},
+    profiler::Profiler,
+    rounding::RoundingMode,
+    sanitizer::HeapSanitizer,
+    stack_budget::StackBudgetChecker,
+    callee_saved::CalleeSavedChecker,
+    clock::ClockMode,
+    shadow_stack::ShadowStack,
+    step::StepOutcome,
+    trace::{Tracer, TraceSnapshot},
+    warning::{CheckMode, ImaWarning, WarningKind},
+    watchpoint::{WatchMode, WatchTarget, Watchpoint, Watchpoints, WatchedValue},
 };
 
 #[cfg(not(feature = "public-ima"))]
-pub struct IMA<RM: RunMode> {
+pub struct IMA<O: ImaObserver = NoObserver> {
     registers: Registers,
-    code: Program<RM>,
+    code: Program,
+    decoded: Rc<DecodedProgram>,
     memory: Memory,
     flags: Flags,
     gb: StackPointer,
@@ -44,12 +86,31 @@ pub struct IMA<RM: RunMode> {
     run_mode: ImaRunMode,
     control_flow: ImaControlFlow,
     cycle_count: usize,
+    instruction_count: usize,
+    output_bytes: usize,
+    limits: ExecutionLimits,
+    rounding_mode: RoundingMode,
+    clock_mode: ClockMode,
+    loop_detector: Option<LoopDetector>,
+    history: Option<History>,
+    watchpoints: Watchpoints,
+    profiler: Option<Profiler>,
+    tracer: Option<Tracer>,
+    uninitialized_mode: CheckMode,
+    warnings: Vec<ImaWarning>,
+    warning_index: HashMap<(CodeAddr, WarningKind), usize>,
+    heap_sanitizer: Option<HeapSanitizer>,
+    stack_budget_checker: Option<StackBudgetChecker>,
+    callee_saved_checker: Option<CalleeSavedChecker>,
+    shadow_stack: ShadowStack,
+    observer: O,
 }
 
 #[cfg(feature = "public-ima")]
-pub struct IMA<RM: RunMode> {
+pub struct IMA<O: ImaObserver = NoObserver> {
     pub registers: Registers,
-    pub code: Program<RM>,
+    pub code: Program,
+    pub decoded: Rc<DecodedProgram>,
     pub memory: Memory,
     pub flags: Flags,
     pub gb: StackPointer,
@@ -59,18 +120,54 @@ pub struct IMA<RM: RunMode> {
     pub run_mode: ImaRunMode,
     pub control_flow: ImaControlFlow,
     pub cycle_count: usize,
+    pub instruction_count: usize,
+    pub output_bytes: usize,
+    pub limits: ExecutionLimits,
+    pub rounding_mode: RoundingMode,
+    pub clock_mode: ClockMode,
+    pub loop_detector: Option<LoopDetector>,
+    pub history: Option<History>,
+    pub watchpoints: Watchpoints,
+    pub profiler: Option<Profiler>,
+    pub tracer: Option<Tracer>,
+    pub uninitialized_mode: CheckMode,
+    pub warnings: Vec<ImaWarning>,
+    pub warning_index: HashMap<(CodeAddr, WarningKind), usize>,
+    pub heap_sanitizer: Option<HeapSanitizer>,
+    pub stack_budget_checker: Option<StackBudgetChecker>,
+    pub callee_saved_checker: Option<CalleeSavedChecker>,
+    pub shadow_stack: ShadowStack,
+    pub observer: O,
 }
 
-impl<RM: RunMode> IMA<RM> {
+impl IMA {
     /// Creates a new IMA with the given program, options, input and output.
     pub fn new(
-        program: Program<RM>,
+        program: Program,
         options: ImaOptions,
-    ) -> IMA<RM> {
+    ) -> IMA {
+        IMA::with_observer(program, options, NoObserver)
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Creates a new IMA with the given program and options, notifying the observer while it runs.
+    pub fn with_observer(
+        program: Program,
+        options: ImaOptions,
+        observer: O,
+    ) -> IMA<O> {
+        let decoded = Rc::new(DecodedProgram::decode(&program));
+        // stepping back is mostly useful to debug
+        let history = options.history.unwrap_or(match options.run_mode {
+            ImaRunMode::Debug => DEFAULT_HISTORY,
+            _ => 0,
+        });
         IMA {
-            registers: Registers::new(16), // originally not changeable, but easily expandable.
+            registers: Registers::new(options.register_count),
             code: program,
-            memory: Memory::new(options.heap_size, options.stack_size),
+            decoded,
+            memory: Memory::with_allocator(options.heap_size, options.stack_size, options.allocator),
             flags: Flags::new(),
             gb: StackPointer::zero(),
             lb: StackPointer::zero(),
@@ -79,48 +176,188 @@ impl<RM: RunMode> IMA<RM> {
             run_mode: options.run_mode,
             control_flow: ImaControlFlow::Continue,
             cycle_count: 0,
+            instruction_count: 0,
+            output_bytes: 0,
+            limits: options.limits,
+            rounding_mode: RoundingMode::default(),
+            clock_mode: options.clock,
+            loop_detector: match options.detect_infinite_loops {
+                true => Some(LoopDetector::new()),
+                false => None,
+            },
+            history: match history {
+                0 => None,
+                capacity => Some(History::new(capacity)),
+            },
+            watchpoints: Watchpoints::new(),
+            profiler: match options.profile {
+                true => Some(Profiler::new()),
+                false => None,
+            },
+            tracer: None,
+            uninitialized_mode: options.uninitialized_mode,
+            warnings: Vec::new(),
+            warning_index: HashMap::new(),
+            heap_sanitizer: match options.sanitize_heap {
+                true => Some(HeapSanitizer::new()),
+                false => None,
+            },
+            stack_budget_checker: match options.tsto_check {
+                CheckMode::Off => None,
+                mode => Some(StackBudgetChecker::new(mode)),
+            },
+            callee_saved_checker: match options.callee_saved_check {
+                CheckMode::Off => None,
+                mode => Some(CalleeSavedChecker::new(
+                    mode,
+                    options.callee_saved_registers.into_iter().filter(|r| usize::from(r.0) < options.register_count).collect(),
+                )),
+            },
+            shadow_stack: ShadowStack::new(),
+            observer,
         }
     }
 }
 
-impl IMA<ReleaseModeProgram> {
-    /// Run the ima in release mode.
-    pub fn run<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), ImaError> {
+impl<O: ImaObserver> IMA<O> {
+    /// Run the ima in release mode, until it halts or stops on an error.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<ImaExitStatus, ImaError> {
         let res = loop {
-            let instruction = match self.code.fetch() {
-                Some(ins) => ins.clone(),
-                None => return Err(ImaError::NoMoreInstructions),
-            };
-            
-            self.code.increment_pc();
-
-            self.execute(instruction.clone(), input, output).map_err(|e|
-                ImaError::ExecutionError{
-                    error: e,
-                    line: self.code.pc(),
-                    instruction
-                }
-            )?;
-            
-            match self.control_flow {
-                ImaControlFlow::Continue => (),
-                ImaControlFlow::Halt => break Ok(()),
-                ImaControlFlow::Error => break Ok(()), // todo return with error or failure ?
+            if let StepOutcome::Halted(status) = self.execute_step(&mut StreamIo::new(&mut *input, &mut *output))? {
+                break Ok(status);
             }
         };
 
         if self.run_mode == ImaRunMode::Stats {
             write!(output, "Cycle count: {}\n", self.cycle_count).map_err(|e| ImaError::DebugIoError(e))?;
+            let heap_stats = self.memory.allocator_stats();
+            // programs that never use the heap keep the original output
+            if heap_stats.allocations + heap_stats.failed_allocations > 0 {
+                writeln!(output, "{}", heap_stats).map_err(ImaError::DebugIoError)?;
+            }
         }
 
         res
     }
 }
 
+impl<O: ImaObserver> IMA<O> {
+    /// Get the instruction at the given address as written in the program, for the errors and the warnings.
+    /// The decoded program has the same addresses as the program, so the instruction is always there.
+    fn instruction_at(&self, pc: CodeAddr) -> Instruction {
+        self.code.instruction(pc).cloned().expect("The decoded program has the addresses of the program")
+    }
+
+    /// Build the error for a failure of the instruction at the given address.
+    fn execution_error(&self, error: ImaExecutionError, pc: CodeAddr) -> ImaError {
+        ImaError::ExecutionError {
+            error,
+            line: self.code.source_line(pc),
+            instruction: self.instruction_at(pc),
+            backtrace: self.backtrace(),
+        }
+    }
+
+    /// Checks before the execution of the instruction at the given address.
+    /// Returns the snapshot of the machine if the instruction is traced.
+    fn before_execute(&mut self, op: &Op, pc: CodeAddr) -> Result<Option<TraceSnapshot>, ImaError> {
+        self.check_uninitialized(op, pc)?;
+        self.check_heap_access(op, pc)?;
+        self.make_heap_room(op);
+        self.check_stack_budget(op, pc)?;
+        self.check_callee_saved(op, pc)?;
+        self.check_frame(op, pc)?;
+        self.observe_before(op, pc);
+        Ok(self.trace_snapshot(pc))
+    }
+
+    /// Book-keeping after the successful execution of the instruction at the given address:
+    /// tracing, observer, watchpoints, profiling, heap, frames and calls tracking, execution limits and loop detection.
+    /// `cycles` is the cycle count before the execution of the instruction,
+    /// and `watched` the watchpoints resolved before it.
+    fn after_execute(
+        &mut self,
+        op: &Op,
+        pc: CodeAddr,
+        cycles: usize,
+        snapshot: Option<TraceSnapshot>,
+        watched: Vec<WatchedValue>,
+    ) -> Result<(), ImaError> {
+        let cycles = self.cycle_count - cycles;
+        let writes = self.memory.take_write_log();
+        if let Some(snapshot) = snapshot {
+            self.write_trace(snapshot, pc, cycles, &writes).map_err(ImaError::TraceIoError)?;
+        }
+        self.observe_after(op, pc, cycles, &writes);
+        if !watched.is_empty() {
+            self.watch_after(watched, pc, &writes);
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, op, cycles, self.code.pc());
+        }
+        self.track_heap(op, pc);
+        self.track_stack_budget(op);
+        self.track_callee_saved(op, pc);
+        self.track_frames(op, pc);
+        self.check_limits(pc)?;
+        self.check_infinite_loop(op, pc)
+    }
+
+    /// Get the current counters of the machine.
+    pub fn counters(&self) -> ExecutionCounters {
+        ExecutionCounters {
+            cycles: self.cycle_count,
+            instructions: self.instruction_count,
+            output_bytes: self.output_bytes,
+            elapsed: self.ima_start_time.elapsed(),
+        }
+    }
+
+    /// Get the statistics on the use of the heap.
+    pub fn allocator_stats(&self) -> AllocatorStats {
+        self.memory.allocator_stats()
+    }
+
+    /// Check the execution limits of the machine, after executing the instruction at the given address.
+    /// Fails if any of the limits is exceeded.
+    fn check_limits(&self, pc: CodeAddr) -> Result<(), ImaError> {
+        let line = self.code.source_line(pc);
+        if self.limits.max_cycles.is_some_and(|max| self.cycle_count > max) {
+            return Err(ImaError::CycleLimitReached { line, counters: self.counters() });
+        }
+        if self.limits.max_instructions.is_some_and(|max| self.instruction_count > max) {
+            return Err(ImaError::InstructionLimitReached { line, counters: self.counters() });
+        }
+        if self.limits.max_output_bytes.is_some_and(|max| self.output_bytes > max) {
+            return Err(ImaError::OutputLimitReached { line, counters: self.counters() });
+        }
+        if self.limits.timeout.is_some_and(|max| self.ima_start_time.elapsed() > max) {
+            return Err(ImaError::TimeoutReached { line, counters: self.counters() });
+        }
+        Ok(())
+    }
+
+    /// If loop detection is enabled, check the state of the machine after a backward branch.
+    /// Fails if the machine is back in a state it already was in.
+    fn check_infinite_loop(&mut self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        if self.loop_detector.is_none() {
+            return Ok(());
+        }
+        if loop_detection::is_external_input(op) {
+            // the machine could get out of the loop with a different input
+            self.loop_detector.as_mut().unwrap().clear();
+        }
+        else if loop_detection::is_branch(op) && self.code.pc() <= pc && self.visit_loop_state(self.code.pc()) {
+            return Err(ImaError::InfiniteLoop { line: self.code.source_line(pc), cycles: self.cycle_count });
+        }
+        Ok(())
+    }
+}
 
-impl IMA<DebugModeProgram> {
+impl<O: ImaObserver> IMA<O> {
     /// Runs the IMA in debug mode, expecting command line arguments from the user.
-    pub fn run_debug<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), ImaError> {
+    /// Returns how the machine stopped, like `run`. Quitting before the machine stops counts as a halt.
+    pub fn run_debug<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<ImaExitStatus, ImaError> {
         let res = loop {
             // fetch user input
             let mut command = String::new();
@@ -142,15 +379,20 @@ impl IMA<DebugModeProgram> {
                     if let Err(e) = self.run_until_breakpoint(input, output) {
                         output.write(format!("Error: {:?}", e).as_bytes()).map_err(|e| ImaError::DebugIoError(e))?;
                     }
+                    self.display_watch_hits(output).map_err(ImaError::DebugIoError)?;
                 },
-                ("c", "") => if let Err(e) = self.run_until_breakpoint(input, output) {
-                    output.write(format!("Error: {:?}", e).as_bytes()).map_err(|e| ImaError::DebugIoError(e))?;
+                ("c", "") => {
+                    if let Err(e) = self.run_until_breakpoint(input, output) {
+                        output.write(format!("Error: {:?}", e).as_bytes()).map_err(|e| ImaError::DebugIoError(e))?;
+                    }
+                    self.display_watch_hits(output).map_err(ImaError::DebugIoError)?;
                 }
                 ("a", line) => {
                     let line = line.trim();
                     match line.parse::<u32>() {
                         Ok(line) => {
-                            self.code.set_breakpoint(line);
+                            // the debugger shows the lines counted from 1
+                            self.code.set_breakpoint(line.saturating_sub(1));
                             output.write(format!("Breakpoint set at line {}\n", line).as_bytes()).map_err(|e| ImaError::DebugIoError(e))?;
                         },
                         Err(e) => {
@@ -162,7 +404,7 @@ impl IMA<DebugModeProgram> {
                     let line = line.trim();
                     match line.parse::<u32>() {
                         Ok(line) => {
-                            self.code.remove_breakpoint(line);
+                            self.code.remove_breakpoint(line.saturating_sub(1));
                             writeln!(output, "Breakpoint removed at line {}", line).map_err(|e| ImaError::DebugIoError(e))?;
                         },
                         Err(e) => {
@@ -175,20 +417,75 @@ impl IMA<DebugModeProgram> {
                     self.code.display_inst(output).map_err(|e| ImaError::DebugIoError(e))?;
                 }
                 ("x", "") => {
-                    let instruction = match self.code.fetch() {
-                        Some(ins) => ins.clone(),
-                        None => return Err(ImaError::NoMoreInstructions),
-                    };
-                    self.code.increment_pc();
-                    self.execute(instruction.clone(), input, output).map_err(|e|
-                        ImaError::ExecutionError{
-                            error: e,
-                            line: self.code.pc(),
-                            instruction
-                        }
-                    )?;
+                    if let Err(e) = self.execute_step(&mut StreamIo::new(&mut *input, &mut *output)) {
+                        writeln!(output, "Error: {:?}", e).map_err(ImaError::DebugIoError)?;
+                    }
+                    self.display_watch_hits(output).map_err(ImaError::DebugIoError)?;
                     self.code.display_inst(output).map_err(|e| ImaError::DebugIoError(e))?;
                 }
+                ("w", "") => {
+                    for watchpoint in self.watchpoints.points() {
+                        let mode = match watchpoint.mode {
+                            WatchMode::Write => "write",
+                            WatchMode::ReadWrite => "read or write",
+                        };
+                        match &watchpoint.condition {
+                            Some(condition) => writeln!(output, "Watchpoint {} on {}, when {}", watchpoint.target, mode, condition),
+                            None => writeln!(output, "Watchpoint {} on {}", watchpoint.target, mode),
+                        }.map_err(ImaError::DebugIoError)?;
+                    }
+                }
+                ("w", arg) => {
+                    match arg.parse::<Watchpoint>() {
+                        Ok(Watchpoint { target: WatchTarget::Register(register), .. }) if usize::from(register.0) >= self.registers.count() => {
+                            writeln!(output, "Register {} does not exist, the machine has {} registers", register, self.registers.count()).map_err(ImaError::DebugIoError)?;
+                        },
+                        Ok(watchpoint) => {
+                            writeln!(output, "Watchpoint set on {}", watchpoint).map_err(ImaError::DebugIoError)?;
+                            self.add_watchpoint(watchpoint);
+                        },
+                        Err(e) => writeln!(output, "{}", e).map_err(ImaError::DebugIoError)?,
+                    }
+                }
+                ("k", arg) => {
+                    match arg.parse::<WatchTarget>() {
+                        Ok(target) if self.remove_watchpoint(&target) => {
+                            writeln!(output, "Watchpoint removed on {}", target).map_err(ImaError::DebugIoError)?;
+                        },
+                        Ok(target) => writeln!(output, "No watchpoint on {}", target).map_err(ImaError::DebugIoError)?,
+                        Err(e) => writeln!(output, "{}", e).map_err(ImaError::DebugIoError)?,
+                    }
+                }
+                ("u", "") => {
+                    if !self.step_back() {
+                        writeln!(output, "No instruction to step back").map_err(ImaError::DebugIoError)?;
+                    }
+                    self.code.display_inst(output).map_err(ImaError::DebugIoError)?;
+                }
+                ("v", "") => {
+                    if !self.reverse_continue() {
+                        writeln!(output, "Reached the start of the history").map_err(ImaError::DebugIoError)?;
+                    }
+                    self.code.display_inst(output).map_err(ImaError::DebugIoError)?;
+                }
+                ("g", arg) => {
+                    let arg = arg.trim();
+                    match arg.parse::<usize>() {
+                        Ok(cycle) => match self.go_to_cycle(cycle, &mut StreamIo::new(&mut *input, &mut *output)) {
+                            Ok(CycleOutcome::Reached) => {},
+                            // reading from the stream waits for the input, so it is never pending
+                            Ok(CycleOutcome::Unreachable | CycleOutcome::NeedsInput) => {
+                                writeln!(output, "Unable to reach cycle {}", cycle).map_err(ImaError::DebugIoError)?
+                            },
+                            Err(e) => writeln!(output, "Error: {:?}", e).map_err(ImaError::DebugIoError)?,
+                        },
+                        Err(e) => {
+                            writeln!(output, "Failed to parse as usize: {}", e).map_err(ImaError::DebugIoError)?;
+                        }
+                    }
+                    writeln!(output, "Cycle count: {}", self.cycle_count).map_err(ImaError::DebugIoError)?;
+                    self.code.display_inst(output).map_err(ImaError::DebugIoError)?;
+                }
                 ("i", "") => {
                     self.code.display_inst(output).map_err(|e| ImaError::DebugIoError(e))?;
                 }
@@ -233,7 +530,11 @@ impl IMA<DebugModeProgram> {
                 }
                 ("b", arg) => {
                     let register = match arg.trim().parse::<u8>() {
-                        Ok(register) => register,
+                        Ok(register) if usize::from(register) < self.registers.count() => register,
+                        Ok(register) => {
+                            writeln!(output, "Register {} does not exist, the machine has {} registers", register, self.registers.count()).map_err(ImaError::DebugIoError)?;
+                            continue;
+                        }
                         Err(e) => {
                             writeln!(output, "Failed to parse as u8: {}", e).map_err(|e| ImaError::DebugIoError(e))?;
                             continue;
@@ -246,7 +547,7 @@ impl IMA<DebugModeProgram> {
                 }
 
                 
-                ("q", "") => break Ok(()),
+                ("q", "") => break Ok(ImaExitStatus::Halted),
                 _ => writeln!(output, "Unknown Command").map_err(|e| ImaError::DebugIoError(e))?,
             }
 
@@ -254,11 +555,11 @@ impl IMA<DebugModeProgram> {
                 ImaControlFlow::Continue => (),
                 ImaControlFlow::Halt => {
                     writeln!(output, "Machine exit on halt status.").map_err(|e| ImaError::DebugIoError(e))?;
-                    break Ok(())
+                    break Ok(ImaExitStatus::Halted)
                 },
                 ImaControlFlow::Error => {
                     writeln!(output, "Machine exit on error status.").map_err(|e| ImaError::DebugIoError(e))?;
-                    break Ok(())
+                    break Ok(ImaExitStatus::ErrorInstruction)
                 },
             }
         };
@@ -268,41 +569,40 @@ impl IMA<DebugModeProgram> {
         res
     }
 
-    /// Run the program until a breakpoint is reached.
+    /// Set a breakpoint on the first instruction at or after the given line index.
+    pub fn set_breakpoint(&mut self, line: CodeAddr) {
+        self.code.set_breakpoint(line);
+    }
+
+    /// Remove the breakpoint of the first instruction at or after the given line index.
+    pub fn remove_breakpoint(&mut self, line: CodeAddr) {
+        self.code.remove_breakpoint(line);
+    }
+
+    /// Run the program until a breakpoint is reached, or a watchpoint fires.
     /// If there is a breakpoint on the first instruction, it will be ignored.
     /// This allows to actually make progress when this is called reapeatedly.
-    pub fn run_until_breakpoint<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), ImaError> {
+    /// Returns the outcome of the last step: a breakpoint, a watchpoint, or how the machine stopped.
+    pub fn run_until_breakpoint<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<StepOutcome, ImaError> {
         loop {
-            let instruction = match self.code.fetch() {
-                Some(ins) => ins.clone(),
-                None => return Err(ImaError::NoMoreInstructions),
-            };
-            
-            self.code.increment_pc();
-
-            self.execute(instruction.clone(), input, output).map_err(|e|
-                ImaError::ExecutionError{
-                    error: e,
-                    line: self.code.pc(),
-                    instruction
-                }
-            )?;
-            
-            match self.control_flow {
-                ImaControlFlow::Continue => (),
-                ImaControlFlow::Halt => break Ok(()),
-                ImaControlFlow::Error => break Ok(()), // todo return with error or failure ?
+            match self.execute_step(&mut StreamIo::new(&mut *input, &mut *output))? {
+                StepOutcome::Continued => (),
+                outcome => break Ok(outcome),
             }
+        }
+    }
 
-            if self.code.is_breakpoint() {
-                break Ok(());
-            }
+    /// Display the watchpoints that fired on the last instruction, with the values before and after it.
+    fn display_watch_hits<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        for hit in self.watchpoints.hits() {
+            writeln!(output, "{}", hit)?;
         }
+        Ok(())
     }
 
     /// Reset the ima to its initial state.
     pub fn reset(&mut self) {
-        self.registers = Registers::new(16);
+        self.registers = Registers::new(self.registers.count());
         self.memory.clear();
         self.flags = Flags::new();
         self.gb = StackPointer::zero();
@@ -310,6 +610,32 @@ impl IMA<DebugModeProgram> {
         self.sp = StackPointer::zero();
         self.ima_start_time = Instant::now();
         self.control_flow = ImaControlFlow::Continue;
+        self.cycle_count = 0;
+        self.instruction_count = 0;
+        self.output_bytes = 0;
+        self.rounding_mode = RoundingMode::default();
+        if let Some(loop_detector) = self.loop_detector.as_mut() {
+            loop_detector.clear();
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.watchpoints.clear_hits();
+        if let Some(profiler) = self.profiler.as_mut() {
+            *profiler = Profiler::new();
+        }
+        self.warnings.clear();
+        self.warning_index.clear();
+        if let Some(heap_sanitizer) = self.heap_sanitizer.as_mut() {
+            *heap_sanitizer = HeapSanitizer::new();
+        }
+        if let Some(checker) = self.stack_budget_checker.as_mut() {
+            checker.reset();
+        }
+        if let Some(checker) = self.callee_saved_checker.as_mut() {
+            checker.reset();
+        }
+        self.shadow_stack = ShadowStack::new();
         self.code.reset();
     }
 }
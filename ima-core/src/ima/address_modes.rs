@@ -7,19 +7,17 @@ use super::{
         DataTypeFlag
     },
     IMA,
+    observer::ImaObserver,
     zones::{
         memory::Pointer,
-        program::{
-            CodeAddr,
-            RunMode
-        }
+        program::CodeAddr
     },
     error::ImaExecutionError
 };
 
 /// Designes a register name Rm (R0, R1, R2, ...)
-/// The holded value is less than the max register count, 16.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+/// The holded value is less than the register count of the machine, 16 by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
 pub struct RegisterIndex(pub u8);
 
 impl Display for RegisterIndex {
@@ -102,74 +100,131 @@ impl Display for DVAL {
     }
 }
 
+/// Flat encoding of a DADR, computed once when the program is loaded.
+/// The address is the base register, offset by the immediate and by the index register if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    /// Register holding the base address.
+    pub base: Register,
+    /// Register holding an integer offset, if any.
+    pub index: Option<RegisterIndex>,
+    /// Immediate offset.
+    pub offset: i32,
+}
+
+impl From<&DADR> for Address {
+    fn from(dadr: &DADR) -> Self {
+        match *dadr {
+            DADR::OffsetIndirect { register, offset } => Address {
+                base: register,
+                index: None,
+                offset,
+            },
+            DADR::OffsetAndDisplacedIndirect { address_register, register_offset, immediate_offset } => Address {
+                base: address_register,
+                index: Some(register_offset),
+                offset: immediate_offset,
+            },
+        }
+    }
+}
+
+impl Address {
+    /// Get the registers Rm used to compute the address.
+    pub fn registers(&self) -> Vec<RegisterIndex> {
+        let base = match self.base {
+            Register::R(index) => Some(index),
+            _ => None,
+        };
+        base.into_iter().chain(self.index).collect()
+    }
+}
+
+/// Flat encoding of a DVAL, computed once when the program is loaded.
+/// Labels are already resolved, so they are immediate code addresses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    /// Value at the given address.
+    Memory(Address),
+    /// Value in the given register.
+    Register(RegisterIndex),
+    /// Immediate value, or code address.
+    Immediate(DataType),
+}
+
+impl From<&DVAL> for Operand {
+    fn from(dval: &DVAL) -> Self {
+        match dval {
+            DVAL::DADR(dadr) => Operand::Memory(dadr.into()),
+            DVAL::Register(index) => Operand::Register(*index),
+            DVAL::Immediate(value) => Operand::Immediate(*value),
+            DVAL::Label(addr) => Operand::Immediate(DataType::CodeAddr(*addr)),
+        }
+    }
+}
+
+impl Operand {
+    /// Get the registers Rm used to compute the value.
+    pub fn registers(&self) -> Vec<RegisterIndex> {
+        match self {
+            Operand::Memory(address) => address.registers(),
+            Operand::Register(index) => vec![*index],
+            Operand::Immediate(_) => Vec::new(),
+        }
+    }
+}
+
 /// Trait to allows to compute DADR.
 /// Because DADR depends on register values, the DADR in itself means nothing.
 /// It need a machine support to be computed.
 pub trait GetDadr {
-    fn get_dadr(&self, dadr: DADR) -> Result<Pointer, ImaExecutionError>;
+    fn get_dadr(&self, dadr: Address) -> Result<Pointer, ImaExecutionError>;
 }
 
 /// Trait to allows to compute DVAL.
 /// Because DVAL can depends on DADR or registers, the DVAL in itself means nothing.
 /// It need a machine support to be computed.
 pub trait GetDval: GetDadr {
-    fn get_dval(&self, dval: DVAL) -> Result<DataType, ImaExecutionError>;
+    fn get_dval(&self, dval: Operand) -> Result<DataType, ImaExecutionError>;
 }
 
-impl<RM: RunMode> GetDadr for IMA<RM> {
-    fn get_dadr(&self, dadr: DADR) -> Result<Pointer, ImaExecutionError> {
-        match dadr {
-            DADR::OffsetIndirect { register, offset } => {
-                let register_value = match register {
-                    Register::GB => Pointer::Stack(self.gb),
-                    Register::LB => Pointer::Stack(self.lb),
-                    Register::SP => Pointer::Stack(self.sp),
-                    Register::R(index) => match self.registers.get(index) {
-                        DataType::MemAddr(value) => value,
-                        other => return Err(ImaExecutionError::InvalidDataType {
-                            expected: DataTypeFlag::MemAddr,
-                            found: other.into()
-                        }),
-                    },
-                };
-                register_value.offset(offset)
+impl<O: ImaObserver> GetDadr for IMA<O> {
+    fn get_dadr(&self, dadr: Address) -> Result<Pointer, ImaExecutionError> {
+        let base = match dadr.base {
+            Register::GB => Pointer::Stack(self.gb),
+            Register::LB => Pointer::Stack(self.lb),
+            Register::SP => Pointer::Stack(self.sp),
+            Register::R(index) => match self.registers.get(index) {
+                DataType::MemAddr(value) => value,
+                other => return Err(ImaExecutionError::InvalidDataType {
+                    expected: DataTypeFlag::MemAddr,
+                    found: other.into()
+                }),
             },
-            DADR::OffsetAndDisplacedIndirect { address_register, register_offset, immediate_offset } => {
-                let address_register_value = match address_register {
-                    Register::GB => Pointer::Stack(self.gb),
-                    Register::LB => Pointer::Stack(self.lb),
-                    Register::SP => Pointer::Stack(self.sp),
-                    Register::R(index) => match self.registers.get(index) {
-                        DataType::MemAddr(value) => value,
-                        other => return Err(ImaExecutionError::InvalidDataType {
-                            expected: DataTypeFlag::MemAddr,
-                            found: other.into()
-                        }),
-                    },
-                };
-                let register_offset_value = match self.registers.get(register_offset) {
-                    DataType::Int(value) => value,
-                    other => return Err(ImaExecutionError::InvalidDataType {
-                        expected: DataTypeFlag::Int,
-                        found: other.into()
-                    }),
-                };
-                address_register_value.offset(register_offset_value + immediate_offset)
+        };
+        let offset = match dadr.index {
+            Some(index) => match self.registers.get(index) {
+                DataType::Int(value) => value + dadr.offset,
+                other => return Err(ImaExecutionError::InvalidDataType {
+                    expected: DataTypeFlag::Int,
+                    found: other.into()
+                }),
             },
-        }
+            None => dadr.offset,
+        };
+        base.offset(offset)
     }
 }
 
-impl<RM: RunMode> GetDval for IMA<RM> {
-    fn get_dval(&self, dval: DVAL) -> Result<DataType, ImaExecutionError> {
+impl<O: ImaObserver> GetDval for IMA<O> {
+    fn get_dval(&self, dval: Operand) -> Result<DataType, ImaExecutionError> {
         match dval {
-            DVAL::DADR(dadr) => {
+            Operand::Memory(dadr) => {
                 let ptr = self.get_dadr(dadr)?;
                 self.memory.get(ptr).ok_or(ImaExecutionError::InvalidMemoryAddress(ptr))
             },
-            DVAL::Register(index) => Ok(self.registers.get(index)),
-            DVAL::Immediate(value) => Ok(value),
-            DVAL::Label(addr) => Ok(DataType::CodeAddr(addr)),
+            Operand::Register(index) => Ok(self.registers.get(index)),
+            Operand::Immediate(value) => Ok(value),
         }
     }
-}
\ No newline at end of file
+}
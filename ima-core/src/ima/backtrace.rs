@@ -0,0 +1,106 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::fmt::Display;
+
+use crate::{
+    instructions::Instruction,
+    parser::label::Label,
+};
+
+use super::{
+    IMA,
+    address_modes::DVAL,
+    data_type::DataType,
+    observer::ImaObserver,
+    zones::{
+        memory::{Pointer, StackPointer},
+        program::{CodeAddr, SourceLocation},
+    },
+};
+
+/// Maximum number of frames in a backtrace, to keep errors readable on deep recursions.
+const MAX_FRAMES: usize = 64;
+
+/// An active call on the machine, found by walking the LB chain.
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    /// Label of the called subroutine, if it could be found.
+    pub callee: Option<Label>,
+    /// Location of the BSR instruction that made the call.
+    pub call_site: Option<SourceLocation>,
+    /// Value of LB in the called subroutine.
+    pub lb: StackPointer,
+}
+
+impl Display for CallFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.callee {
+            Some(label) => write!(f, "in {}", label)?,
+            None => write!(f, "in <unknown>")?,
+        }
+        match &self.call_site {
+            Some(location) => write!(f, ", called at line {}", location.line),
+            None => write!(f, ", called from an unknown line"),
+        }
+    }
+}
+
+/// All the active calls of the machine, the innermost first.
+#[derive(Debug, Clone, Default)]
+pub struct Backtrace {
+    pub frames: Vec<CallFrame>,
+    /// The LB chain was corrupted or too long, so not all frames could be found.
+    pub truncated: bool,
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n    #{} {}", i, frame)?;
+        }
+        if self.truncated {
+            write!(f, "\n    ...")?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Walk the LB chain to find all active calls.
+    /// Each BSR saves the return address at LB - 1 and the previous LB at LB.
+    pub fn backtrace(&self) -> Backtrace {
+        let mut backtrace = Backtrace::default();
+        let mut lb = self.lb;
+        while lb != self.gb {
+            if backtrace.frames.len() >= MAX_FRAMES {
+                backtrace.truncated = true;
+                break;
+            }
+            let return_addr = lb.offset(-1).and_then(|addr| self.memory.get_stack(addr));
+            let previous_lb = self.memory.get_stack(lb);
+            let (return_addr, previous_lb) = match (return_addr, previous_lb) {
+                (Some(DataType::CodeAddr(ret)), Some(DataType::MemAddr(Pointer::Stack(prev)))) if prev < lb => (ret, prev),
+                _ => {
+                    backtrace.truncated = true;
+                    break;
+                }
+            };
+            let call_site = self.code.previous_pc(return_addr);
+            backtrace.frames.push(CallFrame {
+                callee: call_site.and_then(|pc| self.callee(pc)),
+                call_site: call_site.and_then(|pc| self.code.location(pc)),
+                lb,
+            });
+            lb = previous_lb;
+        }
+        backtrace
+    }
+
+    /// Find the label of the subroutine called by the BSR instruction at the given address.
+    pub(super) fn callee(&self, call_site: CodeAddr) -> Option<Label> {
+        match self.code.instruction(call_site) {
+            Some(Instruction::BSR(DVAL::Label(addr))) => self.code.location(*addr)?.label,
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,135 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::fmt::Display;
+
+use crate::parser::label::Label;
+use super::{
+    IMA,
+    address_modes::RegisterIndex,
+    data_type::DataType,
+    decoded::Op,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    warning::{CheckMode, WarningKind},
+    zones::program::{CodeAddr, DisplayLine},
+};
+
+/// Registers a subroutine did not restore before returning.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClobberedRegisters {
+    /// Label of the subroutine, if it could be found.
+    pub callee: Option<Label>,
+    /// Source line of the BSR instruction that made the call, if it is known.
+    pub call_site: Option<u32>,
+    /// The registers that changed during the call.
+    pub registers: Vec<RegisterIndex>,
+}
+
+impl Display for ClobberedRegisters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Registers")?;
+        for (i, register) in self.registers.iter().enumerate() {
+            match i {
+                0 => write!(f, " {}", register)?,
+                _ => write!(f, ", {}", register)?,
+            }
+        }
+        match &self.callee {
+            Some(label) => write!(f, " not restored by {}", label)?,
+            None => write!(f, " not restored by <unknown>")?,
+        }
+        write!(f, ", called at {}", DisplayLine(self.call_site))
+    }
+}
+
+/// Register values at the time of a call.
+#[derive(Debug, Clone)]
+struct SavedRegisters {
+    /// Address of the BSR instruction that made the call.
+    call_site: CodeAddr,
+    /// Values of the callee-saved registers, in the order of the checker registers.
+    values: Vec<DataType>,
+}
+
+/// Snapshots the callee-saved registers at each BSR, to check they are restored at the matching RTS.
+#[derive(Debug, Clone)]
+pub struct CalleeSavedChecker {
+    /// Whether clobbered registers are a warning or an error.
+    mode: CheckMode,
+    /// The registers a subroutine must restore before returning.
+    registers: Vec<RegisterIndex>,
+    /// Snapshots of the active calls, the innermost last.
+    calls: Vec<SavedRegisters>,
+}
+
+impl CalleeSavedChecker {
+    /// Creates a checker for the given callee-saved registers, with no active calls.
+    pub fn new(mode: CheckMode, registers: Vec<RegisterIndex>) -> CalleeSavedChecker {
+        CalleeSavedChecker {
+            mode,
+            registers,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Forget all the active calls.
+    pub fn reset(&mut self) {
+        self.calls.clear();
+    }
+}
+
+/// The default callee-saved registers of the calling convention: R2 to R15.
+pub fn default_callee_saved() -> Vec<RegisterIndex> {
+    (2..16).map(RegisterIndex).collect()
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Before the execution of a RTS, check the callee-saved registers have their values from the matching BSR.
+    pub(super) fn check_callee_saved(&mut self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        let checker = match (self.callee_saved_checker.as_ref(), op) {
+            (Some(checker), Op::RTS) => checker,
+            _ => return Ok(()),
+        };
+        let saved = match checker.calls.last() {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+        let registers: Vec<RegisterIndex> = checker.registers.iter()
+            .zip(saved.values.iter())
+            .filter(|(register, value)| self.registers.get(**register) != **value)
+            .map(|(register, _)| *register)
+            .collect();
+        if registers.is_empty() {
+            return Ok(());
+        }
+        let clobbered = ClobberedRegisters {
+            callee: self.callee(saved.call_site),
+            call_site: self.code.source_line(saved.call_site),
+            registers,
+        };
+        self.report(
+            checker.mode,
+            WarningKind::RegistersClobbered(clobbered.clone()),
+            ImaExecutionError::RegistersClobbered(clobbered),
+            pc,
+        )
+    }
+
+    /// After the execution of an instruction, snapshot the registers on BSR and forget them on RTS.
+    pub(super) fn track_callee_saved(&mut self, op: &Op, pc: CodeAddr) {
+        let checker = match self.callee_saved_checker.as_mut() {
+            Some(checker) => checker,
+            None => return,
+        };
+        match op {
+            Op::BSR(_) => {
+                let values = checker.registers.iter().map(|register| self.registers.get(*register)).collect();
+                checker.calls.push(SavedRegisters { call_site: pc, values });
+            },
+            Op::RTS => {
+                checker.calls.pop();
+            },
+            _ => {},
+        }
+    }
+}
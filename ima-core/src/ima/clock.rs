@@ -0,0 +1,71 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::str::FromStr;
+
+use super::{
+    IMA,
+    observer::ImaObserver,
+};
+
+/// Frequency of the virtual clock when none is given, in cycles per second.
+pub const DEFAULT_VIRTUAL_FREQUENCY: u64 = 1_000_000;
+
+/// Where the CLK and SCLK instructions get the time from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockMode {
+    /// CLK measures the real time since the start of the machine, and SCLK reads the UTC wall clock.
+    /// This is the original behavior.
+    #[default]
+    Real,
+    /// The time is derived from the cycle count, at the given number of cycles per second.
+    /// The machine starts on January 1, 2001, 00:00:00 UTC, so SCLK starts at 0.
+    Virtual {
+        frequency: u64,
+    },
+    /// The time never moves: CLK is always 0, and SCLK always gives the epoch,
+    /// in seconds since January 1, 2001, 00:00:00 UTC.
+    Fixed {
+        epoch: i32,
+    },
+}
+
+impl FromStr for ClockMode {
+    type Err = ();
+    /// Parse "real", "virtual", "virtual:FREQUENCY" or "fixed:EPOCH".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "real" => Ok(ClockMode::Real),
+            None if s == "virtual" => Ok(ClockMode::Virtual { frequency: DEFAULT_VIRTUAL_FREQUENCY }),
+            Some(("virtual", frequency)) => match frequency.parse() {
+                Ok(frequency) if frequency > 0 => Ok(ClockMode::Virtual { frequency }),
+                _ => Err(()),
+            },
+            Some(("fixed", epoch)) => epoch.parse().map(|epoch| ClockMode::Fixed { epoch }).map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Seconds elapsed since the start of the machine, for CLK.
+    pub(super) fn clock_elapsed(&self) -> f64 {
+        match self.clock_mode {
+            ClockMode::Real => self.ima_start_time.elapsed().as_secs_f64(),
+            ClockMode::Virtual { frequency } => self.cycle_count as f64 / frequency as f64,
+            ClockMode::Fixed { .. } => 0.0,
+        }
+    }
+
+    /// Seconds since January 1, 2001, 00:00:00 UTC, for SCLK.
+    pub(super) fn clock_seconds(&self) -> i64 {
+        use chrono::TimeZone;
+        match self.clock_mode {
+            ClockMode::Real => {
+                let start_date = chrono::Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).single().unwrap();
+                chrono::Utc::now().signed_duration_since(start_date).num_seconds()
+            },
+            ClockMode::Virtual { frequency } => (self.cycle_count as u64 / frequency) as i64,
+            ClockMode::Fixed { epoch } => epoch.into(),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 /// Created by Virgile HENRY, 2023/09/28
 
 /// Control flow for the IMA interpreter.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy)]
 pub enum ImaControlFlow {
     /// The machine should continue.
@@ -9,4 +10,14 @@ pub enum ImaControlFlow {
     Halt,
     /// The machine have been instructed to stop with an error.
     Error,
-}
\ No newline at end of file
+}
+
+/// How the machine stopped, when it stopped on its own.
+/// Runtime errors are not part of this, they are reported as errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImaExitStatus {
+    /// The machine executed a HALT instruction.
+    Halted,
+    /// The machine executed an ERROR instruction.
+    ErrorInstruction,
+}
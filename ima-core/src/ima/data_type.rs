@@ -12,6 +12,7 @@ pub type Float = f32;
 
 /// The IMA stores data on 32 bits, but each word is data type tagged.
 /// therefore this enum represent any data the machine can hold, and the type of the data.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DataType {
     Int(Int),
@@ -28,6 +29,15 @@ impl DataType {
             _ => false,
         }
     }
+
+    /// Check if both words hold exactly the same bits:
+    /// unlike `==`, a NaN is the same as itself, and 0.0 is not the same as -0.0.
+    pub fn same_bits(&self, other: &DataType) -> bool {
+        match (self, other) {
+            (DataType::Float(a), DataType::Float(b)) => a.to_bits() == b.to_bits(),
+            (a, b) => a == b,
+        }
+    }
 }
 
 impl Display for DataType {
@@ -0,0 +1,390 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::instructions::Instruction;
+
+use super::{
+    address_modes::{Address, Operand, RegisterIndex},
+    cycles::CycleCost,
+    zones::{
+        flags::Flags,
+        program::{CodeAddr, Program},
+    },
+};
+
+type Rm = RegisterIndex;
+
+/// Flag checked by a conditional instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    EQ,
+    NE,
+    GT,
+    GE,
+    LT,
+    LE,
+    OV,
+}
+
+impl Condition {
+    /// Check if the condition holds with the given flags.
+    pub fn holds(self, flags: &Flags) -> bool {
+        match self {
+            Condition::EQ => flags.eq(),
+            Condition::NE => flags.ne(),
+            Condition::GT => flags.gt(),
+            Condition::GE => flags.ge(),
+            Condition::LT => flags.lt(),
+            Condition::LE => flags.le(),
+            Condition::OV => flags.ov(),
+        }
+    }
+}
+
+/// An instruction with its operands in their flat encoding, ready to be executed.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    LOAD(Operand, Rm),
+    STORE(Rm, Address),
+    PUSH(Rm),
+    POP(Rm),
+    LEA(Address, Rm),
+    PEA(Address),
+    NEW(Operand, Rm),
+    DEL(Rm),
+    CMP(Operand, Rm),
+    ADD(Operand, Rm),
+    SUB(Operand, Rm),
+    MUL(Operand, Rm),
+    OPP(Operand, Rm),
+    QUO(Operand, Rm),
+    REM(Operand, Rm),
+    SEQ(Rm),
+    SGT(Rm),
+    SGE(Rm),
+    SOV(Rm),
+    SNE(Rm),
+    SLT(Rm),
+    SLE(Rm),
+    SHL(Rm),
+    SHR(Rm),
+    DIV(Operand, Rm),
+    FMA(Operand, Rm),
+    FLOAT(Operand, Rm),
+    INT(Operand, Rm),
+    SETROUND_TONEAREST,
+    SETROUND_UPWARD,
+    SETROUND_DOWNWARD,
+    SETROUND_TOWARDZERO,
+    BRA(Operand),
+    BEQ(Operand),
+    BGT(Operand),
+    BGE(Operand),
+    BOV(Operand),
+    BNE(Operand),
+    BLT(Operand),
+    BLE(Operand),
+    BSR(Operand),
+    RTS,
+    RINT,
+    RFLOAT,
+    WINT,
+    WFLOAT,
+    WFLOATX,
+    WSTR(String),
+    WNL,
+    RUTF8,
+    WUTF8,
+    ADDSP(u32),
+    SUBSP(u32),
+    TSTO(u32),
+    HALT,
+    ERROR,
+    SCLK,
+    CLK,
+}
+
+impl From<&Instruction> for Op {
+    fn from(instruction: &Instruction) -> Self {
+        match instruction {
+            Instruction::LOAD(dval, rm) => Op::LOAD(dval.into(), *rm),
+            Instruction::STORE(rm, dadr) => Op::STORE(*rm, dadr.into()),
+            Instruction::PUSH(rm) => Op::PUSH(*rm),
+            Instruction::POP(rm) => Op::POP(*rm),
+            Instruction::LEA(dadr, rm) => Op::LEA(dadr.into(), *rm),
+            Instruction::PEA(dadr) => Op::PEA(dadr.into()),
+            Instruction::NEW(dval, rm) => Op::NEW(dval.into(), *rm),
+            Instruction::DEL(rm) => Op::DEL(*rm),
+            Instruction::CMP(dval, rm) => Op::CMP(dval.into(), *rm),
+            Instruction::ADD(dval, rm) => Op::ADD(dval.into(), *rm),
+            Instruction::SUB(dval, rm) => Op::SUB(dval.into(), *rm),
+            Instruction::MUL(dval, rm) => Op::MUL(dval.into(), *rm),
+            Instruction::OPP(dval, rm) => Op::OPP(dval.into(), *rm),
+            Instruction::QUO(dval, rm) => Op::QUO(dval.into(), *rm),
+            Instruction::REM(dval, rm) => Op::REM(dval.into(), *rm),
+            Instruction::SEQ(rm) => Op::SEQ(*rm),
+            Instruction::SGT(rm) => Op::SGT(*rm),
+            Instruction::SGE(rm) => Op::SGE(*rm),
+            Instruction::SOV(rm) => Op::SOV(*rm),
+            Instruction::SNE(rm) => Op::SNE(*rm),
+            Instruction::SLT(rm) => Op::SLT(*rm),
+            Instruction::SLE(rm) => Op::SLE(*rm),
+            Instruction::SHL(rm) => Op::SHL(*rm),
+            Instruction::SHR(rm) => Op::SHR(*rm),
+            Instruction::DIV(dval, rm) => Op::DIV(dval.into(), *rm),
+            Instruction::FMA(dval, rm) => Op::FMA(dval.into(), *rm),
+            Instruction::FLOAT(dval, rm) => Op::FLOAT(dval.into(), *rm),
+            Instruction::INT(dval, rm) => Op::INT(dval.into(), *rm),
+            Instruction::SETROUND_TONEAREST => Op::SETROUND_TONEAREST,
+            Instruction::SETROUND_UPWARD => Op::SETROUND_UPWARD,
+            Instruction::SETROUND_DOWNWARD => Op::SETROUND_DOWNWARD,
+            Instruction::SETROUND_TOWARDZERO => Op::SETROUND_TOWARDZERO,
+            Instruction::BRA(dval) => Op::BRA(dval.into()),
+            Instruction::BEQ(dval) => Op::BEQ(dval.into()),
+            Instruction::BGT(dval) => Op::BGT(dval.into()),
+            Instruction::BGE(dval) => Op::BGE(dval.into()),
+            Instruction::BOV(dval) => Op::BOV(dval.into()),
+            Instruction::BNE(dval) => Op::BNE(dval.into()),
+            Instruction::BLT(dval) => Op::BLT(dval.into()),
+            Instruction::BLE(dval) => Op::BLE(dval.into()),
+            Instruction::BSR(dval) => Op::BSR(dval.into()),
+            Instruction::RTS => Op::RTS,
+            Instruction::RINT => Op::RINT,
+            Instruction::RFLOAT => Op::RFLOAT,
+            Instruction::WINT => Op::WINT,
+            Instruction::WFLOAT => Op::WFLOAT,
+            Instruction::WFLOATX => Op::WFLOATX,
+            Instruction::WSTR(string) => Op::WSTR(string.clone()),
+            Instruction::WNL => Op::WNL,
+            Instruction::RUTF8 => Op::RUTF8,
+            Instruction::WUTF8 => Op::WUTF8,
+            Instruction::ADDSP(value) => Op::ADDSP(*value),
+            Instruction::SUBSP(value) => Op::SUBSP(*value),
+            Instruction::TSTO(value) => Op::TSTO(*value),
+            Instruction::HALT => Op::HALT,
+            Instruction::ERROR => Op::ERROR,
+            Instruction::SCLK => Op::SCLK,
+            Instruction::CLK => Op::CLK,
+        }
+    }
+}
+
+/// The operands an instruction accesses, with the registers it uses implicitly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operands {
+    /// Value operand (dval) read by the instruction.
+    pub value: Option<Operand>,
+    /// Address written by STORE.
+    pub destination: Option<Address>,
+    /// Address computed by LEA and PEA, which do not access it.
+    pub address: Option<Address>,
+    /// Registers read besides the value operand: Rm of the arithmetic, STORE and PUSH, R0 of FMA and R1 of the writes.
+    pub registers: Vec<RegisterIndex>,
+    /// Register written: Rm, R0 of CLK or R1 of the reads.
+    pub written: Option<RegisterIndex>,
+    /// True if the instruction computes with the values it reads,
+    /// false if it only moves them around, jumps to them or frees them.
+    pub computes: bool,
+}
+
+impl Operands {
+    /// Get the memory operand accessed by the instruction, read or written.
+    pub fn accessed(&self) -> Option<Address> {
+        match self.value {
+            Some(Operand::Memory(address)) => Some(address),
+            _ => self.destination,
+        }
+    }
+
+    /// Get all the registers read by the instruction, including the ones used to compute addresses.
+    pub fn registers_read(&self) -> Vec<RegisterIndex> {
+        let mut registers = self.value.map(|value| value.registers()).unwrap_or_default();
+        registers.extend(self.destination.or(self.address).map(|address| address.registers()).unwrap_or_default());
+        registers.extend(&self.registers);
+        registers
+    }
+}
+
+impl Op {
+    /// Get the operands the instruction accesses.
+    pub fn operands(&self) -> Operands {
+        match self {
+            Op::ADD(value, rm) |
+            Op::SUB(value, rm) |
+            Op::MUL(value, rm) |
+            Op::DIV(value, rm) |
+            Op::QUO(value, rm) |
+            Op::REM(value, rm) => Operands {
+                value: Some(*value),
+                registers: vec![*rm],
+                written: Some(*rm),
+                computes: true,
+                ..Operands::default()
+            },
+            Op::CMP(value, rm) => Operands {
+                value: Some(*value),
+                registers: vec![*rm],
+                computes: true,
+                ..Operands::default()
+            },
+            Op::FMA(value, rm) => Operands {
+                value: Some(*value),
+                registers: vec![*rm, RegisterIndex(0)],
+                written: Some(*rm),
+                computes: true,
+                ..Operands::default()
+            },
+            Op::OPP(value, rm) |
+            Op::FLOAT(value, rm) |
+            Op::INT(value, rm) => Operands {
+                value: Some(*value),
+                written: Some(*rm),
+                computes: true,
+                ..Operands::default()
+            },
+            Op::LOAD(value, rm) |
+            Op::NEW(value, rm) => Operands {
+                value: Some(*value),
+                written: Some(*rm),
+                ..Operands::default()
+            },
+            Op::BRA(value) |
+            Op::BEQ(value) |
+            Op::BGT(value) |
+            Op::BGE(value) |
+            Op::BOV(value) |
+            Op::BNE(value) |
+            Op::BLT(value) |
+            Op::BLE(value) |
+            Op::BSR(value) => Operands {
+                value: Some(*value),
+                ..Operands::default()
+            },
+            Op::STORE(rm, address) => Operands {
+                destination: Some(*address),
+                registers: vec![*rm],
+                computes: true,
+                ..Operands::default()
+            },
+            Op::LEA(address, rm) => Operands {
+                address: Some(*address),
+                written: Some(*rm),
+                ..Operands::default()
+            },
+            Op::PEA(address) => Operands {
+                address: Some(*address),
+                ..Operands::default()
+            },
+            Op::PUSH(rm) |
+            Op::DEL(rm) => Operands {
+                registers: vec![*rm],
+                ..Operands::default()
+            },
+            Op::SHL(rm) |
+            Op::SHR(rm) => Operands {
+                registers: vec![*rm],
+                written: Some(*rm),
+                computes: true,
+                ..Operands::default()
+            },
+            Op::POP(rm) |
+            Op::SEQ(rm) |
+            Op::SGT(rm) |
+            Op::SGE(rm) |
+            Op::SOV(rm) |
+            Op::SNE(rm) |
+            Op::SLT(rm) |
+            Op::SLE(rm) => Operands {
+                written: Some(*rm),
+                ..Operands::default()
+            },
+            Op::CLK => Operands {
+                written: Some(RegisterIndex(0)),
+                ..Operands::default()
+            },
+            Op::RINT |
+            Op::RFLOAT |
+            Op::RUTF8 |
+            Op::SCLK => Operands {
+                written: Some(RegisterIndex(1)),
+                ..Operands::default()
+            },
+            Op::WINT |
+            Op::WFLOAT |
+            Op::WFLOATX |
+            Op::WUTF8 => Operands {
+                registers: vec![RegisterIndex(1)],
+                computes: true,
+                ..Operands::default()
+            },
+            _ => Operands::default(),
+        }
+    }
+}
+
+/// An instruction decoded once, when the program is loaded.
+#[derive(Debug, Clone)]
+pub struct DecodedInstruction {
+    /// The instruction ready to be executed.
+    /// The instruction as written, for the errors and the traces, is looked up in the program by address.
+    pub op: Op,
+    /// Cost of the instruction in cycles, when its condition does not hold.
+    pub cycles: usize,
+    /// Flag checked by a conditional instruction, which costs one more cycle when it holds.
+    pub condition: Option<Condition>,
+}
+
+impl DecodedInstruction {
+    /// Decode the instruction, computing its static cost in cycles.
+    pub fn decode(instruction: &Instruction) -> DecodedInstruction {
+        // only the cost of conditional instructions depends on the flags
+        let flags = Flags::new();
+        let (cycles, condition) = match instruction {
+            Instruction::BEQ(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::EQ)),
+            Instruction::BGE(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::GE)),
+            Instruction::BGT(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::GT)),
+            Instruction::BLE(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::LE)),
+            Instruction::BLT(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::LT)),
+            Instruction::BNE(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::NE)),
+            Instruction::BOV(dval) => (4 + dval.cycle_cost(&flags), Some(Condition::OV)),
+            Instruction::SEQ(_) => (2, Some(Condition::EQ)),
+            Instruction::SGE(_) => (2, Some(Condition::GE)),
+            Instruction::SGT(_) => (2, Some(Condition::GT)),
+            Instruction::SLE(_) => (2, Some(Condition::LE)),
+            Instruction::SLT(_) => (2, Some(Condition::LT)),
+            Instruction::SNE(_) => (2, Some(Condition::NE)),
+            Instruction::SOV(_) => (2, Some(Condition::OV)),
+            other => (other.cycle_cost(&flags), None),
+        };
+        DecodedInstruction {
+            op: instruction.into(),
+            cycles,
+            condition,
+        }
+    }
+
+    /// Cost of the instruction in cycles, with the given flags.
+    pub fn cycle_cost(&self, flags: &Flags) -> usize {
+        match self.condition {
+            Some(condition) if condition.holds(flags) => self.cycles + 1,
+            _ => self.cycles,
+        }
+    }
+}
+
+/// The program of the machine, decoded once when the machine is created.
+/// It is indexed like the program counter.
+#[derive(Debug, Clone, Default)]
+pub struct DecodedProgram(Vec<DecodedInstruction>);
+
+impl DecodedProgram {
+    /// Decode all the instructions of the program.
+    pub fn decode(program: &Program) -> DecodedProgram {
+        DecodedProgram(program.instructions().iter().map(DecodedInstruction::decode).collect())
+    }
+
+    /// Get the decoded instruction at the given program counter.
+    pub fn get(&self, pc: CodeAddr) -> Option<&DecodedInstruction> {
+        self.0.get(pc as usize)
+    }
+}
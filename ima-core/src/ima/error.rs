@@ -5,7 +5,18 @@ use std::{fmt::Display, error::Error};
 
 use crate::instructions::Instruction;
 
-use super::{data_type::DataTypeFlag, zones::memory::Pointer};
+use super::{
+    backtrace::Backtrace,
+    callee_saved::ClobberedRegisters,
+    shadow_stack::FrameCorruption,
+    data_type::DataTypeFlag,
+    limits::ExecutionCounters,
+    uninitialized::ValueSource,
+    zones::{
+        memory::{HeapPointer, Pointer},
+        program::DisplayLine,
+    },
+};
 
 /// Operation error: The machine have been instructed to perform an operation,
 /// but the found data types are not valid for this operation.
@@ -65,8 +76,30 @@ pub enum ImaExecutionError {
     InvalidOperation(OperationType),
     /// The Machine failed to read user input. This will be caused by an IO error, not a user error.
     FailedToReadInput(std::io::Error),
+    /// The machine needs input that is not available yet. The instruction can be executed again once it is.
+    InputPending,
     /// The Machine failed to write user output. This will be caused by an IO error, not a user error.
     FailedToWriteIO(std::io::Error),
+    /// The machine used an undefined value as an operand, in strict mode.
+    UninitializedRead(ValueSource),
+    /// The machine accessed a freed heap block, with the heap sanitizer.
+    UseAfterFree {
+        ptr: HeapPointer,
+        freed_at: Option<u32>,
+    },
+    /// The machine freed a heap block twice, with the heap sanitizer.
+    DoubleFree {
+        ptr: HeapPointer,
+        freed_at: Option<u32>,
+    },
+    /// The stack grew past the budget declared by the last TSTO of the frame, with the TSTO checker.
+    StackBudgetExceeded {
+        declared: Option<u32>,
+    },
+    /// A subroutine returned without restoring its callee-saved registers, with the callee-saved checker.
+    RegistersClobbered(ClobberedRegisters),
+    /// A RTS found a frame different from the one saved by the matching BSR.
+    CorruptedFrame(Box<FrameCorruption>),
 }
 
 impl Display for ImaExecutionError {
@@ -79,7 +112,15 @@ impl Display for ImaExecutionError {
             ImaExecutionError::InvalidMemoryAddress(ptr) => write!(f, "Invalid memory address ({ptr})"),
             ImaExecutionError::InvalidOperation(op) => write!(f, "Invalid operation: {}", op),
             ImaExecutionError::FailedToReadInput(e) => write!(f, "Failed to read input: {}", e),
+            ImaExecutionError::InputPending => write!(f, "Waiting for input"),
             ImaExecutionError::FailedToWriteIO(e) => write!(f, "Failed to write output: {}", e),
+            ImaExecutionError::UninitializedRead(source) => write!(f, "Use of an uninitialized value from {}", source),
+            ImaExecutionError::UseAfterFree { ptr, freed_at } => write!(f, "Use after free of {}, freed at {}", ptr, DisplayLine(*freed_at)),
+            ImaExecutionError::DoubleFree { ptr, freed_at } => write!(f, "Double free of {}, already freed at {}", ptr, DisplayLine(*freed_at)),
+            ImaExecutionError::StackBudgetExceeded { declared: Some(declared) } => write!(f, "Stack grows past the TSTO #{} of the frame", declared),
+            ImaExecutionError::StackBudgetExceeded { declared: None } => write!(f, "Stack grows without a TSTO in the frame"),
+            ImaExecutionError::RegistersClobbered(clobbered) => write!(f, "{}", clobbered),
+            ImaExecutionError::CorruptedFrame(corruption) => write!(f, "{}", corruption),
         }
     }
 }
@@ -89,26 +130,79 @@ impl Error for ImaExecutionError {}
 #[derive(Debug)]
 pub enum ImaError {
     /// An error occured during the execution of the machine.
+    /// The line is the source line of the failing instruction,
+    /// and the backtrace lists the active calls at the time of the error.
     ExecutionError {
         error: ImaExecutionError,
-        line: u32,
+        line: Option<u32>,
         instruction: Instruction,
+        backtrace: Backtrace,
     },
     /// The machine have no more instructions to execute.
     NoMoreInstructions,
+    /// The machine reached the same state twice, and will loop forever.
+    InfiniteLoop {
+        line: Option<u32>,
+        cycles: usize,
+    },
+    /// The machine spent more cycles than the allowed maximum.
+    CycleLimitReached {
+        line: Option<u32>,
+        counters: ExecutionCounters,
+    },
+    /// The machine executed more instructions than the allowed maximum.
+    InstructionLimitReached {
+        line: Option<u32>,
+        counters: ExecutionCounters,
+    },
+    /// The machine ran for longer than the allowed wall-clock time.
+    TimeoutReached {
+        line: Option<u32>,
+        counters: ExecutionCounters,
+    },
+    /// The machine wrote more bytes than the allowed maximum.
+    OutputLimitReached {
+        line: Option<u32>,
+        counters: ExecutionCounters,
+    },
     /// The machine failed an io operation in debug mode.
     DebugIoError(std::io::Error),
+    /// The machine failed to write the execution trace.
+    TraceIoError(std::io::Error),
 }
 
 impl Display for ImaError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[Ima Error]: ")?;
         match self {
-            ImaError::ExecutionError{error, line, instruction} => write!(f, "{}, at line {}: {}", error, line, instruction),
+            ImaError::ExecutionError{error, line, instruction, backtrace} => write!(f, "{}, at {}: {}{}", error, DisplayLine(*line), instruction, backtrace),
             ImaError::NoMoreInstructions => write!(f, "No more instructions"),
+            ImaError::InfiniteLoop{line, cycles} => write!(f, "Infinite loop detected at {} after {} cycles", DisplayLine(*line), cycles),
+            ImaError::CycleLimitReached{line, counters} => write!(f, "Cycle limit reached at {} ({})", DisplayLine(*line), counters),
+            ImaError::InstructionLimitReached{line, counters} => write!(f, "Instruction limit reached at {} ({})", DisplayLine(*line), counters),
+            ImaError::TimeoutReached{line, counters} => write!(f, "Timeout reached at {} ({})", DisplayLine(*line), counters),
+            ImaError::OutputLimitReached{line, counters} => write!(f, "Output limit reached at {} ({})", DisplayLine(*line), counters),
             ImaError::DebugIoError(e) => write!(f, "Error on debug I/O: {}. This is not a machine error, but should be due to the environment", e),
+            ImaError::TraceIoError(e) => write!(f, "Unable to write the execution trace: {}", e),
         }
     }
 }
 
-impl Error for ImaError {}
\ No newline at end of file
+impl Error for ImaError {}
+
+impl ImaError {
+    /// Get the source line the machine stopped on, if the error is related to a line.
+    pub fn line(&self) -> Option<u32> {
+        match self {
+            ImaError::ExecutionError { line, .. } |
+            ImaError::InfiniteLoop { line, .. } |
+            ImaError::CycleLimitReached { line, .. } |
+            ImaError::InstructionLimitReached { line, .. } |
+            ImaError::TimeoutReached { line, .. } |
+            ImaError::OutputLimitReached { line, .. } => *line,
+            ImaError::NoMoreInstructions |
+            ImaError::DebugIoError(_) |
+            ImaError::TraceIoError(_) => None,
+        }
+    }
+}
@@ -0,0 +1,261 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::collections::VecDeque;
+
+use super::{
+    IMA,
+    address_modes::RegisterIndex,
+    callee_saved::CalleeSavedChecker,
+    control_flow::ImaControlFlow,
+    data_type::DataType,
+    decoded::Op,
+    error::ImaError,
+    io::ImaIo,
+    observer::ImaObserver,
+    rounding::RoundingMode,
+    sanitizer::HeapSanitizer,
+    shadow_stack::ShadowStack,
+    stack_budget::StackBudgetChecker,
+    step::StepOutcome,
+    zones::{
+        flags::Flags,
+        memory::{MemoryChange, StackPointer},
+        program::CodeAddr,
+        registers::Registers,
+    },
+};
+
+/// Number of steps kept to step back in debug mode, when not given.
+pub const DEFAULT_HISTORY: usize = 10_000;
+
+/// Where the machine stopped when going to a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleOutcome {
+    /// The machine is at the first instruction that starts at or after the cycle.
+    Reached,
+    /// The history does not go back that far, or the machine stopped before.
+    Unreachable,
+    /// The next instruction reads input, but there is none left.
+    /// The caller can supply more input and go to the cycle again.
+    NeedsInput,
+}
+
+/// Frame and heap bookkeeping of the checkers, saved before the instructions that change it.
+#[derive(Clone)]
+struct CheckerState {
+    shadow_stack: ShadowStack,
+    stack_budget_checker: Option<StackBudgetChecker>,
+    callee_saved_checker: Option<CalleeSavedChecker>,
+    heap_sanitizer: Option<HeapSanitizer>,
+}
+
+/// Everything a step changed in the machine, with the values before the step.
+#[derive(Clone)]
+struct UndoRecord {
+    pc: CodeAddr,
+    /// Only the registers the step changed.
+    registers: Vec<(RegisterIndex, DataType)>,
+    flags: Flags,
+    gb: StackPointer,
+    lb: StackPointer,
+    sp: StackPointer,
+    control_flow: ImaControlFlow,
+    cycle_count: usize,
+    instruction_count: usize,
+    output_bytes: usize,
+    rounding_mode: RoundingMode,
+    memory: Vec<MemoryChange>,
+    checkers: Option<Box<CheckerState>>,
+}
+
+/// A step being recorded: the record, and the registers before the step to find the changed ones.
+pub(super) struct PendingUndo {
+    record: UndoRecord,
+    registers: Registers,
+}
+
+/// The undo records of the last steps of the machine, to execute them backward.
+/// The oldest records are dropped once the history is full, so the memory stays bounded.
+pub struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates an empty history, keeping at most the given number of steps.
+    pub fn new(capacity: usize) -> History {
+        History {
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Number of steps that can be undone.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Check if there is no step to undo.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Most steps the history can keep.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Forget all the steps.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Cycle count of the machine before the last step, if there is one.
+    pub fn last_cycle(&self) -> Option<usize> {
+        self.records.back().map(|record| record.cycle_count)
+    }
+
+    fn push(&mut self, record: UndoRecord) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// Returns true if the instruction changes the bookkeeping of the frame or heap checkers.
+fn changes_checkers(op: &Op) -> bool {
+    matches!(op,
+        Op::BSR(_) |
+        Op::RTS |
+        Op::TSTO(_) |
+        Op::NEW(..) |
+        Op::DEL(_)
+    )
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// If the history is enabled, start recording the changes of the next step.
+    pub(super) fn begin_undo(&mut self, op: &Op) -> Option<PendingUndo> {
+        // nothing is recorded without a history
+        self.history.as_ref()?;
+        self.memory.start_undo_log();
+        let checkers = changes_checkers(op).then(|| Box::new(CheckerState {
+            shadow_stack: self.shadow_stack.clone(),
+            stack_budget_checker: self.stack_budget_checker.clone(),
+            callee_saved_checker: self.callee_saved_checker.clone(),
+            heap_sanitizer: self.heap_sanitizer.clone(),
+        }));
+        Some(PendingUndo {
+            record: UndoRecord {
+                pc: self.code.pc(),
+                registers: Vec::new(),
+                flags: self.flags.clone(),
+                gb: self.gb,
+                lb: self.lb,
+                sp: self.sp,
+                control_flow: self.control_flow,
+                cycle_count: self.cycle_count,
+                instruction_count: self.instruction_count,
+                output_bytes: self.output_bytes,
+                rounding_mode: self.rounding_mode,
+                memory: Vec::new(),
+                checkers,
+            },
+            registers: self.registers.clone(),
+        })
+    }
+
+    /// Add the step to the history, whether it succeeded or not.
+    pub(super) fn end_undo(&mut self, pending: Option<PendingUndo>) {
+        let PendingUndo { mut record, registers } = match pending {
+            Some(pending) => pending,
+            None => return,
+        };
+        record.memory = self.memory.take_undo_log();
+        record.registers = (0..registers.count())
+            .map(|index| RegisterIndex(index as u8))
+            .filter(|index| registers.get(*index) != self.registers.get(*index))
+            .map(|index| (index, registers.get(index)))
+            .collect();
+        if let Some(history) = self.history.as_mut() {
+            history.push(record);
+        }
+    }
+
+    /// Get the history of the machine, if it is enabled.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Undo the last step of the machine. Returns false if there is no step left in the history.
+    /// The input read and the output written are not taken back,
+    /// and the warnings and the profile keep the undone step.
+    pub fn step_back(&mut self) -> bool {
+        let record = match self.history.as_mut().and_then(|history| history.records.pop_back()) {
+            Some(record) => record,
+            None => return false,
+        };
+        for (index, value) in record.registers {
+            self.registers.set(index, value);
+        }
+        self.memory.undo(record.memory);
+        self.flags = record.flags;
+        self.gb = record.gb;
+        self.lb = record.lb;
+        self.sp = record.sp;
+        self.code.set_pc(record.pc);
+        self.control_flow = record.control_flow;
+        self.cycle_count = record.cycle_count;
+        self.instruction_count = record.instruction_count;
+        self.output_bytes = record.output_bytes;
+        self.rounding_mode = record.rounding_mode;
+        if let Some(checkers) = record.checkers {
+            let checkers = *checkers;
+            self.shadow_stack = checkers.shadow_stack;
+            self.stack_budget_checker = checkers.stack_budget_checker;
+            self.callee_saved_checker = checkers.callee_saved_checker;
+            self.heap_sanitizer = checkers.heap_sanitizer;
+        }
+        self.watchpoints.clear_hits();
+        // the states seen after this step would be seen again
+        if let Some(loop_detector) = self.loop_detector.as_mut() {
+            loop_detector.clear();
+        }
+        true
+    }
+
+    /// Undo steps until the next instruction has a breakpoint.
+    /// At least one step is undone, so this makes progress when called repeatedly.
+    /// Returns false if the history ran out before reaching a breakpoint.
+    pub fn reverse_continue(&mut self) -> bool {
+        loop {
+            if !self.step_back() {
+                return false;
+            }
+            if self.code.is_breakpoint(self.code.pc()) {
+                return true;
+            }
+        }
+    }
+
+    /// Go backward or forward to the first instruction that starts at or after the given cycle.
+    /// Breakpoints and watchpoints are ignored on the way.
+    pub fn go_to_cycle<IO: ImaIo>(&mut self, cycle: usize, io: &mut IO) -> Result<CycleOutcome, ImaError> {
+        while self.cycle_count > cycle {
+            match self.history.as_ref().and_then(History::last_cycle) {
+                Some(previous) if previous >= cycle => { self.step_back(); },
+                Some(_) => return Ok(CycleOutcome::Reached),
+                None => return Ok(CycleOutcome::Unreachable),
+            }
+        }
+        while self.cycle_count < cycle {
+            match self.step(io)? {
+                StepOutcome::Continued | StepOutcome::Breakpoint | StepOutcome::Watchpoint => (),
+                StepOutcome::Halted(_) => return Ok(CycleOutcome::Unreachable),
+                StepOutcome::NeedsInput => return Ok(CycleOutcome::NeedsInput),
+            }
+        }
+        Ok(CycleOutcome::Reached)
+    }
+}
@@ -1,18 +1,16 @@
 /// Created by Virgile HENRY, 2023/09/28
 
 
-use std::io::{BufRead, Write};
-
 use crate::{ima::{
-    cycles::CycleCost,
     IMA,
     address_modes::{
-        DVAL,
+        Address,
         GetDval,
+        Operand,
         RegisterIndex,
-        DADR,
         GetDadr
     },
+    decoded::{DecodedInstruction, Op},
     error::{
         ImaExecutionError,
         OperationType
@@ -22,90 +20,94 @@ use crate::{ima::{
         DataTypeFlag
     },
     zones::memory::Pointer
-}, instructions::Instructions, complete::Instruction};
+}, instructions::Instructions};
 
 use super::{
     control_flow::ImaControlFlow,
+    io::{ImaInput, ImaIo},
+    observer::ImaObserver,
     options::ImaRunMode,
-    zones::program::RunMode
+    rounding::RoundingMode,
 };
 
-impl<RM: RunMode> IMA<RM> {
-    pub fn execute<R: BufRead, W: Write>(&mut self, instruction: Instruction, input: &mut R, output: &mut W) -> Result<(), ImaExecutionError> {
-        let cycle_cost = instruction.cycle_cost(&self.flags);
-        match instruction {
-            Instruction::LOAD(dval, rm) => self.load(dval, rm)?,
-            Instruction::STORE(rm, dadr) => self.store(rm, dadr)?,
-            Instruction::PUSH(rm) => self.push(rm)?,
-            Instruction::POP(rm) => self.pop(rm)?,
-            Instruction::LEA(dadr, rm) => self.lea(dadr, rm)?,
-            Instruction::PEA(dadr) => self.pea(dadr)?,
-            Instruction::NEW(dval, rm) => self.new(dval, rm)?,
-            Instruction::DEL(rm) => self.del(rm)?,
-            Instruction::CMP(dval, rm) => self.cmp(dval, rm)?,
-            Instruction::ADD(dval, rm) => self.add(dval, rm)?,
-            Instruction::SUB(dval, rm) => self.sub(dval, rm)?,
-            Instruction::MUL(dval, rm) => self.mul(dval, rm)?,
-            Instruction::OPP(dval, rm) => self.opp(dval, rm)?,
-            Instruction::QUO(dval, rm) => self.quo(dval, rm)?,
-            Instruction::REM(dval, rm) => self.rem(dval, rm)?,
-            Instruction::SEQ(rm) => self.seq(rm),
-            Instruction::SGT(rm) => self.sgt(rm),
-            Instruction::SGE(rm) => self.sge(rm),
-            Instruction::SOV(rm) => self.sov(rm),
-            Instruction::SNE(rm) => self.sne(rm),
-            Instruction::SLT(rm) => self.slt(rm),
-            Instruction::SLE(rm) => self.sle(rm),
-            Instruction::SHL(rm) => self.shl(rm)?,
-            Instruction::SHR(rm) => self.shr(rm)?,
-            Instruction::DIV(dval, rm) => self.div(dval, rm)?,
-            Instruction::FMA(dval, rm) => self.fma(dval, rm)?,
-            Instruction::FLOAT(dval, rm) => self.float(dval, rm)?,
-            Instruction::INT(dval, rm) => self.int(dval, rm)?,
-            Instruction::SETROUND_TONEAREST => self.setround_tonearest(),
-            Instruction::SETROUND_UPWARD => self.setround_upward(),
-            Instruction::SETROUND_DOWNWARD => self.setround_downward(),
-            Instruction::SETROUND_TOWARDZERO => self.setround_towardzero(),
-            Instruction::BRA(dval) => self.bra(dval)?,
-            Instruction::BEQ(dval) => self.beq(dval)?,
-            Instruction::BGT(dval) => self.bgt(dval)?,
-            Instruction::BGE(dval) => self.bge(dval)?,
-            Instruction::BOV(dval) => self.bov(dval)?,
-            Instruction::BNE(dval) => self.bne(dval)?,
-            Instruction::BLT(dval) => self.blt(dval)?,
-            Instruction::BLE(dval) => self.ble(dval)?,
-            Instruction::BSR(dval) => self.bsr(dval)?,
-            Instruction::RTS => self.rts()?,
-            Instruction::RINT => self.rint(input)?,
-            Instruction::RFLOAT => self.rfloat(input)?,
-            Instruction::WINT => self.wint(output)?,
-            Instruction::WFLOAT => self.wfloat(output)?,
-            Instruction::WFLOATX => self.wfloatx(output)?,
-            Instruction::WSTR(string) => self.wstr(output, string)?,
-            Instruction::WNL => self.wnl(output)?,
-            Instruction::RUTF8 => self.rutf8(input)?,
-            Instruction::WUTF8 => self.wutf8(output)?,
-            Instruction::ADDSP(value) => self.addsp(value)?,
-            Instruction::SUBSP(value) => self.subsp(value)?,
-            Instruction::TSTO(value) => self.tsto(value),
-            Instruction::HALT => self.halt(),
-            Instruction::ERROR => self.error(),
-            Instruction::SCLK => self.sclk(),
-            Instruction::CLK => self.clk(),
+impl<O: ImaObserver> IMA<O> {
+    /// Execute a decoded instruction, and count its cycles.
+    pub fn execute<IO: ImaIo>(&mut self, decoded: &DecodedInstruction, io: &mut IO) -> Result<(), ImaExecutionError> {
+        let cycle_cost = decoded.cycle_cost(&self.flags);
+        match decoded.op {
+            Op::LOAD(dval, rm) => self.load(dval, rm)?,
+            Op::STORE(rm, dadr) => self.store(rm, dadr)?,
+            Op::PUSH(rm) => self.push(rm)?,
+            Op::POP(rm) => self.pop(rm)?,
+            Op::LEA(dadr, rm) => self.lea(dadr, rm)?,
+            Op::PEA(dadr) => self.pea(dadr)?,
+            Op::NEW(dval, rm) => self.new(dval, rm)?,
+            Op::DEL(rm) => self.del(rm)?,
+            Op::CMP(dval, rm) => self.cmp(dval, rm)?,
+            Op::ADD(dval, rm) => self.add(dval, rm)?,
+            Op::SUB(dval, rm) => self.sub(dval, rm)?,
+            Op::MUL(dval, rm) => self.mul(dval, rm)?,
+            Op::OPP(dval, rm) => self.opp(dval, rm)?,
+            Op::QUO(dval, rm) => self.quo(dval, rm)?,
+            Op::REM(dval, rm) => self.rem(dval, rm)?,
+            Op::SEQ(rm) => self.seq(rm),
+            Op::SGT(rm) => self.sgt(rm),
+            Op::SGE(rm) => self.sge(rm),
+            Op::SOV(rm) => self.sov(rm),
+            Op::SNE(rm) => self.sne(rm),
+            Op::SLT(rm) => self.slt(rm),
+            Op::SLE(rm) => self.sle(rm),
+            Op::SHL(rm) => self.shl(rm)?,
+            Op::SHR(rm) => self.shr(rm)?,
+            Op::DIV(dval, rm) => self.div(dval, rm)?,
+            Op::FMA(dval, rm) => self.fma(dval, rm)?,
+            Op::FLOAT(dval, rm) => self.float(dval, rm)?,
+            Op::INT(dval, rm) => self.int(dval, rm)?,
+            Op::SETROUND_TONEAREST => self.setround_tonearest(),
+            Op::SETROUND_UPWARD => self.setround_upward(),
+            Op::SETROUND_DOWNWARD => self.setround_downward(),
+            Op::SETROUND_TOWARDZERO => self.setround_towardzero(),
+            Op::BRA(dval) => self.bra(dval)?,
+            Op::BEQ(dval) => self.beq(dval)?,
+            Op::BGT(dval) => self.bgt(dval)?,
+            Op::BGE(dval) => self.bge(dval)?,
+            Op::BOV(dval) => self.bov(dval)?,
+            Op::BNE(dval) => self.bne(dval)?,
+            Op::BLT(dval) => self.blt(dval)?,
+            Op::BLE(dval) => self.ble(dval)?,
+            Op::BSR(dval) => self.bsr(dval)?,
+            Op::RTS => self.rts()?,
+            Op::RINT => self.rint(io)?,
+            Op::RFLOAT => self.rfloat(io)?,
+            Op::WINT => self.wint(io)?,
+            Op::WFLOAT => self.wfloat(io)?,
+            Op::WFLOATX => self.wfloatx(io)?,
+            Op::WSTR(ref string) => self.wstr(io, string)?,
+            Op::WNL => self.wnl(io)?,
+            Op::RUTF8 => self.rutf8(io)?,
+            Op::WUTF8 => self.wutf8(io)?,
+            Op::ADDSP(value) => self.addsp(value)?,
+            Op::SUBSP(value) => self.subsp(value)?,
+            Op::TSTO(value) => self.tsto(value),
+            Op::HALT => self.halt(),
+            Op::ERROR => self.error(),
+            Op::SCLK => self.sclk(),
+            Op::CLK => self.clk(),
         }
         self.cycle_count += cycle_cost;
+        self.instruction_count += 1;
         Ok(())
     }
 
 } 
 
-impl<RM:RunMode> Instructions for IMA<RM> {
-    fn add(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+impl<O: ImaObserver> Instructions for IMA<O> {
+    fn add(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.get_dval(dval)?;
         let v2 = self.registers.get(rm);
         match (v1, v2) {
             (DataType::Float(f1), DataType::Float(f2)) => {
-                let res = f1 + f2;
+                let res = self.rounding_mode.add(f1, f2);
                 self.flags.set_ov(res.is_infinite());
                 self.flags.set_cmp_float(0.0, res);
                 self.registers.set(rm, DataType::Float(res));
@@ -126,7 +128,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn beq(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn beq(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.eq() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -135,7 +137,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bge(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bge(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.ge() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -144,7 +146,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bgt(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bgt(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.gt() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -153,7 +155,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn ble(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn ble(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.le() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -162,7 +164,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn blt(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn blt(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.lt() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -171,7 +173,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bne(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bne(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.ne() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -180,7 +182,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bov(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bov(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         if !self.flags.ov() { return Ok(()) }
         let v = self.get_dval(dval)?;
         match v {
@@ -189,7 +191,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bra(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bra(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::CodeAddr(addr) => Ok(self.code.set_pc(addr)),
@@ -197,7 +199,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn bsr(&mut self, dval: DVAL) -> Result<(), ImaExecutionError> {
+    fn bsr(&mut self, dval: Operand) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::CodeAddr(addr) => {
@@ -213,12 +215,11 @@ impl<RM:RunMode> Instructions for IMA<RM> {
     }
 
     fn clk(&mut self) {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(self.ima_start_time);
-        self.registers.set(RegisterIndex(0), DataType::Float(elapsed.as_secs_f32()));
+        let elapsed = self.clock_elapsed();
+        self.registers.set(RegisterIndex(0), DataType::Float(elapsed as f32));
     }
 
-    fn cmp(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn cmp(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.get_dval(dval)?;
         let v2 = self.registers.get(rm);
         match (v1, v2) {
@@ -241,7 +242,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         match addr {
             DataType::MemAddr(Pointer::Heap(addr)) => {
                 match self.memory.free(addr) {
-                    Some(_) => {},
+                    Some(_) => self.observer.free(addr),
                     None => self.flags.set_ov(true),
                 };
                 Ok(())
@@ -250,12 +251,12 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn div(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn div(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.registers.get(rm);
         let v2 = self.get_dval(dval)?;
         match (v1, v2) {
             (DataType::Float(f1), DataType::Float(f2)) => {
-                let res = f1 / f2;
+                let res = self.rounding_mode.div(f1, f2);
                 self.flags.set_ov(res.is_infinite());
                 self.flags.set_cmp_float(0.0, res);
                 self.registers.set(rm, DataType::Float(res));
@@ -266,29 +267,27 @@ impl<RM:RunMode> Instructions for IMA<RM> {
     }
 
     fn error(&mut self) {
-        // todo : this could be done more gracefully
         self.control_flow = ImaControlFlow::Error;
     }
 
-    fn float(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn float(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::Int(i) => {
-                // todo : some error handling checks ? no idea how rust handles it
-                self.registers.set(rm, DataType::Float(i as f32));
+                self.registers.set(rm, DataType::Float(self.rounding_mode.float(i)));
             },
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Int, found: v.into() }),
         }
         Ok(())
     }
 
-    fn fma(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn fma(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.get_dval(dval)?;
         let v2 = self.registers.get(rm);
         let v3 = self.registers.get(RegisterIndex(0));
         match (v1, v2, v3) {
             (DataType::Float(f1), DataType::Float(f2), DataType::Float(f3)) => {
-                let res = f1 * f2 + f3;
+                let res = self.rounding_mode.fma(f1, f2, f3);
                 self.flags.set_ov(res.is_infinite());
                 self.flags.set_cmp_float(0.0, res);
                 self.registers.set(rm, DataType::Float(res));
@@ -302,25 +301,22 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         self.control_flow = ImaControlFlow::Halt;
     }
 
-    fn int(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn int(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
-            DataType::Float(f) => {
-                // todo : some error handling checks ? no idea how rust handles it
-                self.registers.set(rm, DataType::Int(f as i32));
-            },
+            DataType::Float(f) => self.registers.set(rm, DataType::Int(self.rounding_mode.int(f))),
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Float, found: v.into() }),
         }
         Ok(())
     }
 
-    fn lea(&mut self, dadr: DADR, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn lea(&mut self, dadr: Address, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let addr = self.get_dadr(dadr)?;
         self.registers.set(rm, DataType::MemAddr(addr));
         Ok(())
     }
 
-    fn load(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn load(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::Int(i) => self.flags.set_cmp_int(0, i),
@@ -332,12 +328,12 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn mul(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn mul(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.get_dval(dval)?;
         let v2 = self.registers.get(rm);
         match (v1, v2) {
             (DataType::Float(f1), DataType::Float(f2)) => {
-                let res = f1 * f2;
+                let res = self.rounding_mode.mul(f1, f2);
                 self.flags.set_ov(res.is_infinite());
                 self.flags.set_cmp_float(0.0, res);
                 self.registers.set(rm, DataType::Float(res));
@@ -353,7 +349,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn new(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn new(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::Int(i) => {
@@ -371,7 +367,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         }
     }
 
-    fn opp(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn opp(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v = self.get_dval(dval)?;
         match v {
             DataType::Float(f) => {
@@ -390,7 +386,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn pea(&mut self, dadr: DADR) -> Result<(), ImaExecutionError> {
+    fn pea(&mut self, dadr: Address) -> Result<(), ImaExecutionError> {
         let addr = self.get_dadr(dadr)?;
         self.sp = self.sp.offset(1).ok_or(ImaExecutionError::StackOverflow)?;
         self.memory.set_stack(self.sp, DataType::MemAddr(addr))?;
@@ -424,7 +420,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn quo(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn quo(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.registers.get(rm);
         let v2 = self.get_dval(dval)?;
         match (v1, v2) {
@@ -443,7 +439,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn rem(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn rem(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.registers.get(rm);
         let v2 = self.get_dval(dval)?;
         match (v1, v2) {
@@ -462,41 +458,29 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn rfloat<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError> {
+    fn rfloat<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         // wait for user input
-        let mut user_input = String::new();
-
-        match input.read_line(&mut user_input) {
-            Ok(_) => {},
-            Err(e) => return Err(ImaExecutionError::FailedToReadInput(e))
-        }
-
-        match user_input.trim().parse() {
-            Ok(num) => {
+        match io.read_float().map_err(ImaExecutionError::FailedToReadInput)? {
+            ImaInput::Value(num) => {
                 self.flags.set_cmp_float(0.0, num);
                 self.registers.set(RegisterIndex(1), DataType::Float(num));
             },
-            Err(_) => self.flags.set_ov(true),
+            ImaInput::Invalid => self.flags.set_ov(true),
+            ImaInput::Pending => return Err(ImaExecutionError::InputPending),
         };
 
         Ok(())
     }
 
-    fn rint<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError> {
+    fn rint<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         // wait for user input
-        let mut user_input = String::new();
-
-        match input.read_line(&mut user_input) {
-            Ok(_) => {},
-            Err(e) => return Err(ImaExecutionError::FailedToReadInput(e))
-        }
-
-        match user_input.trim().parse() {
-            Ok(num) => {
+        match io.read_int().map_err(ImaExecutionError::FailedToReadInput)? {
+            ImaInput::Value(num) => {
                 self.flags.set_cmp_int(0, num);
                 self.registers.set(RegisterIndex(1), DataType::Int(num));
             },
-            Err(_) => self.flags.set_ov(true),
+            ImaInput::Invalid => self.flags.set_ov(true),
+            ImaInput::Pending => return Err(ImaExecutionError::InputPending),
         };
 
         Ok(())
@@ -508,7 +492,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
             let v = self.memory.get_stack(addr).ok_or(ImaExecutionError::InvalidMemoryAddress(Pointer::Stack(addr)))?;
             match v {
                 DataType::CodeAddr(addr) => addr,
-                _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::MemAddr, found: v.into() }),
+                _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::CodeAddr, found: v.into() }),
             }
         });
         self.sp = self.lb.offset(-2).ok_or(ImaExecutionError::StackUnderflow)?;
@@ -523,29 +507,18 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         Ok(())
     }
 
-    fn rutf8<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError> {
-        let mut buf = [0u8; 4];
-        input.read_exact(&mut buf).map_err(|e| ImaExecutionError::FailedToReadInput(e))?;
-
-        let v = i32::from_ne_bytes(buf);
-        self.registers.set(RegisterIndex(1), DataType::Int(v));
+    fn rutf8<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
+        match io.read_char().map_err(ImaExecutionError::FailedToReadInput)? {
+            ImaInput::Value(word) => self.registers.set(RegisterIndex(1), DataType::Int(word)),
+            ImaInput::Invalid => self.flags.set_ov(true),
+            ImaInput::Pending => return Err(ImaExecutionError::InputPending),
+        };
 
         Ok(())
     }
 
     fn sclk(&mut self) {
-        use chrono::TimeZone;
-        // Create a Utc DateTime for January 1, 2001, 00:00:00
-        let start_date = chrono::Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).single().unwrap();
-
-        // Get the current Utc DateTime or any other DateTime you want to measure the duration from
-        let current_date = chrono::Utc::now(); // Replace this with your desired instant
-
-        // Calculate the duration between the two DateTimes
-        let duration = current_date.signed_duration_since(start_date);
-
-        // Get the total number of seconds in the duration
-        let seconds = duration.num_seconds();
+        let seconds = self.clock_seconds();
 
         // finally write to R1
         self.registers.set(RegisterIndex(1), DataType::Int(seconds as i32));
@@ -556,19 +529,19 @@ impl<RM:RunMode> Instructions for IMA<RM> {
     }
 
     fn setround_downward(&mut self) {
-        // todo
+        self.rounding_mode = RoundingMode::Downward;
     }
 
     fn setround_tonearest(&mut self) {
-        // todo
+        self.rounding_mode = RoundingMode::ToNearest;
     }
 
     fn setround_towardzero(&mut self) {
-        // todo
+        self.rounding_mode = RoundingMode::TowardZero;
     }
 
     fn setround_upward(&mut self) {
-        // todo
+        self.rounding_mode = RoundingMode::Upward;
     }
 
     fn sge(&mut self, rm: RegisterIndex) {
@@ -623,7 +596,7 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         self.registers.set(rm, DataType::Int(self.flags.ov().into()))
     }
 
-    fn store(&mut self, rm: RegisterIndex, dadr: DADR) -> Result<(), ImaExecutionError> {
+    fn store(&mut self, rm: RegisterIndex, dadr: Address) -> Result<(), ImaExecutionError> {
         let addr = self.get_dadr(dadr)?;
         let v = self.registers.get(rm);
         match v {
@@ -635,12 +608,12 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         self.memory.set(addr, v)
     }
 
-    fn sub(&mut self, dval: DVAL, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
+    fn sub(&mut self, dval: Operand, rm: RegisterIndex) -> Result<(), ImaExecutionError> {
         let v1 = self.registers.get(rm);
         let v2 = self.get_dval(dval)?;
         match (v1, v2) {
             (DataType::Float(f1), DataType::Float(f2)) => {
-                let res = f1 - f2;
+                let res = self.rounding_mode.sub(f1, f2);
                 self.flags.set_ov(res.is_infinite());
                 self.flags.set_cmp_float(0.0, res);
                 self.registers.set(rm, DataType::Float(res));
@@ -665,70 +638,67 @@ impl<RM:RunMode> Instructions for IMA<RM> {
         self.flags.set_ov(self.sp.as_index() + value as usize > self.memory.stack_size())
     }
 
-    fn wfloat<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError> {
+    fn wfloat<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         let v = self.registers.get(RegisterIndex(1));
         match v {
             DataType::Float(f) => {
-                output.write(format!("{}", f).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
-                output.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.write(format!("{}", f).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
             },
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Float, found: v.into() }),
         }
         Ok(())
     }
 
-    fn wfloatx<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError> {
+    fn wfloatx<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         let v = self.registers.get(RegisterIndex(1));
         match v {
             DataType::Float(f) => {
-                output.write(format!("{}", f).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.write(format!("{}", f).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
                 if self.run_mode == ImaRunMode::WriteNewLines {
-                    output.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                    io.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
                 }
-                output.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
             },
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Float, found: v.into() }),
         }
         Ok(())
     }
 
-    fn wint<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError> {
+    fn wint<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         let v = self.registers.get(RegisterIndex(1));
         match v {
             DataType::Int(i) => {
-                output.write(format!("{}", i).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.write(format!("{}", i).as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
                 if self.run_mode == ImaRunMode::WriteNewLines {
-                    output.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                    io.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
                 }
-                output.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+                io.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
             },
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Int, found: v.into() }),
         }
         Ok(())
     }
 
-    fn wnl<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError> {
-        output.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
-        output.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))
+    fn wnl<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
+        io.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+        io.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))
     }
 
-    fn wstr<W: Write>(&mut self, output: &mut W, string: String) -> Result<(), ImaExecutionError> {
-        output.write(string.as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+    fn wstr<IO: ImaIo>(&mut self, io: &mut IO, string: &str) -> Result<(), ImaExecutionError> {
+        io.write(string.as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
         if self.run_mode == ImaRunMode::WriteNewLines {
-            output.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+            io.write(format!("\n").as_bytes()).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
         }
-        output.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))
+        io.flush().map_err(|e| ImaExecutionError::FailedToWriteIO(e))
     }
 
-    fn wutf8<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError> {
+    fn wutf8<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError> {
         let bytes = match self.registers.get(RegisterIndex(1)) {
-            DataType::Int(i) => {
-                let u: u32 = unsafe { std::mem::transmute(i) };
-                u.to_be_bytes()
-            }
+            DataType::Int(i) => (i as u32).to_be_bytes(),
             _ => return Err(ImaExecutionError::InvalidDataType { expected: DataTypeFlag::Int, found: self.registers.get(RegisterIndex(1)).into() }),
         };
-        output.write(&bytes).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
+        io.write(&bytes).map_err(|e| ImaExecutionError::FailedToWriteIO(e))?;
         Ok(())
     }
         
@@ -0,0 +1,171 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, ErrorKind, StdinLock, Stdout, Write},
+};
+
+/// Result of reading a value from the input of the machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImaInput<T> {
+    /// A value was read.
+    Value(T),
+    /// Something was read, but it is not a valid value. The machine sets the overflow flag.
+    Invalid,
+    /// No input is available yet. The instruction is not executed,
+    /// so the caller can supply more input and resume the machine.
+    Pending,
+}
+
+impl<T: std::str::FromStr> ImaInput<T> {
+    /// Parse a line of input, ignoring the surrounding whitespaces.
+    fn parse(line: &str) -> ImaInput<T> {
+        match line.trim().parse() {
+            Ok(value) => ImaInput::Value(value),
+            Err(_) => ImaInput::Invalid,
+        }
+    }
+}
+
+/// Input and output of the machine, used by the RINT, RFLOAT, RUTF8 and W* instructions.
+pub trait ImaIo {
+    /// Read an integer, for RINT. This waits for a whole line.
+    fn read_int(&mut self) -> std::io::Result<ImaInput<i32>>;
+    /// Read a float, for RFLOAT. This waits for a whole line.
+    fn read_float(&mut self) -> std::io::Result<ImaInput<f32>>;
+    /// Read the 4 bytes of a character word, for RUTF8, as a native endian integer.
+    /// This does not wait for a newline.
+    fn read_char(&mut self) -> std::io::Result<ImaInput<i32>>;
+    /// Write bytes to the output.
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+    /// Flush the output, if it is buffered.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// I/O on a reader and a writer, like the standard input and output.
+/// The input is never pending: reading blocks until something comes, and the end of the input is invalid.
+pub struct StreamIo<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> StreamIo<R, W> {
+    /// Creates an I/O reading from the input, and writing to the output.
+    pub fn new(input: R, output: W) -> StreamIo<R, W> {
+        StreamIo { input, output }
+    }
+
+    /// Get back the input and output.
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+
+    /// Read a line of the input.
+    fn read_line(&mut self) -> std::io::Result<String> {
+        let mut line = String::new();
+        self.input.read_line(&mut line)?;
+        Ok(line)
+    }
+}
+
+impl StreamIo<StdinLock<'static>, Stdout> {
+    /// Creates an I/O on the standard input and output.
+    pub fn stdio() -> StreamIo<StdinLock<'static>, Stdout> {
+        StreamIo::new(std::io::stdin().lock(), std::io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> ImaIo for StreamIo<R, W> {
+    fn read_int(&mut self) -> std::io::Result<ImaInput<i32>> {
+        Ok(ImaInput::parse(&self.read_line()?))
+    }
+
+    fn read_float(&mut self) -> std::io::Result<ImaInput<f32>> {
+        Ok(ImaInput::parse(&self.read_line()?))
+    }
+
+    fn read_char(&mut self) -> std::io::Result<ImaInput<i32>> {
+        let mut buffer = [0u8; 4];
+        match self.input.read_exact(&mut buffer) {
+            Ok(()) => Ok(ImaInput::Value(i32::from_ne_bytes(buffer))),
+            // the input ended, maybe in the middle of the word
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(ImaInput::Invalid),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// In memory I/O, for tests and embedders that feed the machine themselves.
+/// When the supplied input runs out, reads are pending until more is pushed.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedIo {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl ScriptedIo {
+    /// Creates an I/O with the given input, and an empty output.
+    pub fn new(input: &str) -> ScriptedIo {
+        ScriptedIo {
+            input: input.bytes().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Add input after the one not read yet.
+    pub fn push_input(&mut self, input: &str) {
+        self.input.extend(input.bytes());
+    }
+
+    /// Get everything written so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Get everything written since the last call, and clear the output.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Take the next line of input, with its newline.
+    /// Pending if there is no complete line yet.
+    fn read_line(&mut self) -> Option<String> {
+        let end = self.input.iter().position(|c| *c == b'\n')?;
+        let line = self.input.drain(..=end).collect::<Vec<u8>>();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+impl ImaIo for ScriptedIo {
+    fn read_int(&mut self) -> std::io::Result<ImaInput<i32>> {
+        Ok(self.read_line().map_or(ImaInput::Pending, |line| ImaInput::parse(&line)))
+    }
+
+    fn read_float(&mut self) -> std::io::Result<ImaInput<f32>> {
+        Ok(self.read_line().map_or(ImaInput::Pending, |line| ImaInput::parse(&line)))
+    }
+
+    fn read_char(&mut self) -> std::io::Result<ImaInput<i32>> {
+        if self.input.len() < 4 {
+            return Ok(ImaInput::Pending);
+        }
+        let mut buffer = [0u8; 4];
+        buffer.iter_mut().zip(self.input.drain(..4)).for_each(|(byte, input)| *byte = input);
+        Ok(ImaInput::Value(i32::from_ne_bytes(buffer)))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{
+    fmt::Display,
+    time::Duration,
+};
+
+/// Limits on the execution of the machine.
+/// When any of them is reached, the machine is stopped with an error.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionLimits {
+    /// Maximum number of cycles the machine can spend.
+    pub max_cycles: Option<usize>,
+    /// Maximum number of instructions the machine can execute.
+    pub max_instructions: Option<usize>,
+    /// Maximum wall-clock time the machine can run for.
+    pub timeout: Option<Duration>,
+    /// Maximum number of bytes the machine can write to the output.
+    pub max_output_bytes: Option<usize>,
+}
+
+/// Counters of the machine at the moment it was stopped.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionCounters {
+    /// Number of cycles spent.
+    pub cycles: usize,
+    /// Number of instructions executed.
+    pub instructions: usize,
+    /// Number of bytes written to the output.
+    pub output_bytes: usize,
+    /// Wall-clock time since the start of the machine.
+    pub elapsed: Duration,
+}
+
+impl Display for ExecutionCounters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} cycles, {} instructions, {} bytes written, {:.3}s elapsed",
+            self.cycles, self.instructions, self.output_bytes, self.elapsed.as_secs_f64()
+        )
+    }
+}
@@ -0,0 +1,148 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use super::{
+    IMA,
+    decoded::Op,
+    observer::ImaObserver,
+    rounding::RoundingMode,
+    zones::{
+        flags::Flags,
+        memory::{StackPointer, UsedMemory},
+        program::CodeAddr,
+        registers::Registers,
+    },
+};
+
+/// Full state of the machine at a backward branch, compared exactly so a loop is never reported by mistake.
+/// The cycle count is not part of the state, but the touched parts of the memory are.
+#[derive(Clone)]
+pub(crate) struct LoopState {
+    pc: CodeAddr,
+    registers: Registers,
+    flags: Flags,
+    rounding_mode: RoundingMode,
+    gb: StackPointer,
+    lb: StackPointer,
+    sp: StackPointer,
+    memory: UsedMemory,
+}
+
+/// Finds the machine states repeating at backward branches, with the cycle detection of Brent:
+/// a single state is kept, and replaced by the current one each time the number of branches
+/// since it was saved reaches the period, which doubles then.
+/// Without input, the machine is deterministic: if the state ever repeats, it will loop forever,
+/// and the loop is found once the period is larger than its length.
+/// The memory used stays bounded, and the full state is only copied on powers of two.
+#[derive(Default)]
+pub struct LoopDetector {
+    /// State saved at the start of the current period.
+    saved: Option<LoopState>,
+    /// Backward branches since the state was saved.
+    branches: u64,
+    /// Backward branches before the state is saved again.
+    period: u64,
+}
+
+impl LoopDetector {
+    /// Creates a new loop detector, with no known states.
+    pub fn new() -> LoopDetector {
+        LoopDetector {
+            saved: None,
+            branches: 0,
+            period: 1,
+        }
+    }
+
+    /// Forget the state seen so far.
+    pub fn clear(&mut self) {
+        *self = LoopDetector::new();
+    }
+
+    /// Count a backward branch. Returns true if the current state must be saved.
+    fn next_branch(&mut self) -> bool {
+        self.branches += 1;
+        if self.saved.is_some() && self.branches < self.period {
+            return false;
+        }
+        self.branches = 0;
+        self.period *= 2;
+        true
+    }
+}
+
+/// Returns true if the instruction can jump back in the program.
+/// Only those instructions are checked, as any infinite loop needs at least one of them.
+pub fn is_branch(op: &Op) -> bool {
+    matches!(op,
+        Op::BRA(_) |
+        Op::BEQ(_) |
+        Op::BGT(_) |
+        Op::BGE(_) |
+        Op::BOV(_) |
+        Op::BNE(_) |
+        Op::BLT(_) |
+        Op::BLE(_)
+    )
+}
+
+/// Returns true if the instruction brings a value from outside the machine.
+/// The state after such instructions can't be compared with previous ones.
+pub fn is_external_input(op: &Op) -> bool {
+    matches!(op,
+        Op::RINT |
+        Op::RFLOAT |
+        Op::RUTF8 |
+        Op::CLK |
+        Op::SCLK
+    )
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Copy the full state of the machine, before the instruction at the given address.
+    pub(crate) fn loop_state(&self, pc: CodeAddr) -> LoopState {
+        LoopState {
+            pc,
+            registers: self.registers.clone(),
+            flags: self.flags.clone(),
+            rounding_mode: self.rounding_mode,
+            gb: self.gb,
+            lb: self.lb,
+            sp: self.sp,
+            memory: self.memory.used(self.sp),
+        }
+    }
+
+    /// Check if the machine is exactly in the given state, before the instruction at the given address.
+    /// The memory is only compared when everything else is the same: in a loop making progress,
+    /// a counter in a register usually differs, so most branches only cost a few comparisons.
+    pub(crate) fn is_loop_state(&self, state: &LoopState, pc: CodeAddr) -> bool {
+        state.pc == pc
+            && state.sp == self.sp
+            && state.lb == self.lb
+            && state.gb == self.gb
+            && state.rounding_mode == self.rounding_mode
+            && state.flags == self.flags
+            && state.registers.same_bits(&self.registers)
+            && self.memory.same_used(self.sp, &state.memory)
+    }
+
+    /// Visit the state of the machine at a backward branch, before the instruction at the given address.
+    /// Returns true if the state repeats, so the machine loops forever.
+    pub(super) fn visit_loop_state(&mut self, pc: CodeAddr) -> bool {
+        let detector = match self.loop_detector.as_ref() {
+            Some(detector) => detector,
+            None => return false,
+        };
+        if detector.saved.as_ref().is_some_and(|state| self.is_loop_state(state, pc)) {
+            return true;
+        }
+        let save = self.loop_detector.as_mut().is_some_and(LoopDetector::next_branch);
+        if save {
+            let state = self.loop_state(pc);
+            if let Some(detector) = self.loop_detector.as_mut() {
+                detector.saved = Some(state);
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,222 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::instructions::Instruction;
+
+use super::{
+    IMA,
+    address_modes::{GetDadr, Operand, RegisterIndex},
+    data_type::DataType,
+    decoded::Op,
+    zones::{
+        memory::{HeapPointer, Pointer},
+        program::CodeAddr,
+    },
+};
+
+/// Hooks called by the machine while it runs, to build tooling on top of it.
+/// All methods do nothing by default, so an observer only implements the ones it needs.
+pub trait ImaObserver {
+    /// Returns false if the observer ignores everything,
+    /// so the machine can skip the work needed to call the other hooks.
+    fn is_active(&self) -> bool {
+        true
+    }
+    /// Called before the execution of the instruction at the given address.
+    fn before_instruction(&mut self, _pc: CodeAddr, _instruction: &Instruction) {}
+    /// Called after the successful execution of the instruction at the given address, with the cycles it took.
+    fn after_instruction(&mut self, _pc: CodeAddr, _instruction: &Instruction, _cycles: usize) {}
+    /// Called for each memory word an instruction reads, before its execution.
+    fn memory_read(&mut self, _at: Pointer, _value: DataType) {}
+    /// Called for each memory word an instruction wrote, after its execution.
+    fn memory_write(&mut self, _at: Pointer, _value: DataType) {}
+    /// Called when NEW allocates a block on the heap.
+    fn allocate(&mut self, _block: HeapPointer, _size: usize) {}
+    /// Called when DEL frees a block of the heap, during its execution.
+    /// A DEL that fails and only sets OV frees nothing, and is not reported.
+    fn free(&mut self, _block: HeapPointer) {}
+    /// Called with the value an instruction read from the input.
+    fn input(&mut self, _value: DataType) {}
+    /// Called with the bytes an instruction wrote to the output.
+    fn output(&mut self, _bytes: &[u8]) {}
+}
+
+/// The observer of a machine without tooling: it ignores everything, at no cost.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoObserver;
+
+impl ImaObserver for NoObserver {
+    fn is_active(&self) -> bool {
+        false
+    }
+}
+
+/// Two observers, both notified of everything.
+impl<A: ImaObserver, B: ImaObserver> ImaObserver for (A, B) {
+    fn is_active(&self) -> bool {
+        self.0.is_active() || self.1.is_active()
+    }
+    fn before_instruction(&mut self, pc: CodeAddr, instruction: &Instruction) {
+        self.0.before_instruction(pc, instruction);
+        self.1.before_instruction(pc, instruction);
+    }
+    fn after_instruction(&mut self, pc: CodeAddr, instruction: &Instruction, cycles: usize) {
+        self.0.after_instruction(pc, instruction, cycles);
+        self.1.after_instruction(pc, instruction, cycles);
+    }
+    fn memory_read(&mut self, at: Pointer, value: DataType) {
+        self.0.memory_read(at, value);
+        self.1.memory_read(at, value);
+    }
+    fn memory_write(&mut self, at: Pointer, value: DataType) {
+        self.0.memory_write(at, value);
+        self.1.memory_write(at, value);
+    }
+    fn allocate(&mut self, block: HeapPointer, size: usize) {
+        self.0.allocate(block, size);
+        self.1.allocate(block, size);
+    }
+    fn free(&mut self, block: HeapPointer) {
+        self.0.free(block);
+        self.1.free(block);
+    }
+    fn input(&mut self, value: DataType) {
+        self.0.input(value);
+        self.1.input(value);
+    }
+    fn output(&mut self, bytes: &[u8]) {
+        self.0.output(bytes);
+        self.1.output(bytes);
+    }
+}
+
+/// Any number of observers, notified of everything in order.
+/// With boxed observers, they can be of different types.
+impl<O: ImaObserver> ImaObserver for Vec<O> {
+    fn is_active(&self) -> bool {
+        self.iter().any(|observer| observer.is_active())
+    }
+    fn before_instruction(&mut self, pc: CodeAddr, instruction: &Instruction) {
+        self.iter_mut().for_each(|observer| observer.before_instruction(pc, instruction));
+    }
+    fn after_instruction(&mut self, pc: CodeAddr, instruction: &Instruction, cycles: usize) {
+        self.iter_mut().for_each(|observer| observer.after_instruction(pc, instruction, cycles));
+    }
+    fn memory_read(&mut self, at: Pointer, value: DataType) {
+        self.iter_mut().for_each(|observer| observer.memory_read(at, value));
+    }
+    fn memory_write(&mut self, at: Pointer, value: DataType) {
+        self.iter_mut().for_each(|observer| observer.memory_write(at, value));
+    }
+    fn allocate(&mut self, block: HeapPointer, size: usize) {
+        self.iter_mut().for_each(|observer| observer.allocate(block, size));
+    }
+    fn free(&mut self, block: HeapPointer) {
+        self.iter_mut().for_each(|observer| observer.free(block));
+    }
+    fn input(&mut self, value: DataType) {
+        self.iter_mut().for_each(|observer| observer.input(value));
+    }
+    fn output(&mut self, bytes: &[u8]) {
+        self.iter_mut().for_each(|observer| observer.output(bytes));
+    }
+}
+
+impl<O: ImaObserver + ?Sized> ImaObserver for Box<O> {
+    fn is_active(&self) -> bool {
+        (**self).is_active()
+    }
+    fn before_instruction(&mut self, pc: CodeAddr, instruction: &Instruction) {
+        (**self).before_instruction(pc, instruction);
+    }
+    fn after_instruction(&mut self, pc: CodeAddr, instruction: &Instruction, cycles: usize) {
+        (**self).after_instruction(pc, instruction, cycles);
+    }
+    fn memory_read(&mut self, at: Pointer, value: DataType) {
+        (**self).memory_read(at, value);
+    }
+    fn memory_write(&mut self, at: Pointer, value: DataType) {
+        (**self).memory_write(at, value);
+    }
+    fn allocate(&mut self, block: HeapPointer, size: usize) {
+        (**self).allocate(block, size);
+    }
+    fn free(&mut self, block: HeapPointer) {
+        (**self).free(block);
+    }
+    fn input(&mut self, value: DataType) {
+        (**self).input(value);
+    }
+    fn output(&mut self, bytes: &[u8]) {
+        (**self).output(bytes);
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Get the observer of the machine.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Get the observer of the machine, mutably.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Get the memory words the instruction reads, with their current value.
+    pub(super) fn memory_reads(&self, op: &Op) -> Vec<(Pointer, DataType)> {
+        let pointers = match op {
+            Op::POP(_) => vec![Pointer::Stack(self.sp)],
+            Op::RTS => self.lb.offset(-1).into_iter()
+                .chain(Some(self.lb))
+                .map(Pointer::Stack)
+                .collect(),
+            _ => match op.operands().value {
+                Some(Operand::Memory(address)) => self.get_dadr(address).ok().into_iter().collect(),
+                _ => Vec::new(),
+            },
+        };
+        pointers.into_iter()
+            .filter_map(|ptr| self.memory.get(ptr).map(|value| (ptr, value)))
+            .collect()
+    }
+
+    /// Notify the observer of the instruction about to be executed, and of its memory reads.
+    /// This also starts logging the memory writes.
+    pub(super) fn observe_before(&mut self, op: &Op, pc: CodeAddr) {
+        if !self.observer.is_active() {
+            return;
+        }
+        if let Some(instruction) = self.code.instruction(pc) {
+            self.observer.before_instruction(pc, instruction);
+        }
+        for (at, value) in self.memory_reads(op) {
+            self.observer.memory_read(at, value);
+        }
+        self.memory.start_write_log();
+    }
+
+    /// Notify the observer of the instruction that was just executed:
+    /// its memory writes, allocated blocks and input.
+    pub(super) fn observe_after(&mut self, op: &Op, pc: CodeAddr, cycles: usize, writes: &[(Pointer, DataType)]) {
+        if !self.observer.is_active() {
+            return;
+        }
+        for (at, value) in writes {
+            self.observer.memory_write(*at, *value);
+        }
+        match op {
+            Op::NEW(_, rm) => if let DataType::MemAddr(Pointer::Heap(ptr)) = self.registers.get(*rm) {
+                if let Some((block, size)) = self.memory.get_block(ptr) {
+                    self.observer.allocate(block, size);
+                }
+            },
+            Op::RINT | Op::RFLOAT | Op::RUTF8 => {
+                self.observer.input(self.registers.get(RegisterIndex(1)));
+            },
+            _ => {},
+        }
+        if let Some(instruction) = self.code.instruction(pc) {
+            self.observer.after_instruction(pc, instruction, cycles);
+        }
+    }
+}
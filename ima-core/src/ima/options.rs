@@ -1,6 +1,19 @@
 /// Created by Virgile HENRY, 2023/09/28
 
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, str::FromStr, time::Duration};
+
+use crate::parser::label::Label;
+
+use super::{
+    address_modes::RegisterIndex,
+    callee_saved::default_callee_saved,
+    clock::ClockMode,
+    limits::ExecutionLimits,
+    trace::TraceFilter,
+    warning::CheckMode,
+    zones::memory::allocator::AllocatorKind,
+    zones::registers::{DEFAULT_REGISTER_COUNT, MAX_REGISTER_COUNT, MIN_REGISTER_COUNT},
+};
 
 
 #[derive(Debug, Clone)]
@@ -50,6 +63,44 @@ pub struct ImaOptions {
     pub stack_size: usize,
     /// Size of the heap in words.
     pub heap_size: usize,
+    /// Allocator used for the heap.
+    pub allocator: AllocatorKind,
+    /// Number of registers Rm of the machine, 16 by default.
+    /// The program must be parsed for the same number of registers.
+    pub register_count: usize,
+    /// Stop the machine when it reaches the same state twice.
+    pub detect_infinite_loops: bool,
+    /// Number of steps kept to step back. By default, `DEFAULT_HISTORY` in debug mode and none otherwise.
+    pub history: Option<usize>,
+    /// Limits on cycles, instructions, time and output of the machine.
+    pub limits: ExecutionLimits,
+    /// Where CLK and SCLK get the time from.
+    pub clock: ClockMode,
+    /// Record the cycles spent per instruction and per subroutine.
+    pub profile: bool,
+    /// Path to write the profile report to. The folded stacks are written next to it, with a ".folded" extension.
+    pub profile_output: Option<String>,
+    /// Path to write the execution trace to, as JSON Lines.
+    pub trace_output: Option<String>,
+    /// Selects the instructions written to the trace.
+    pub trace_filter: TraceFilter,
+    /// What to do when an undefined value is used as an operand.
+    pub uninitialized_mode: CheckMode,
+    /// Poison freed heap blocks, catch double frees and report leaks on HALT.
+    pub sanitize_heap: bool,
+    /// What to do when the stack grows past the budget declared by the last TSTO of a frame.
+    pub tsto_check: CheckMode,
+    /// What to do when a subroutine returns without restoring its callee-saved registers.
+    pub callee_saved_check: CheckMode,
+    /// The registers a subroutine must restore before returning, R2 to R15 by default.
+    /// Registers the machine does not have are ignored.
+    pub callee_saved_registers: Vec<RegisterIndex>,
+    /// Path to write a snapshot of the machine to, when it halts or the debug session ends.
+    pub snapshot_on_exit: Option<String>,
+    /// Path to write a snapshot of the machine to, when it stops on a runtime error.
+    pub snapshot_on_error: Option<String>,
+    /// Path of a snapshot to start the machine from, instead of the start of the program.
+    pub restore_snapshot: Option<String>,
     /// path to file
     pub file: String,
 }
@@ -60,6 +111,24 @@ impl Default for ImaOptions {
             run_mode: ImaRunMode::Run,
             stack_size: 10_000,
             heap_size: 10_000,
+            allocator: AllocatorKind::default(),
+            register_count: DEFAULT_REGISTER_COUNT,
+            detect_infinite_loops: false,
+            history: None,
+            limits: ExecutionLimits::default(),
+            clock: ClockMode::default(),
+            profile: false,
+            profile_output: None,
+            trace_output: None,
+            trace_filter: TraceFilter::default(),
+            uninitialized_mode: CheckMode::default(),
+            sanitize_heap: false,
+            tsto_check: CheckMode::default(),
+            callee_saved_check: CheckMode::default(),
+            callee_saved_registers: default_callee_saved(),
+            snapshot_on_exit: None,
+            snapshot_on_error: None,
+            restore_snapshot: None,
             file: String::new(),
         }
     }
@@ -67,7 +136,8 @@ impl Default for ImaOptions {
 
 impl ImaOptions {
     /// Parse the options from the command line arguments into ImaOptions.
-    pub fn new(args: std::env::Args) -> Result<ImaOptions, OptionParsingError> {
+    /// The first argument is the program name, and is skipped.
+    pub fn new(args: impl Iterator<Item = String>) -> Result<ImaOptions, OptionParsingError> {
         let mut options = ImaOptions::default();
         let mut args = args.skip(1);
 
@@ -76,6 +146,71 @@ impl ImaOptions {
                 "-d" => options.run_mode = ImaRunMode::Debug,
                 "-s" => options.run_mode = ImaRunMode::Stats,
                 "-r" => options.run_mode = ImaRunMode::WriteNewLines,
+                "--allocator" => options.allocator = parse_value(&mut args, &arg)?,
+                "--registers" => {
+                    let count: usize = parse_value(&mut args, &arg)?;
+                    if !(MIN_REGISTER_COUNT..=MAX_REGISTER_COUNT).contains(&count) {
+                        return Err(OptionParsingError::InvalidArgumentFormat {
+                            for_arg: arg.to_string(),
+                            found: count.to_string(),
+                        });
+                    }
+                    options.register_count = count;
+                }
+                "--detect-loops" => options.detect_infinite_loops = true,
+                "--history" => options.history = Some(parse_value(&mut args, &arg)?),
+                "--max-cycles" => options.limits.max_cycles = Some(parse_value(&mut args, &arg)?),
+                "--max-instructions" => options.limits.max_instructions = Some(parse_value(&mut args, &arg)?),
+                "--timeout" => {
+                    let seconds: f64 = parse_value(&mut args, &arg)?;
+                    options.limits.timeout = Some(Duration::try_from_secs_f64(seconds).map_err(|_| OptionParsingError::InvalidArgumentFormat {
+                        for_arg: arg.to_string(),
+                        found: seconds.to_string(),
+                    })?);
+                }
+                "--clock" => options.clock = parse_value(&mut args, &arg)?,
+                "--max-output" => options.limits.max_output_bytes = Some(parse_value(&mut args, &arg)?),
+                "--profile" => {
+                    options.profile = true;
+                    options.profile_output = Some(parse_value(&mut args, &arg)?);
+                }
+                "--uninitialized" => options.uninitialized_mode = parse_value(&mut args, &arg)?,
+                "--sanitize-heap" => options.sanitize_heap = true,
+                "--check-tsto" => options.tsto_check = parse_value(&mut args, &arg)?,
+                "--check-callee-saved" => options.callee_saved_check = parse_value(&mut args, &arg)?,
+                "--callee-saved" => {
+                    let list: String = parse_value(&mut args, &arg)?;
+                    match parse_registers(&list) {
+                        Some(registers) => options.callee_saved_registers = registers,
+                        None => return Err(OptionParsingError::InvalidArgumentFormat {
+                            for_arg: arg.to_string(),
+                            found: list,
+                        }),
+                    }
+                }
+                "--trace" => options.trace_output = Some(parse_value(&mut args, &arg)?),
+                "--trace-lines" => {
+                    let range: String = parse_value(&mut args, &arg)?;
+                    let parsed = match range.split_once('-') {
+                        Some((start, end)) => start.trim().parse::<u32>().ok().zip(end.trim().parse::<u32>().ok()),
+                        None => range.trim().parse::<u32>().ok().map(|line| (line, line)),
+                    };
+                    match parsed {
+                        Some(range) => options.trace_filter.lines.push(range),
+                        None => return Err(OptionParsingError::InvalidArgumentFormat {
+                            for_arg: arg.to_string(),
+                            found: range,
+                        }),
+                    }
+                }
+                "--trace-label" => {
+                    let label: String = parse_value(&mut args, &arg)?;
+                    // labels are case insensitive, and stored in lower case
+                    options.trace_filter.labels.push(Label(label.to_lowercase()));
+                }
+                "--snapshot-on-exit" => options.snapshot_on_exit = Some(parse_value(&mut args, &arg)?),
+                "--snapshot-on-error" => options.snapshot_on_error = Some(parse_value(&mut args, &arg)?),
+                "--restore-snapshot" => options.restore_snapshot = Some(parse_value(&mut args, &arg)?),
                 "-p" => {
                     let stack_size = args.next().ok_or(OptionParsingError::MissingArgumentValue {
                         for_arg: "-p".to_string(),
@@ -104,4 +239,31 @@ impl ImaOptions {
 
         Err(OptionParsingError::NoFileProvided)
     }
-}
\ No newline at end of file
+}
+
+/// Parse the next argument as the value of the given option.
+fn parse_value<T: FromStr>(args: &mut impl Iterator<Item = String>, for_arg: &str) -> Result<T, OptionParsingError> {
+    let value = args.next().ok_or(OptionParsingError::MissingArgumentValue {
+        for_arg: for_arg.to_string(),
+    })?;
+    value.parse::<T>().map_err(|_| OptionParsingError::InvalidArgumentFormat {
+        for_arg: for_arg.to_string(),
+        found: value,
+    })
+}
+
+/// Parse a comma separated list of registers or register ranges, like "R2-R7,R10".
+fn parse_registers(list: &str) -> Option<Vec<RegisterIndex>> {
+    let mut registers = Vec::new();
+    for item in list.split(',') {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start = RegisterIndex::from_str(start).ok()?;
+                let end = RegisterIndex::from_str(end).ok()?;
+                registers.extend((start.0..=end.0).map(RegisterIndex));
+            },
+            None => registers.push(RegisterIndex::from_str(item).ok()?),
+        }
+    }
+    Some(registers)
+}
@@ -0,0 +1,253 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{
+    collections::HashMap,
+    io::Write,
+};
+
+use super::{
+    IMA,
+    decoded::Op,
+    observer::ImaObserver,
+    zones::program::CodeAddr,
+};
+
+/// Execution count and cycles spent on a single instruction.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstructionProfile {
+    /// Number of times the instruction was executed.
+    pub executions: usize,
+    /// Total number of cycles spent on the instruction.
+    pub cycles: usize,
+}
+
+/// A node of the call tree. The root is the main program.
+#[derive(Debug, Clone)]
+struct CallNode {
+    /// Entry address of the subroutine, None for the main program.
+    function: Option<CodeAddr>,
+    /// Parent node, None for the root.
+    parent: Option<usize>,
+    /// Children nodes, by subroutine entry address.
+    children: HashMap<CodeAddr, usize>,
+    /// Number of times this node was entered.
+    calls: usize,
+    /// Cycles spent in this node, excluding the children.
+    self_cycles: usize,
+}
+
+/// Cycles spent in a subroutine, summed over all its calls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FunctionProfile {
+    /// Number of times the subroutine was called.
+    pub calls: usize,
+    /// Cycles spent in the subroutine and everything it called.
+    pub inclusive_cycles: usize,
+    /// Cycles spent in the subroutine itself.
+    pub exclusive_cycles: usize,
+}
+
+/// Records the cycles spent per instruction and per call stack.
+/// The call stack is tracked with BSR and RTS.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    /// Profile of each instruction, indexed by code address.
+    instructions: Vec<InstructionProfile>,
+    /// Call tree, the first node being the main program.
+    nodes: Vec<CallNode>,
+    /// Current node in the call tree.
+    current: usize,
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    /// Creates an empty profiler.
+    pub fn new() -> Profiler {
+        Profiler {
+            instructions: Vec::new(),
+            nodes: vec![CallNode {
+                function: None,
+                parent: None,
+                children: HashMap::new(),
+                calls: 1,
+                self_cycles: 0,
+            }],
+            current: 0,
+        }
+    }
+
+    /// Record the execution of an instruction.
+    /// `new_pc` is the program counter after the execution, used to find the called subroutine on BSR.
+    pub fn record(&mut self, pc: CodeAddr, op: &Op, cycles: usize, new_pc: CodeAddr) {
+        if self.instructions.len() <= pc as usize {
+            self.instructions.resize(pc as usize + 1, InstructionProfile::default());
+        }
+        let profile = &mut self.instructions[pc as usize];
+        profile.executions += 1;
+        profile.cycles += cycles;
+        self.nodes[self.current].self_cycles += cycles;
+
+        match op {
+            Op::BSR(_) => self.enter(new_pc),
+            Op::RTS => if let Some(parent) = self.nodes[self.current].parent {
+                self.current = parent;
+            },
+            _ => {},
+        }
+    }
+
+    /// Enter the subroutine at the given address from the current node.
+    fn enter(&mut self, function: CodeAddr) {
+        let next = self.nodes.len();
+        let child = *self.nodes[self.current].children.entry(function).or_insert(next);
+        if child == next {
+            self.nodes.push(CallNode {
+                function: Some(function),
+                parent: Some(self.current),
+                children: HashMap::new(),
+                calls: 0,
+                self_cycles: 0,
+            });
+        }
+        self.nodes[child].calls += 1;
+        self.current = child;
+    }
+
+    /// Get the profile of the instruction at the given address.
+    pub fn instruction(&self, pc: CodeAddr) -> InstructionProfile {
+        self.instructions.get(pc as usize).copied().unwrap_or_default()
+    }
+
+    /// Returns true if the function appears in the parents of the node.
+    fn is_recursive(&self, node: usize) -> bool {
+        let function = self.nodes[node].function;
+        let mut parent = self.nodes[node].parent;
+        while let Some(p) = parent {
+            if self.nodes[p].function == function {
+                return true;
+            }
+            parent = self.nodes[p].parent;
+        }
+        false
+    }
+
+    /// Profile of every subroutine, by entry address. The main program has no entry address.
+    /// Recursive calls are not counted twice in the inclusive cycles.
+    pub fn functions(&self) -> HashMap<Option<CodeAddr>, FunctionProfile> {
+        // children are always created after their parent, so summing in reverse order is enough
+        let mut inclusive_cycles = self.nodes.iter().map(|node| node.self_cycles).collect::<Vec<_>>();
+        for index in (0..self.nodes.len()).rev() {
+            if let Some(parent) = self.nodes[index].parent {
+                inclusive_cycles[parent] += inclusive_cycles[index];
+            }
+        }
+        let mut result: HashMap<Option<CodeAddr>, FunctionProfile> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let profile = result.entry(node.function).or_default();
+            profile.calls += node.calls;
+            profile.exclusive_cycles += node.self_cycles;
+            if !self.is_recursive(index) {
+                profile.inclusive_cycles += inclusive_cycles[index];
+            }
+        }
+        result
+    }
+
+    /// All the call stacks with the cycles spent on top of them, from the main program to the innermost call.
+    pub fn stacks(&self) -> Vec<(Vec<Option<CodeAddr>>, usize)> {
+        let mut result = Vec::new();
+        for node in &self.nodes {
+            if node.self_cycles == 0 {
+                continue;
+            }
+            let mut stack = vec![node.function];
+            let mut parent = node.parent;
+            while let Some(p) = parent {
+                stack.push(self.nodes[p].function);
+                parent = self.nodes[p].parent;
+            }
+            stack.reverse();
+            result.push((stack, node.self_cycles));
+        }
+        result
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Get the profiler of the machine, if profiling is enabled.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Name of a subroutine for the profiler reports: its label, or its address if it has none.
+    fn function_name(&self, function: Option<CodeAddr>) -> String {
+        match function {
+            Some(addr) => match self.code.location(addr).and_then(|location| location.label) {
+                Some(label) => label.to_string(),
+                None => format!("@{}", addr),
+            },
+            None => "<main>".to_string(),
+        }
+    }
+
+    /// Write the profile report: cycles per subroutine, then the source annotated with the cycles per line.
+    /// Does nothing if profiling is not enabled.
+    pub fn write_profile_report<W: Write>(&self, source: &str, output: &mut W) -> std::io::Result<()> {
+        let profiler = match &self.profiler {
+            Some(profiler) => profiler,
+            None => return Ok(()),
+        };
+
+        writeln!(output, "Total: {} cycles, {} instructions", self.cycle_count, self.instruction_count)?;
+        writeln!(output)?;
+        writeln!(output, "{:<24} {:>8} {:>12} {:>12}", "Subroutine", "Calls", "Inclusive", "Exclusive")?;
+        let mut functions = profiler.functions().into_iter().collect::<Vec<_>>();
+        functions.sort_by_key(|(_, profile)| std::cmp::Reverse(profile.inclusive_cycles));
+        for (function, profile) in functions {
+            writeln!(
+                output,
+                "{:<24} {:>8} {:>12} {:>12}",
+                self.function_name(function), profile.calls, profile.inclusive_cycles, profile.exclusive_cycles
+            )?;
+        }
+
+        let mut lines: HashMap<u32, InstructionProfile> = HashMap::new();
+        for pc in 0..profiler.instructions.len() as CodeAddr {
+            if let Some(location) = self.code.location(pc) {
+                let line = lines.entry(location.line).or_default();
+                line.executions += profiler.instruction(pc).executions;
+                line.cycles += profiler.instruction(pc).cycles;
+            }
+        }
+
+        writeln!(output)?;
+        writeln!(output, "{:>10} {:>12} | {:>5} | Source", "Count", "Cycles", "Line")?;
+        for (index, text) in source.lines().enumerate() {
+            match lines.get(&(index as u32 + 1)) {
+                Some(profile) => writeln!(output, "{:>10} {:>12} | {:>5} | {}", profile.executions, profile.cycles, index + 1, text)?,
+                None => writeln!(output, "{:>10} {:>12} | {:>5} | {}", "", "", index + 1, text)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the call stacks in the folded format used by flamegraph tools:
+    /// one line per stack, with the frames separated by semicolons and followed by the cycles.
+    /// Does nothing if profiling is not enabled.
+    pub fn write_folded_stacks<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        let profiler = match &self.profiler {
+            Some(profiler) => profiler,
+            None => return Ok(()),
+        };
+        for (stack, cycles) in profiler.stacks() {
+            let names = stack.into_iter().map(|function| self.function_name(function)).collect::<Vec<_>>();
+            writeln!(output, "{} {}", names.join(";"), cycles)?;
+        }
+        Ok(())
+    }
+}
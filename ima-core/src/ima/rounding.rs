@@ -0,0 +1,155 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::cmp::Ordering;
+
+use super::data_type::{Float, Int};
+
+/// Rounding mode for the floating point operations of the machine.
+/// Set by the SETROUND_* instructions.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RoundingMode {
+    /// Round to the nearest value, ties to even. This is the default.
+    #[default]
+    ToNearest,
+    /// Round toward positive infinity.
+    Upward,
+    /// Round toward negative infinity.
+    Downward,
+    /// Round toward zero.
+    TowardZero,
+}
+
+/// Error free sum of two f64: returns (s, e) such that s + e = a + b exactly.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    let e = (a - (s - bb)) + (b - bb);
+    (s, e)
+}
+
+/// Next representable float toward positive infinity.
+fn next_up(f: Float) -> Float {
+    if f.is_nan() || f == Float::INFINITY {
+        f
+    } else if f == 0.0 {
+        Float::from_bits(1)
+    } else if f > 0.0 {
+        Float::from_bits(f.to_bits() + 1)
+    } else {
+        Float::from_bits(f.to_bits() - 1)
+    }
+}
+
+/// Next representable float toward negative infinity.
+fn next_down(f: Float) -> Float {
+    -next_up(-f)
+}
+
+impl RoundingMode {
+    /// Correct the result of an operation rounded to nearest, knowing the sign of the rounding error.
+    /// The error is the exact result minus the rounded one.
+    /// If the operation overflowed from finite operands, the error sign tells on which side.
+    fn correct(self, nearest: Float, error: Ordering) -> Float {
+        match (self, error) {
+            (_, Ordering::Equal) => nearest,
+            (RoundingMode::ToNearest, _) => nearest,
+            (RoundingMode::Upward, Ordering::Greater) => next_up(nearest),
+            (RoundingMode::Downward, Ordering::Less) => next_down(nearest),
+            (RoundingMode::TowardZero, Ordering::Less) if nearest > 0.0 => next_down(nearest),
+            (RoundingMode::TowardZero, Ordering::Greater) if nearest < 0.0 => next_up(nearest),
+            (RoundingMode::Upward, Ordering::Less) |
+            (RoundingMode::Downward, Ordering::Greater) |
+            (RoundingMode::TowardZero, _) => match nearest {
+                // overflow in the wrong direction: stay on the largest finite value
+                Float::INFINITY => Float::MAX,
+                Float::NEG_INFINITY => Float::MIN,
+                _ => nearest,
+            },
+        }
+    }
+
+    /// Round the exact value hi + lo (both f64, with |lo| <= ulp(hi) / 2) to a float.
+    fn round_exact(self, hi: f64, lo: f64) -> Float {
+        if hi.is_nan() || hi.is_infinite() {
+            return hi as Float;
+        }
+        let mut nearest = hi as Float;
+        if nearest.is_infinite() {
+            // overflowed, the exact value is past the largest float
+            let error = if nearest > 0.0 { Ordering::Greater } else { Ordering::Less };
+            return self.correct(nearest, error);
+        }
+        // hi - nearest is exact, as both values are very close
+        let diff = hi - nearest as f64;
+        if diff != 0.0 && lo != 0.0 {
+            let other = if diff > 0.0 { next_up(nearest) } else { next_down(nearest) };
+            if other as f64 - hi == diff && (lo > 0.0) == (diff > 0.0) {
+                // hi is a tie between two floats, but the exact value is on the side of the other one
+                nearest = other;
+            }
+        }
+        let error = (hi - nearest as f64) + lo;
+        self.correct(nearest, error.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+    }
+
+    /// Performs a float addition with the rounding mode.
+    pub fn add(self, a: Float, b: Float) -> Float {
+        let (hi, lo) = two_sum(a as f64, b as f64);
+        self.round_exact(hi, lo)
+    }
+
+    /// Performs a float subtraction with the rounding mode.
+    pub fn sub(self, a: Float, b: Float) -> Float {
+        self.add(a, -b)
+    }
+
+    /// Performs a float multiplication with the rounding mode.
+    pub fn mul(self, a: Float, b: Float) -> Float {
+        // the product of two 24 bits mantissas fits in the 53 bits of a f64
+        self.round_exact(a as f64 * b as f64, 0.0)
+    }
+
+    /// Performs a float fused multiply-add (a * b + c) with the rounding mode.
+    pub fn fma(self, a: Float, b: Float, c: Float) -> Float {
+        let (hi, lo) = two_sum(a as f64 * b as f64, c as f64);
+        self.round_exact(hi, lo)
+    }
+
+    /// Performs a float division with the rounding mode.
+    pub fn div(self, a: Float, b: Float) -> Float {
+        let nearest = a / b;
+        if b == 0.0 || !a.is_finite() || !b.is_finite() || nearest.is_nan() {
+            return nearest;
+        }
+        if nearest.is_infinite() {
+            let error = if nearest > 0.0 { Ordering::Greater } else { Ordering::Less };
+            return self.correct(nearest, error);
+        }
+        // the remainder a - q * b is exact in f64, and has the sign of (a / b - q) * b.
+        let remainder = a as f64 - nearest as f64 * b as f64;
+        let error = match remainder.partial_cmp(&0.0) {
+            Some(Ordering::Equal) | None => Ordering::Equal,
+            Some(ordering) if b > 0.0 => ordering,
+            Some(ordering) => ordering.reverse(),
+        };
+        self.correct(nearest, error)
+    }
+
+    /// Convert an integer to a float with the rounding mode.
+    pub fn float(self, i: Int) -> Float {
+        self.round_exact(i as f64, 0.0)
+    }
+
+    /// Convert a float to an integer with the rounding mode.
+    /// Out of range values saturate, and NaN gives 0, like the `as` cast INT always used.
+    pub fn int(self, f: Float) -> Int {
+        let rounded = match self {
+            RoundingMode::ToNearest => f.round_ties_even(),
+            RoundingMode::Upward => f.ceil(),
+            RoundingMode::Downward => f.floor(),
+            RoundingMode::TowardZero => f.trunc(),
+        };
+        rounded as Int
+    }
+}
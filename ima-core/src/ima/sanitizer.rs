@@ -0,0 +1,168 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{
+    IMA,
+    address_modes::{GetDadr, GetDval},
+    control_flow::ImaControlFlow,
+    data_type::DataType,
+    decoded::Op,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    warning::WarningKind,
+    zones::{
+        memory::{HeapPointer, Memory, Pointer},
+        program::CodeAddr,
+    },
+};
+
+/// Most freed words kept poisoned at once. Older blocks are given back to the allocator past it.
+pub const QUARANTINE_WORDS: usize = 1024;
+
+/// Keeps track of the heap blocks to catch use after free, double free and leaks.
+/// Freed blocks are poisoned and kept in quarantine, so any access to them can be caught.
+/// The quarantine is bounded: the oldest blocks are given back to the allocator once it holds
+/// more than `QUARANTINE_WORDS`, or when a NEW would not fit otherwise.
+/// This way, the sanitizer does not make a program run out of heap.
+#[derive(Debug, Clone, Default)]
+pub struct HeapSanitizer {
+    /// Live blocks, with their size and the address of the NEW that allocated them.
+    live: BTreeMap<HeapPointer, (usize, CodeAddr)>,
+    /// Freed blocks in quarantine, with their size and the source line of the DEL that freed them.
+    freed: BTreeMap<HeapPointer, (usize, Option<u32>)>,
+    /// Freed blocks in quarantine, the oldest first.
+    quarantine: VecDeque<HeapPointer>,
+    /// Number of words in quarantine.
+    quarantined_words: usize,
+}
+
+impl HeapSanitizer {
+    /// Creates a sanitizer with no known blocks.
+    pub fn new() -> HeapSanitizer {
+        HeapSanitizer::default()
+    }
+
+    /// Number of freed words still poisoned.
+    pub fn quarantined_words(&self) -> usize {
+        self.quarantined_words
+    }
+
+    /// Take the oldest block out of quarantine, with its size.
+    fn pop_quarantine(&mut self) -> Option<(HeapPointer, usize)> {
+        let ptr = self.quarantine.pop_front()?;
+        let (size, _) = self.freed.remove(&ptr)?;
+        self.quarantined_words -= size;
+        Some((ptr, size))
+    }
+
+    /// Find the freed block containing the pointer, and the line it was freed at.
+    pub fn freed_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, Option<u32>)> {
+        let (start, (size, line)) = self.freed.range(..=ptr).next_back()?;
+        match ptr.as_index() < start.as_index() + size {
+            true => Some((*start, *line)),
+            false => None,
+        }
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Before the execution of an instruction, check it does not access or free a freed block.
+    /// LEA and PEA only compute their address, and do not access it.
+    pub(super) fn check_heap_access(&self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        let sanitizer = match &self.heap_sanitizer {
+            Some(sanitizer) => sanitizer,
+            None => return Ok(()),
+        };
+        let error = match op {
+            Op::DEL(rm) => match self.registers.get(*rm) {
+                DataType::MemAddr(Pointer::Heap(ptr)) => sanitizer.freed_block(ptr)
+                    .filter(|(start, _)| *start == ptr)
+                    .map(|(_, freed_at)| ImaExecutionError::DoubleFree { ptr, freed_at }),
+                _ => None,
+            },
+            op => match op.operands().accessed().and_then(|address| self.get_dadr(address).ok()) {
+                Some(Pointer::Heap(ptr)) => sanitizer.freed_block(ptr)
+                    .map(|(_, freed_at)| ImaExecutionError::UseAfterFree { ptr, freed_at }),
+                _ => None,
+            },
+        };
+        match error {
+            Some(error) => Err(self.execution_error(error, pc)),
+            None => Ok(()),
+        }
+    }
+
+    /// After the execution of an instruction, keep track of the allocated and freed blocks.
+    /// On HALT, all the blocks still allocated are reported as leaks.
+    pub(super) fn track_heap(&mut self, op: &Op, pc: CodeAddr) {
+        if self.heap_sanitizer.is_none() {
+            return;
+        }
+        match op {
+            Op::NEW(_, rm) => if let DataType::MemAddr(Pointer::Heap(ptr)) = self.registers.get(*rm) {
+                if let Some((_, size)) = self.memory.get_block(ptr) {
+                    let sanitizer = self.heap_sanitizer.as_mut().unwrap();
+                    sanitizer.live.insert(ptr, (size, pc));
+                }
+            },
+            Op::DEL(rm) => if let DataType::MemAddr(Pointer::Heap(ptr)) = self.registers.get(*rm) {
+                // the block is no longer allocated if the free succeeded
+                if self.memory.get_block(ptr).is_none() {
+                    let line = self.code.source_line(pc);
+                    let sanitizer = self.heap_sanitizer.as_mut().unwrap();
+                    if let Some((size, _)) = sanitizer.live.remove(&ptr) {
+                        sanitizer.freed.insert(ptr, (size, line));
+                        sanitizer.quarantine.push_back(ptr);
+                        sanitizer.quarantined_words += size;
+                        self.memory.poison(ptr, size);
+                        self.release_quarantine(|sanitizer, _| sanitizer.quarantined_words > QUARANTINE_WORDS);
+                    }
+                }
+            },
+            _ => {},
+        }
+        if let ImaControlFlow::Halt = self.control_flow {
+            self.report_leaks();
+        }
+    }
+
+    /// Before a NEW, give the oldest freed blocks back to the allocator until the new block fits,
+    /// so the program gets the same heap as without the sanitizer.
+    pub(super) fn make_heap_room(&mut self, op: &Op) {
+        if self.heap_sanitizer.as_ref().is_none_or(|sanitizer| sanitizer.quarantine.is_empty()) {
+            return;
+        }
+        let size = match op {
+            Op::NEW(value, _) => match self.get_dval(*value) {
+                Ok(DataType::Int(size)) if size >= 0 => size as usize,
+                _ => return,
+            },
+            _ => return,
+        };
+        self.release_quarantine(|_, memory| !memory.can_allocate(size));
+    }
+
+    /// Give the oldest freed blocks back to the allocator, as long as the condition holds.
+    fn release_quarantine(&mut self, condition: impl Fn(&HeapSanitizer, &Memory) -> bool) {
+        while let Some(sanitizer) = self.heap_sanitizer.as_mut() {
+            if !condition(sanitizer, &self.memory) {
+                break;
+            }
+            match sanitizer.pop_quarantine() {
+                Some((ptr, size)) => self.memory.release(ptr, size),
+                None => break,
+            }
+        }
+    }
+
+    /// Raise a warning for every block that is still allocated, at the NEW that allocated it.
+    fn report_leaks(&mut self) {
+        let live = self.heap_sanitizer.as_ref().map(|sanitizer| sanitizer.live.clone()).unwrap_or_default();
+        for (block, size) in self.memory.allocations() {
+            if let Some((_, new_pc)) = live.get(&block) {
+                self.warn(WarningKind::MemoryLeak { block, size }, *new_pc);
+            }
+        }
+    }
+}
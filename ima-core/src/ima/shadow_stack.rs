@@ -0,0 +1,203 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::fmt::Display;
+
+use crate::parser::label::Label;
+use super::{
+    IMA,
+    address_modes::GetDadr,
+    data_type::DataType,
+    decoded::Op,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    zones::{
+        memory::{Pointer, StackPointer},
+        program::{CodeAddr, DisplayLine},
+    },
+};
+
+/// The two words a BSR saves on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSlot {
+    /// The return address, at LB - 1.
+    ReturnAddress,
+    /// The LB of the caller, at LB.
+    SavedLb,
+}
+
+impl Display for FrameSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameSlot::ReturnAddress => write!(f, "return address"),
+            FrameSlot::SavedLb => write!(f, "saved LB"),
+        }
+    }
+}
+
+/// A frame saved by a BSR that was overwritten before the matching RTS.
+#[derive(Debug, Clone)]
+pub struct FrameCorruption {
+    /// The overwritten word of the frame.
+    pub slot: FrameSlot,
+    /// Label of the subroutine owning the frame, if it could be found.
+    pub callee: Option<Label>,
+    /// Source line of the BSR instruction that made the call, if it is known.
+    pub call_site: Option<u32>,
+    /// Value saved by the BSR.
+    pub expected: DataType,
+    /// Value found at RTS.
+    pub found: DataType,
+    /// Source line of the last instruction that wrote to the slot, if it is known.
+    pub written_at: Option<u32>,
+}
+
+impl Display for FrameCorruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Corrupted {} in the frame of ", self.slot)?;
+        match &self.callee {
+            Some(label) => write!(f, "{}", label)?,
+            None => write!(f, "<unknown>")?,
+        }
+        write!(f, ", called at {}: expected {}, found {}", DisplayLine(self.call_site), self.expected, self.found)?;
+        match self.written_at {
+            Some(line) => write!(f, ", last written at line {}", line),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A frame pushed by a BSR, as it should be found by the matching RTS.
+#[derive(Debug, Clone)]
+struct ShadowFrame {
+    /// Value of LB in the called subroutine.
+    lb: StackPointer,
+    /// Address of the BSR instruction that made the call.
+    call_site: CodeAddr,
+    /// Return address saved at LB - 1.
+    return_addr: DataType,
+    /// LB of the caller saved at LB.
+    saved_lb: DataType,
+    /// Address of the last instruction that wrote to LB - 1 after the call.
+    return_addr_writer: Option<CodeAddr>,
+    /// Address of the last instruction that wrote to LB after the call.
+    saved_lb_writer: Option<CodeAddr>,
+}
+
+/// Copy of the frames pushed by BSR, to check they are intact at the matching RTS.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowStack {
+    /// Active frames, the innermost last. LB strictly grows with the frames.
+    frames: Vec<ShadowFrame>,
+}
+
+impl ShadowStack {
+    /// Creates a shadow stack with no active frames.
+    pub fn new() -> ShadowStack {
+        ShadowStack::default()
+    }
+
+    /// Number of active frames.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Drop the frames above the given LB, left without a RTS.
+    fn drop_above(&mut self, lb: StackPointer) {
+        while let Some(frame) = self.frames.last() {
+            if frame.lb <= lb {
+                break;
+            }
+            self.frames.pop();
+        }
+    }
+
+    /// Find the frame slot at the given address, if any.
+    fn slot_mut(&mut self, at: StackPointer) -> Option<(&mut ShadowFrame, FrameSlot)> {
+        // the return address is right below LB, so a frame at LB = at + 1 may own it
+        let index = self.frames.partition_point(|frame| frame.lb < at);
+        match self.frames.get(index) {
+            Some(frame) if frame.lb == at => Some((&mut self.frames[index], FrameSlot::SavedLb)),
+            Some(frame) if frame.lb.offset(-1) == Some(at) => Some((&mut self.frames[index], FrameSlot::ReturnAddress)),
+            _ => None,
+        }
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Get the stack address the instruction is about to write to, if any.
+    fn written_stack_addr(&self, op: &Op) -> Option<StackPointer> {
+        match op {
+            Op::STORE(_, address) => match self.get_dadr(*address) {
+                Ok(Pointer::Stack(ptr)) => Some(ptr),
+                _ => None,
+            },
+            Op::PUSH(_) | Op::PEA(_) => self.sp.offset(1),
+            _ => None,
+        }
+    }
+
+    /// Before the execution of an instruction, remember it if it overwrites a frame,
+    /// and on RTS, check the frame is the one saved by the matching BSR.
+    pub(super) fn check_frame(&mut self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        if let Some(at) = self.written_stack_addr(op) {
+            match self.shadow_stack.slot_mut(at) {
+                Some((frame, FrameSlot::ReturnAddress)) => frame.return_addr_writer = Some(pc),
+                Some((frame, FrameSlot::SavedLb)) => frame.saved_lb_writer = Some(pc),
+                None => {},
+            }
+        }
+        if !matches!(op, Op::RTS) {
+            return Ok(());
+        }
+        let frame = match self.shadow_stack.frames.last() {
+            Some(frame) if frame.lb == self.lb => frame,
+            _ => return Ok(()),
+        };
+        let slots = [
+            (FrameSlot::ReturnAddress, frame.lb.offset(-1), frame.return_addr, frame.return_addr_writer),
+            (FrameSlot::SavedLb, Some(frame.lb), frame.saved_lb, frame.saved_lb_writer),
+        ];
+        for (slot, at, expected, writer) in slots {
+            let found = match at.and_then(|at| self.memory.get_stack(at)) {
+                Some(found) => found,
+                None => continue,
+            };
+            if found != expected {
+                let corruption = FrameCorruption {
+                    slot,
+                    callee: self.callee(frame.call_site),
+                    call_site: self.code.source_line(frame.call_site),
+                    expected,
+                    found,
+                    written_at: writer.and_then(|writer| self.code.source_line(writer)),
+                };
+                return Err(self.execution_error(ImaExecutionError::CorruptedFrame(Box::new(corruption)), pc));
+            }
+        }
+        Ok(())
+    }
+
+    /// After the execution of an instruction, push the frames saved by BSR and pop them on RTS.
+    pub(super) fn track_frames(&mut self, op: &Op, pc: CodeAddr) {
+        match op {
+            Op::BSR(_) => {
+                let return_addr = self.lb.offset(-1).and_then(|at| self.memory.get_stack(at));
+                let saved_lb = self.memory.get_stack(self.lb);
+                if let (Some(return_addr), Some(saved_lb)) = (return_addr, saved_lb) {
+                    self.shadow_stack.drop_above(self.lb.offset(-1).unwrap_or(self.lb));
+                    self.shadow_stack.frames.push(ShadowFrame {
+                        lb: self.lb,
+                        call_site: pc,
+                        return_addr,
+                        saved_lb,
+                        return_addr_writer: None,
+                        saved_lb_writer: None,
+                    });
+                }
+            },
+            // frames left without RTS, if any, are dropped with the returning one
+            Op::RTS => self.shadow_stack.drop_above(self.lb),
+            _ => {},
+        }
+    }
+}
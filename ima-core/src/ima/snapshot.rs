@@ -0,0 +1,201 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    IMA,
+    control_flow::ImaControlFlow,
+    observer::ImaObserver,
+    rounding::RoundingMode,
+    zones::{
+        flags::Flags,
+        memory::{MemoryState, StackPointer},
+        program::{CodeAddr, Program},
+        registers::Registers,
+    },
+};
+
+/// Version of the snapshot format, written in every snapshot.
+/// It must be increased whenever the format changes: older snapshots are then rejected.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The snapshot could not be read or written.
+    Io(std::io::Error),
+    /// The snapshot is not valid JSON, or misses some of the machine state.
+    Format(serde_json::Error),
+    /// The snapshot was written with another version of the format.
+    UnsupportedVersion(u32),
+    /// The snapshot was taken while running another program.
+    ProgramMismatch,
+    /// The snapshot was taken on a machine with another number of registers, stack size, heap size or heap allocator.
+    ConfigurationMismatch {
+        /// The setting that differs: "registers", "stack words", "heap words" or "allocator".
+        setting: &'static str,
+        snapshot: String,
+        machine: String,
+    },
+    /// The snapshot holds a state the machine can't be in, with the reason:
+    /// a pointer out of the stack, a program counter past the program, or inconsistent heap blocks.
+    InvalidState(&'static str),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Unable to read or write the snapshot ({e})"),
+            SnapshotError::Format(e) => write!(f, "Invalid snapshot ({e})"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "Unsupported snapshot version {} (expected {})", version, SNAPSHOT_VERSION),
+            SnapshotError::ProgramMismatch => write!(f, "The snapshot was taken on another program"),
+            SnapshotError::ConfigurationMismatch { setting, snapshot, machine } => {
+                write!(f, "The snapshot was taken with {} {}, but the machine has {}", snapshot, setting, machine)
+            },
+            SnapshotError::InvalidState(reason) => write!(f, "Invalid machine state in the snapshot: {}", reason),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// Full state of the machine, to be saved and restored later on the same program.
+/// The warnings, the checkers and the profiler are not part of it: they start over on restore.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Version of the format, see `SNAPSHOT_VERSION`.
+    pub version: u32,
+    /// Fingerprint of the instructions of the program the snapshot was taken on.
+    pub program: u64,
+    pub registers: Registers,
+    pub flags: Flags,
+    pub memory: MemoryState,
+    pub gb: StackPointer,
+    pub lb: StackPointer,
+    pub sp: StackPointer,
+    pub pc: CodeAddr,
+    pub control_flow: ImaControlFlow,
+    pub cycle_count: usize,
+    pub instruction_count: usize,
+    pub output_bytes: usize,
+    pub rounding_mode: RoundingMode,
+    /// Real time elapsed since the start of the machine.
+    pub elapsed: Duration,
+}
+
+/// Only the version is read first, so that any other format change gives a clear error.
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    version: u32,
+}
+
+impl Snapshot {
+    /// Write the snapshot as JSON.
+    pub fn write(&self, output: impl Write) -> Result<(), SnapshotError> {
+        serde_json::to_writer(output, self).map_err(SnapshotError::Format)
+    }
+
+    /// Read a snapshot written by `Snapshot::write`, checking its version.
+    pub fn read(mut input: impl Read) -> Result<Snapshot, SnapshotError> {
+        let mut content = String::new();
+        input.read_to_string(&mut content).map_err(SnapshotError::Io)?;
+        let header: SnapshotHeader = serde_json::from_str(&content).map_err(SnapshotError::Format)?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.version));
+        }
+        serde_json::from_str(&content).map_err(SnapshotError::Format)
+    }
+}
+
+/// Fingerprint of the instructions of the program, stable across runs and builds (FNV-1a).
+pub fn program_fingerprint(program: &Program) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for instruction in program.instructions() {
+        for byte in format!("{}\n", instruction).bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Take a snapshot of the full state of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            program: program_fingerprint(&self.code),
+            registers: self.registers.clone(),
+            flags: self.flags.clone(),
+            memory: self.memory.state(),
+            gb: self.gb,
+            lb: self.lb,
+            sp: self.sp,
+            pc: self.code.pc(),
+            control_flow: self.control_flow,
+            cycle_count: self.cycle_count,
+            instruction_count: self.instruction_count,
+            output_bytes: self.output_bytes,
+            rounding_mode: self.rounding_mode,
+            elapsed: self.ima_start_time.elapsed(),
+        }
+    }
+
+    /// Restore the machine to the state of the snapshot.
+    /// Fails if the snapshot was taken on another program, with another format version,
+    /// or with another number of registers, stack size, heap size or heap allocator.
+    /// Snapshots can come from anywhere, so the state is checked before anything is restored.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        if snapshot.program != program_fingerprint(&self.code) {
+            return Err(SnapshotError::ProgramMismatch);
+        }
+        // the checkers are configured for the sizes of the machine
+        let settings = [
+            ("registers", snapshot.registers.count().to_string(), self.registers.count().to_string()),
+            ("stack words", snapshot.memory.stack.len().to_string(), self.memory.stack_size().to_string()),
+            ("heap words", snapshot.memory.heap.len().to_string(), self.memory.heap_size().to_string()),
+            ("allocator", snapshot.memory.allocator_kind.to_string(), self.memory.allocator_kind().to_string()),
+        ];
+        if let Some((setting, snapshot, machine)) = settings.into_iter().find(|(_, snapshot, machine)| snapshot != machine) {
+            return Err(SnapshotError::ConfigurationMismatch { setting, snapshot, machine });
+        }
+        let stack_size = self.memory.stack_size();
+        if [snapshot.gb, snapshot.lb, snapshot.sp].iter().any(|pointer| pointer.as_index() >= stack_size) {
+            return Err(SnapshotError::InvalidState("GB, LB or SP is out of the stack"));
+        }
+        if snapshot.pc as usize > self.code.instructions().len() {
+            return Err(SnapshotError::InvalidState("the program counter is past the program"));
+        }
+        if snapshot.memory.allocator.kind() != snapshot.memory.allocator_kind {
+            return Err(SnapshotError::InvalidState("the allocator does not match its kind"));
+        }
+        if !snapshot.memory.allocator.is_consistent(&snapshot.memory.heap) {
+            return Err(SnapshotError::InvalidState("the heap blocks are inconsistent"));
+        }
+        // the checkers start over from the restored state
+        self.reset();
+        self.registers = snapshot.registers;
+        self.flags = snapshot.flags;
+        self.memory.restore(snapshot.memory);
+        self.gb = snapshot.gb;
+        self.lb = snapshot.lb;
+        self.sp = snapshot.sp;
+        self.code.set_pc(snapshot.pc);
+        self.control_flow = snapshot.control_flow;
+        self.cycle_count = snapshot.cycle_count;
+        self.instruction_count = snapshot.instruction_count;
+        self.output_bytes = snapshot.output_bytes;
+        self.rounding_mode = snapshot.rounding_mode;
+        self.ima_start_time = Instant::now().checked_sub(snapshot.elapsed).unwrap_or_else(Instant::now);
+        Ok(())
+    }
+}
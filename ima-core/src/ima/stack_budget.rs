@@ -0,0 +1,107 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use super::{
+    IMA,
+    decoded::Op,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    warning::{CheckMode, WarningKind},
+    zones::{
+        memory::StackPointer,
+        program::CodeAddr,
+    },
+};
+
+/// Stack budget of a frame, declared by its most recent TSTO.
+#[derive(Debug, Clone, Copy)]
+struct FrameBudget {
+    /// Highest stack pointer allowed in the frame, and the size declared by the TSTO.
+    /// None if the frame did not execute any TSTO yet.
+    limit: Option<(StackPointer, u32)>,
+    /// Stack pointer when the frame was entered: without TSTO, the stack can't grow past it.
+    base: StackPointer,
+}
+
+/// Remembers the TSTO budget of each active frame, to check the stack never grows past it.
+/// Frames are entered with BSR and left with RTS.
+#[derive(Debug, Clone)]
+pub struct StackBudgetChecker {
+    /// Whether going past the budget is a warning or an error.
+    mode: CheckMode,
+    /// Budgets of the active frames, the main program being the first one.
+    frames: Vec<FrameBudget>,
+}
+
+impl StackBudgetChecker {
+    /// Creates a checker for a machine with only the main program frame.
+    pub fn new(mode: CheckMode) -> StackBudgetChecker {
+        StackBudgetChecker {
+            mode,
+            frames: vec![FrameBudget { limit: None, base: StackPointer::zero() }],
+        }
+    }
+
+    /// Forget all the frames, going back to the main program frame.
+    pub fn reset(&mut self) {
+        *self = StackBudgetChecker::new(self.mode);
+    }
+
+    /// Budget of the current frame.
+    fn current(&mut self) -> &mut FrameBudget {
+        // the main program frame is never popped
+        self.frames.last_mut().unwrap()
+    }
+}
+
+/// Number of words the instruction pushes on the stack, for the ones that grow it.
+fn stack_growth(op: &Op) -> Option<u32> {
+    match op {
+        Op::PUSH(_) | Op::PEA(_) => Some(1),
+        Op::ADDSP(value) => Some(*value),
+        Op::BSR(_) => Some(2),
+        _ => None,
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Before the execution of an instruction growing the stack,
+    /// check it stays in the budget declared by the last TSTO of the frame.
+    pub(super) fn check_stack_budget(&mut self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        let (mode, frame, growth) = match (self.stack_budget_checker.as_mut(), stack_growth(op)) {
+            (Some(checker), Some(growth)) => (checker.mode, *checker.current(), growth),
+            _ => return Ok(()),
+        };
+        let limit = frame.limit.map(|(limit, _)| limit).unwrap_or(frame.base);
+        match self.sp.offset(growth as i32) {
+            Some(sp) if sp <= limit => Ok(()),
+            _ => {
+                let declared = frame.limit.map(|(_, declared)| declared);
+                self.report(
+                    mode,
+                    WarningKind::StackBudgetExceeded { declared },
+                    ImaExecutionError::StackBudgetExceeded { declared },
+                    pc,
+                )
+            },
+        }
+    }
+
+    /// After the execution of an instruction, keep track of the frames and their budgets.
+    pub(super) fn track_stack_budget(&mut self, op: &Op) {
+        let sp = self.sp;
+        let checker = match self.stack_budget_checker.as_mut() {
+            Some(checker) => checker,
+            None => return,
+        };
+        match op {
+            Op::TSTO(value) => {
+                checker.current().limit = sp.offset(*value as i32).map(|limit| (limit, *value));
+            },
+            Op::BSR(_) => checker.frames.push(FrameBudget { limit: None, base: sp }),
+            Op::RTS if checker.frames.len() > 1 => {
+                checker.frames.pop();
+            },
+            _ => {},
+        }
+    }
+}
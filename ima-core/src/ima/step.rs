@@ -0,0 +1,212 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::rc::Rc;
+
+use super::{
+    IMA,
+    control_flow::{ImaControlFlow, ImaExitStatus},
+    decoded::{DecodedInstruction, Op},
+    error::{ImaError, ImaExecutionError},
+    io::{ImaInput, ImaIo},
+    observer::ImaObserver,
+    zones::program::CodeAddr,
+};
+
+/// What happened when the machine executed a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed, and the machine can continue.
+    Continued,
+    /// The machine stopped on its own, with a HALT or an ERROR instruction.
+    Halted(ImaExitStatus),
+    /// The instruction was executed, and the next one has a breakpoint.
+    Breakpoint,
+    /// The instruction was executed, and it accessed a watched register or memory word.
+    /// The watchpoints that fired are given by `IMA::watchpoints`.
+    Watchpoint,
+    /// The next instruction reads input, but there is none left.
+    /// Nothing was executed: the caller can supply more input and step again.
+    NeedsInput,
+}
+
+/// Input read before the execution of the instruction that needs it,
+/// so the instruction is not started when the input is pending.
+enum ReadAhead {
+    Int(ImaInput<i32>),
+    Float(ImaInput<f32>),
+    Char(ImaInput<i32>),
+}
+
+/// The I/O of the machine during a single instruction.
+/// It serves the input read ahead, and counts the output, dropping anything past the limit:
+/// this way, a runaway program can't write more than the limit, even in a single instruction.
+/// When the observer is active, it also keeps a copy of the output for it.
+struct StepIo<'a, IO: ImaIo> {
+    io: &'a mut IO,
+    read_ahead: Option<ReadAhead>,
+    written: usize,
+    limit: Option<usize>,
+    copy: Option<Vec<u8>>,
+}
+
+impl<'a, IO: ImaIo> ImaIo for StepIo<'a, IO> {
+    fn read_int(&mut self) -> std::io::Result<ImaInput<i32>> {
+        match self.read_ahead.take() {
+            Some(ReadAhead::Int(input)) => Ok(input),
+            _ => self.io.read_int(),
+        }
+    }
+
+    fn read_float(&mut self) -> std::io::Result<ImaInput<f32>> {
+        match self.read_ahead.take() {
+            Some(ReadAhead::Float(input)) => Ok(input),
+            _ => self.io.read_float(),
+        }
+    }
+
+    fn read_char(&mut self) -> std::io::Result<ImaInput<i32>> {
+        match self.read_ahead.take() {
+            Some(ReadAhead::Char(input)) => Ok(input),
+            _ => self.io.read_char(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if let Some(copy) = self.copy.as_mut() {
+            copy.extend_from_slice(bytes);
+        }
+        let allowed = match self.limit {
+            Some(limit) => limit.saturating_sub(self.written).min(bytes.len()),
+            None => bytes.len(),
+        };
+        self.io.write(&bytes[..allowed])?;
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.io.flush()
+    }
+}
+
+/// Read the input of the instruction, if it needs some.
+fn read_ahead<IO: ImaIo>(op: &Op, io: &mut IO) -> std::io::Result<Option<ReadAhead>> {
+    Ok(match op {
+        Op::RINT => Some(ReadAhead::Int(io.read_int()?)),
+        Op::RFLOAT => Some(ReadAhead::Float(io.read_float()?)),
+        Op::RUTF8 => Some(ReadAhead::Char(io.read_char()?)),
+        _ => None,
+    })
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Execute the next instruction of the machine.
+    /// If the machine already stopped, nothing is executed and it stays halted.
+    pub fn step<IO: ImaIo>(&mut self, io: &mut IO) -> Result<StepOutcome, ImaError> {
+        match self.stopped() {
+            Some(outcome) => Ok(outcome),
+            None => self.execute_step(io),
+        }
+    }
+
+    /// Execute at most the given number of instructions.
+    /// Stops early and returns the outcome of the last step if it did not continue.
+    pub fn run_for<IO: ImaIo>(&mut self, steps: usize, io: &mut IO) -> Result<StepOutcome, ImaError> {
+        for _ in 0..steps {
+            match self.step(io)? {
+                StepOutcome::Continued => (),
+                outcome => return Ok(outcome),
+            }
+        }
+        Ok(self.stopped().unwrap_or(StepOutcome::Continued))
+    }
+
+    /// Execute instructions until the predicate holds on the machine, checked after each step.
+    /// Stops early and returns the outcome of the last step if it did not continue.
+    pub fn run_until<IO: ImaIo>(
+        &mut self,
+        io: &mut IO,
+        mut predicate: impl FnMut(&IMA<O>) -> bool,
+    ) -> Result<StepOutcome, ImaError> {
+        loop {
+            match self.step(io)? {
+                StepOutcome::Continued if predicate(self) => break Ok(StepOutcome::Continued),
+                StepOutcome::Continued => (),
+                outcome => break Ok(outcome),
+            }
+        }
+    }
+
+    /// The outcome of the machine if it already stopped on its own.
+    fn stopped(&self) -> Option<StepOutcome> {
+        match self.control_flow {
+            ImaControlFlow::Continue => None,
+            ImaControlFlow::Halt => Some(StepOutcome::Halted(ImaExitStatus::Halted)),
+            ImaControlFlow::Error => Some(StepOutcome::Halted(ImaExitStatus::ErrorInstruction)),
+        }
+    }
+
+    /// Fetch, execute and check the next instruction.
+    /// If it needs input that is pending, nothing is executed.
+    pub(super) fn execute_step<IO: ImaIo>(&mut self, io: &mut IO) -> Result<StepOutcome, ImaError> {
+        let pc = self.code.pc();
+        // the decoded program is shared, so the instruction is borrowed and never cloned
+        let program = Rc::clone(&self.decoded);
+        let decoded = match program.get(pc) {
+            Some(decoded) => decoded,
+            None => return Err(ImaError::NoMoreInstructions),
+        };
+        let read_ahead = match read_ahead(&decoded.op, io) {
+            Ok(Some(ReadAhead::Int(ImaInput::Pending) | ReadAhead::Float(ImaInput::Pending) | ReadAhead::Char(ImaInput::Pending))) => {
+                return Ok(StepOutcome::NeedsInput);
+            },
+            Ok(read_ahead) => read_ahead,
+            Err(e) => return Err(self.execution_error(ImaExecutionError::FailedToReadInput(e), pc)),
+        };
+
+        self.watchpoints.clear_hits();
+        let undo = self.begin_undo(&decoded.op);
+        let result = self.execute_decoded(decoded, pc, read_ahead, io);
+        self.end_undo(undo);
+        result?;
+
+        match self.stopped() {
+            Some(outcome) => Ok(outcome),
+            None if !self.watchpoints.hits().is_empty() => Ok(StepOutcome::Watchpoint),
+            None if self.code.is_breakpoint(self.code.pc()) => Ok(StepOutcome::Breakpoint),
+            None => Ok(StepOutcome::Continued),
+        }
+    }
+
+    /// Execute and check the decoded instruction at the given address, with the input it needs read ahead.
+    fn execute_decoded<IO: ImaIo>(
+        &mut self,
+        decoded: &DecodedInstruction,
+        pc: CodeAddr,
+        read_ahead: Option<ReadAhead>,
+        io: &mut IO,
+    ) -> Result<(), ImaError> {
+        let op = &decoded.op;
+        let cycles = self.cycle_count;
+        // a checker error leaves the pc on the instruction
+        let snapshot = self.before_execute(op, pc)?;
+        let watched = self.watch_before(op);
+        self.code.increment_pc();
+
+        let mut step_io = StepIo {
+            io,
+            read_ahead,
+            written: self.output_bytes,
+            limit: self.limits.max_output_bytes,
+            copy: self.observer.is_active().then(Vec::new),
+        };
+        let result = self.execute(decoded, &mut step_io);
+        self.output_bytes = step_io.written;
+        if let Some(copy) = step_io.copy.filter(|copy| !copy.is_empty()) {
+            self.observer.output(&copy);
+        }
+        result.map_err(|e| self.execution_error(e, pc))?;
+
+        self.after_execute(op, pc, cycles, snapshot, watched)
+    }
+}
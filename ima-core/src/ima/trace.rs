@@ -0,0 +1,255 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::io::Write;
+
+use crate::{
+    instructions::Instruction,
+    parser::label::Label,
+};
+
+use super::{
+    IMA,
+    address_modes::RegisterIndex,
+    data_type::DataType,
+    observer::ImaObserver,
+    zones::{
+        flags::Flags,
+        memory::{Pointer, StackPointer},
+        program::{CodeAddr, SourceLocation},
+        registers::Registers,
+    },
+};
+
+/// Selects the instructions to trace.
+/// An instruction is traced if it matches any of the filters, or if there are no filters at all.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// Ranges of source lines to trace, both ends included.
+    pub lines: Vec<(u32, u32)>,
+    /// Subroutines to trace: an instruction belongs to the last label before it.
+    pub labels: Vec<Label>,
+}
+
+impl TraceFilter {
+    /// Returns true if the instruction at the given location should be traced.
+    pub fn matches(&self, location: &SourceLocation) -> bool {
+        if self.lines.is_empty() && self.labels.is_empty() {
+            return true;
+        }
+        self.lines.iter().any(|(start, end)| (*start..=*end).contains(&location.line))
+            || location.label.as_ref().is_some_and(|label| self.labels.contains(label))
+    }
+}
+
+/// State of the machine before the execution of a traced instruction.
+pub struct TraceSnapshot {
+    registers: Registers,
+    flags: Flags,
+    gb: StackPointer,
+    lb: StackPointer,
+    sp: StackPointer,
+}
+
+/// A single executed instruction, with everything it changed.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    /// Index of the instruction in the run, starting at 0.
+    pub step: usize,
+    /// Address of the instruction.
+    pub pc: CodeAddr,
+    /// Source location of the instruction, if it is known.
+    pub location: Option<SourceLocation>,
+    /// The executed instruction.
+    pub instruction: Instruction,
+    /// Cycles spent on the instruction.
+    pub cycles: usize,
+    /// Registers changed by the instruction, with their new values.
+    pub registers: Vec<(String, DataType)>,
+    /// Flags changed by the instruction, with their new values.
+    pub flags: Vec<(&'static str, bool)>,
+    /// Memory writes made by the instruction, in order.
+    pub memory: Vec<(Pointer, DataType)>,
+    /// Total number of bytes written to the output after the instruction.
+    pub output_bytes: usize,
+}
+
+/// Escape a string to a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Convert a value to JSON: numbers for integers and finite floats, strings for everything else.
+fn json_value(value: &DataType) -> String {
+    match value {
+        DataType::Int(i) => i.to_string(),
+        // debug format always has a decimal point, to tell floats from ints
+        DataType::Float(f) if f.is_finite() => format!("{:?}", f),
+        other => json_string(&other.to_string()),
+    }
+}
+
+impl TraceEntry {
+    /// Convert the entry to a single line JSON object.
+    pub fn to_json(&self) -> String {
+        let line = match &self.location {
+            Some(location) => location.line.to_string(),
+            None => "null".to_string(),
+        };
+        let label = match self.location.as_ref().and_then(|location| location.label.as_ref()) {
+            Some(label) => json_string(&label.0),
+            None => "null".to_string(),
+        };
+        let registers = self.registers.iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), json_value(value)))
+            .collect::<Vec<_>>();
+        let flags = self.flags.iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), value))
+            .collect::<Vec<_>>();
+        let memory = self.memory.iter()
+            .map(|(at, value)| format!("{{\"addr\":{},\"value\":{}}}", json_string(&at.to_string()), json_value(value)))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"step\":{},\"pc\":{},\"line\":{},\"label\":{},\"instruction\":{},\"cycles\":{},\"registers\":{{{}}},\"flags\":{{{}}},\"memory\":[{}],\"output_bytes\":{}}}",
+            self.step,
+            self.pc,
+            line,
+            label,
+            json_string(&self.instruction.to_string()),
+            self.cycles,
+            registers.join(","),
+            flags.join(","),
+            memory.join(","),
+            self.output_bytes,
+        )
+    }
+}
+
+/// Where the trace entries go.
+pub enum TraceOutput {
+    /// Write the entries as JSON Lines.
+    Json(Box<dyn Write>),
+    /// Keep the entries in memory.
+    Record(Vec<TraceEntry>),
+}
+
+/// Traces the executed instructions matching a filter.
+pub struct Tracer {
+    output: TraceOutput,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    /// Creates a tracer sending the entries to the given output.
+    pub fn new(output: TraceOutput, filter: TraceFilter) -> Tracer {
+        Tracer {
+            output,
+            filter,
+        }
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Trace all the instructions matching the filter to the given output, as JSON Lines.
+    pub fn set_tracer<W: Write + 'static>(&mut self, output: W, filter: TraceFilter) {
+        self.tracer = Some(Tracer::new(TraceOutput::Json(Box::new(output)), filter));
+    }
+
+    /// Keep the trace of all the instructions matching the filter in memory.
+    /// The entries can be retrieved with `take_trace`.
+    pub fn record_trace(&mut self, filter: TraceFilter) {
+        self.tracer = Some(Tracer::new(TraceOutput::Record(Vec::new()), filter));
+    }
+
+    /// Take the trace entries recorded so far. Empty if the trace is not recorded in memory.
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        match self.tracer.as_mut().map(|tracer| &mut tracer.output) {
+            Some(TraceOutput::Record(entries)) => std::mem::take(entries),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Take a snapshot of the machine before executing the instruction at the given address,
+    /// if it is traced. This also starts logging the memory writes.
+    pub(super) fn trace_snapshot(&mut self, pc: CodeAddr) -> Option<TraceSnapshot> {
+        let location = self.code.location(pc)?;
+        if !self.tracer.as_ref()?.filter.matches(&location) {
+            return None;
+        }
+        self.memory.start_write_log();
+        Some(TraceSnapshot {
+            registers: self.registers.clone(),
+            flags: self.flags.clone(),
+            gb: self.gb,
+            lb: self.lb,
+            sp: self.sp,
+        })
+    }
+
+    /// Build the trace entry of the instruction at the given address, by comparing the machine with the snapshot.
+    /// `writes` are the memory writes logged since the snapshot.
+    fn trace_entry(&self, snapshot: TraceSnapshot, pc: CodeAddr, cycles: usize, writes: &[(Pointer, DataType)]) -> TraceEntry {
+        let mut registers = Vec::new();
+        for (name, before, after) in [("GB", snapshot.gb, self.gb), ("LB", snapshot.lb, self.lb), ("SP", snapshot.sp, self.sp)] {
+            if before != after {
+                registers.push((name.to_string(), DataType::MemAddr(Pointer::Stack(after))));
+            }
+        }
+        for i in 0..self.registers.count() {
+            let index = RegisterIndex(i as u8);
+            let after = self.registers.get(index);
+            if snapshot.registers.get(index) != after {
+                registers.push((format!("R{}", i), after));
+            }
+        }
+        let flags = snapshot.flags.values().into_iter()
+            .zip(self.flags.values())
+            .filter(|(before, after)| before.1 != after.1)
+            .map(|(_, after)| after)
+            .collect();
+        TraceEntry {
+            // the instruction count was already incremented
+            step: self.instruction_count - 1,
+            pc,
+            location: self.code.location(pc),
+            instruction: self.instruction_at(pc),
+            cycles,
+            registers,
+            flags,
+            memory: writes.to_vec(),
+            output_bytes: self.output_bytes,
+        }
+    }
+
+    /// Write the trace entry of the instruction that was just executed.
+    pub(super) fn write_trace(
+        &mut self,
+        snapshot: TraceSnapshot,
+        pc: CodeAddr,
+        cycles: usize,
+        writes: &[(Pointer, DataType)],
+    ) -> std::io::Result<()> {
+        let entry = self.trace_entry(snapshot, pc, cycles, writes);
+        match self.tracer.as_mut().map(|tracer| &mut tracer.output) {
+            Some(TraceOutput::Json(output)) => writeln!(output, "{}", entry.to_json()),
+            Some(TraceOutput::Record(entries)) => {
+                entries.push(entry);
+                Ok(())
+            },
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{collections::HashMap, fmt::Display};
+
+use super::{
+    IMA,
+    control_flow::ImaExitStatus,
+    data_type::DataType,
+    error::ImaError,
+    options::ImaOptions,
+    trace::{TraceEntry, TraceFilter},
+    zones::{
+        memory::{Pointer, StackPointer},
+        program::Program,
+    },
+};
+
+/// A complete run of a program, with the trace of every executed instruction.
+pub struct TracedRun {
+    /// All the executed instructions, in order.
+    pub entries: Vec<TraceEntry>,
+    /// Everything the program wrote to the output.
+    pub output: Vec<u8>,
+    /// How the run ended.
+    pub result: Result<ImaExitStatus, ImaError>,
+}
+
+impl TracedRun {
+    /// Run the program to completion with the given input, recording the trace.
+    pub fn new(program: Program, options: ImaOptions, input: &[u8]) -> TracedRun {
+        let mut ima = IMA::new(program, options);
+        ima.record_trace(TraceFilter::default());
+        let mut input = std::io::Cursor::new(input);
+        let mut output = Vec::new();
+        let result = ima.run(&mut input, &mut output);
+        TracedRun {
+            entries: ima.take_trace(),
+            output,
+            result,
+        }
+    }
+
+    /// Description of how the run ended.
+    fn end(&self) -> String {
+        match &self.result {
+            Ok(ImaExitStatus::Halted) => "halted".to_string(),
+            Ok(ImaExitStatus::ErrorInstruction) => "stopped on ERROR".to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    /// Bytes written to the output up to the given step, included.
+    fn output_until(&self, step: usize) -> &[u8] {
+        match self.entries.get(step) {
+            Some(entry) => &self.output[..entry.output_bytes.min(self.output.len())],
+            None => &self.output,
+        }
+    }
+
+    /// Bytes written to the output by the instruction at the given step.
+    fn output_at(&self, step: usize) -> &[u8] {
+        match step {
+            0 => self.output_until(step),
+            _ => &self.output_until(step)[self.output_until(step - 1).len()..],
+        }
+    }
+}
+
+/// Visible state of a machine, rebuilt from the trace: registers and stack contents.
+#[derive(Default)]
+struct VisibleState {
+    registers: HashMap<String, DataType>,
+    stack: HashMap<StackPointer, DataType>,
+}
+
+impl VisibleState {
+    /// Apply the changes of a trace entry to the state.
+    fn apply(&mut self, entry: &TraceEntry) {
+        for (name, value) in &entry.registers {
+            self.registers.insert(name.clone(), *value);
+        }
+        for (at, value) in &entry.memory {
+            if let Pointer::Stack(at) = at {
+                self.stack.insert(*at, *value);
+            }
+        }
+    }
+
+    /// Get the value of a register, undefined if it was never written.
+    fn register(&self, name: &str) -> DataType {
+        self.registers.get(name).copied().unwrap_or(DataType::Undefined)
+    }
+
+    /// Get the value of a stack word, undefined if it was never written.
+    fn stack(&self, at: StackPointer) -> DataType {
+        self.stack.get(&at).copied().unwrap_or(DataType::Undefined)
+    }
+}
+
+/// A difference in the visible state of the two runs.
+#[derive(Debug, Clone)]
+pub enum StateDifference {
+    /// The instruction wrote different output.
+    Output {
+        first: String,
+        second: String,
+    },
+    /// A register has different values.
+    Register {
+        name: String,
+        first: DataType,
+        second: DataType,
+    },
+    /// A word of the stack has different values.
+    Stack {
+        at: StackPointer,
+        first: DataType,
+        second: DataType,
+    },
+    /// One of the runs stopped, or they stopped differently.
+    End {
+        first: Option<String>,
+        second: Option<String>,
+    },
+}
+
+impl Display for StateDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateDifference::Output { first, second } => write!(f, "output: {:?} / {:?}", first, second),
+            StateDifference::Register { name, first, second } => write!(f, "{}: {} / {}", name, first, second),
+            StateDifference::Stack { at, first, second } => write!(f, "{}: {} / {}", at, first, second),
+            StateDifference::End { first, second } => write!(
+                f,
+                "end: {} / {}",
+                first.as_deref().unwrap_or("running"),
+                second.as_deref().unwrap_or("running"),
+            ),
+        }
+    }
+}
+
+/// First point where two runs diverge.
+#[derive(Debug, Clone)]
+pub struct TraceDivergence {
+    /// Step at which the visible states first differ.
+    pub step: usize,
+    /// The entries of both runs, from a few steps before the divergence to the divergence itself.
+    /// An entry is missing if that run had already stopped.
+    pub context: Vec<(Option<TraceEntry>, Option<TraceEntry>)>,
+    /// All the differences in the visible state after the diverging step.
+    pub differences: Vec<StateDifference>,
+}
+
+/// Format an entry for the side by side display.
+fn entry_column(entry: &Option<TraceEntry>) -> String {
+    match entry {
+        Some(entry) => match &entry.location {
+            Some(location) => format!("{:>5}: {}", location.line, entry.instruction),
+            None => format!("{:>5}: {}", "?", entry.instruction),
+        },
+        None => "-".to_string(),
+    }
+}
+
+impl Display for TraceDivergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Runs diverge at step {}:", self.step)?;
+        let first_step = self.step + 1 - self.context.len();
+        for (i, (first, second)) in self.context.iter().enumerate() {
+            let marker = if first_step + i == self.step { ">" } else { " " };
+            writeln!(f, "{} {:>8} | {:<40} | {}", marker, first_step + i, entry_column(first), entry_column(second))?;
+        }
+        for difference in &self.differences {
+            writeln!(f, "    {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the visible states of both runs after the given step.
+fn differences(
+    first: &TracedRun,
+    first_state: &VisibleState,
+    second: &TracedRun,
+    second_state: &VisibleState,
+    step: usize,
+) -> Vec<StateDifference> {
+    let mut result = Vec::new();
+
+    if first.output_until(step) != second.output_until(step) {
+        result.push(StateDifference::Output {
+            first: String::from_utf8_lossy(first.output_at(step)).to_string(),
+            second: String::from_utf8_lossy(second.output_at(step)).to_string(),
+        });
+    }
+
+    let mut registers = first_state.registers.keys().chain(second_state.registers.keys()).collect::<Vec<_>>();
+    registers.sort();
+    registers.dedup();
+    for name in registers {
+        let (a, b) = (first_state.register(name), second_state.register(name));
+        if a != b {
+            result.push(StateDifference::Register { name: name.clone(), first: a, second: b });
+        }
+    }
+
+    let mut stack = first_state.stack.keys().chain(second_state.stack.keys()).collect::<Vec<_>>();
+    stack.sort();
+    stack.dedup();
+    for at in stack {
+        let (a, b) = (first_state.stack(*at), second_state.stack(*at));
+        if a != b {
+            result.push(StateDifference::Stack { at: *at, first: a, second: b });
+        }
+    }
+
+    let first_end = (step >= first.entries.len()).then(|| first.end());
+    let second_end = (step >= second.entries.len()).then(|| second.end());
+    let same_end = match (&first.result, &second.result) {
+        (Ok(a), Ok(b)) => a == b,
+        (Err(_), Err(_)) => true,
+        _ => false,
+    };
+    if first_end.is_some() != second_end.is_some() || (first_end.is_some() && !same_end) {
+        result.push(StateDifference::End { first: first_end, second: second_end });
+    }
+
+    result
+}
+
+/// Compare two runs step by step, and find the first step after which their visible state differs:
+/// the output written, the registers or the stack contents.
+/// The divergence holds the `context` steps before it. Returns None if the runs are the same.
+pub fn trace_diff(first: &TracedRun, second: &TracedRun, context: usize) -> Option<TraceDivergence> {
+    let mut first_state = VisibleState::default();
+    let mut second_state = VisibleState::default();
+    // one more step than the longest run, to compare how they ended
+    for step in 0..=first.entries.len().max(second.entries.len()) {
+        if let Some(entry) = first.entries.get(step) {
+            first_state.apply(entry);
+        }
+        if let Some(entry) = second.entries.get(step) {
+            second_state.apply(entry);
+        }
+        let differences = differences(first, &first_state, second, &second_state, step);
+        if !differences.is_empty() {
+            let context = (step.saturating_sub(context)..=step)
+                .map(|i| (first.entries.get(i).cloned(), second.entries.get(i).cloned()))
+                .collect();
+            return Some(TraceDivergence { step, context, differences });
+        }
+    }
+    None
+}
@@ -0,0 +1,87 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::fmt::Display;
+
+use super::{
+    IMA,
+    address_modes::{GetDadr, Operand, RegisterIndex},
+    decoded::Op,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    warning::{CheckMode, WarningKind},
+    zones::{
+        memory::Pointer,
+        program::CodeAddr,
+    },
+};
+
+/// Where an operand value was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueSource {
+    Register(RegisterIndex),
+    Memory(Pointer),
+}
+
+impl Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueSource::Register(index) => write!(f, "{}", index),
+            ValueSource::Memory(ptr) => write!(f, "{}", ptr),
+        }
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Get the source of a register operand, if it is undefined.
+    fn undefined_register(&self, index: RegisterIndex) -> Option<ValueSource> {
+        match self.registers.get(index).is_undefined() {
+            true => Some(ValueSource::Register(index)),
+            false => None,
+        }
+    }
+
+    /// Get the source of a value operand, if it is undefined.
+    /// Invalid addresses are ignored here, the instruction will fail on them anyway.
+    fn undefined_value(&self, value: Operand) -> Option<ValueSource> {
+        match value {
+            Operand::Register(index) => self.undefined_register(index),
+            Operand::Memory(address) => {
+                let ptr = self.get_dadr(address).ok()?;
+                match self.memory.get(ptr)?.is_undefined() {
+                    true => Some(ValueSource::Memory(ptr)),
+                    false => None,
+                }
+            },
+            Operand::Immediate(_) => None,
+        }
+    }
+
+    /// Find the first undefined operand of an arithmetic, compare, store or write instruction.
+    /// Moving values around with LOAD, PUSH or POP is always allowed.
+    pub fn undefined_operand(&self, op: &Op) -> Option<ValueSource> {
+        let operands = op.operands();
+        if !operands.computes {
+            return None;
+        }
+        operands.value
+            .and_then(|value| self.undefined_value(value))
+            .or_else(|| operands.registers.iter().find_map(|index| self.undefined_register(*index)))
+    }
+
+    /// Check the operands of the instruction at the given address before its execution,
+    /// according to the uninitialized mode of the machine.
+    pub(super) fn check_uninitialized(&mut self, op: &Op, pc: CodeAddr) -> Result<(), ImaError> {
+        if self.uninitialized_mode == CheckMode::Off {
+            return Ok(());
+        }
+        match self.undefined_operand(op) {
+            Some(source) => self.report(
+                self.uninitialized_mode,
+                WarningKind::UninitializedRead(source),
+                ImaExecutionError::UninitializedRead(source),
+                pc,
+            ),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::instructions::Instruction;
+
+use super::{
+    IMA,
+    callee_saved::ClobberedRegisters,
+    error::{ImaError, ImaExecutionError},
+    observer::ImaObserver,
+    uninitialized::ValueSource,
+    zones::{
+        memory::HeapPointer,
+        program::{CodeAddr, DisplayLine},
+    },
+};
+
+/// What a checker of the machine does when it finds a problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckMode {
+    /// The check is disabled. This is the original behavior.
+    #[default]
+    Off,
+    /// Problems raise a warning.
+    Warn,
+    /// Problems are runtime errors.
+    Strict,
+}
+
+impl FromStr for CheckMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(CheckMode::Off),
+            "warn" => Ok(CheckMode::Warn),
+            "strict" => Ok(CheckMode::Strict),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Anything suspicious the machine noticed, that does not stop the execution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// An undefined value was used as an operand.
+    UninitializedRead(ValueSource),
+    /// A heap block was still allocated when the machine halted.
+    MemoryLeak {
+        block: HeapPointer,
+        size: usize,
+    },
+    /// The stack grew past the budget declared by the last TSTO of the frame.
+    StackBudgetExceeded {
+        declared: Option<u32>,
+    },
+    /// A subroutine returned without restoring its callee-saved registers.
+    RegistersClobbered(ClobberedRegisters),
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarningKind::UninitializedRead(source) => write!(f, "Use of an uninitialized value from {}", source),
+            WarningKind::MemoryLeak { block, size } => write!(f, "Leak of the block {} of size {}, allocated", block, size),
+            WarningKind::StackBudgetExceeded { declared: Some(declared) } => write!(f, "Stack grows past the TSTO #{} of the frame", declared),
+            WarningKind::StackBudgetExceeded { declared: None } => write!(f, "Stack grows without a TSTO in the frame"),
+            WarningKind::RegistersClobbered(clobbered) => write!(f, "{}", clobbered),
+        }
+    }
+}
+
+/// A warning raised by an instruction.
+/// The same warning on the same instruction is only reported once, with the number of occurences.
+#[derive(Debug, Clone)]
+pub struct ImaWarning {
+    /// What went wrong.
+    pub kind: WarningKind,
+    /// Source line of the instruction, if it is known.
+    pub line: Option<u32>,
+    /// The instruction that raised the warning.
+    pub instruction: Instruction,
+    /// Number of times the instruction raised this warning.
+    pub count: usize,
+}
+
+impl Display for ImaWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[Ima Warning]: {}, at {}: {}", self.kind, DisplayLine(self.line), self.instruction)?;
+        if self.count > 1 {
+            write!(f, " ({} times)", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Record a warning raised by the instruction at the given address.
+    pub(super) fn warn(&mut self, kind: WarningKind, pc: CodeAddr) {
+        match self.warning_index.get(&(pc, kind.clone())) {
+            Some(index) => self.warnings[*index].count += 1,
+            None => {
+                self.warning_index.insert((pc, kind.clone()), self.warnings.len());
+                self.warnings.push(ImaWarning {
+                    kind,
+                    line: self.code.source_line(pc),
+                    instruction: self.instruction_at(pc),
+                    count: 1,
+                });
+            },
+        }
+    }
+
+    /// Report a problem found by a checker on the instruction at the given address:
+    /// nothing, a warning or an error, depending on the mode of the checker.
+    pub(super) fn report(
+        &mut self,
+        mode: CheckMode,
+        kind: WarningKind,
+        error: ImaExecutionError,
+        pc: CodeAddr,
+    ) -> Result<(), ImaError> {
+        match mode {
+            CheckMode::Off => Ok(()),
+            CheckMode::Warn => {
+                self.warn(kind, pc);
+                Ok(())
+            },
+            CheckMode::Strict => Err(self.execution_error(error, pc)),
+        }
+    }
+
+    /// Get all the warnings raised so far, in the order they first appeared.
+    pub fn warnings(&self) -> &[ImaWarning] {
+        &self.warnings
+    }
+}
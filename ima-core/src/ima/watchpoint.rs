@@ -0,0 +1,407 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::instructions::Instruction;
+
+use super::{
+    IMA,
+    address_modes::{DADR, GetDadr, RegisterIndex},
+    data_type::{DataType, Float, Int},
+    decoded::Op,
+    observer::ImaObserver,
+    uninitialized::ValueSource,
+    zones::{
+        memory::Pointer,
+        program::{CodeAddr, DisplayLine},
+    },
+};
+
+/// What a watchpoint looks at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchTarget {
+    /// A register, like R5.
+    Register(RegisterIndex),
+    /// A memory word, like 3(GB). The address is computed again before each instruction.
+    Memory(DADR),
+}
+
+impl Display for WatchTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchTarget::Register(index) => write!(f, "{}", index),
+            WatchTarget::Memory(dadr) => write!(f, "{}", dadr),
+        }
+    }
+}
+
+/// Error parsing a watchpoint target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchTargetParseError {
+    from: String,
+}
+
+impl Display for WatchTargetParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid watchpoint: {}, expected a register or an address like 3(GB)", self.from)
+    }
+}
+
+impl std::error::Error for WatchTargetParseError {}
+
+impl std::str::FromStr for WatchTarget {
+    type Err = WatchTargetParseError;
+
+    /// Parse a register name (R5) or an address with the DADR syntax (3(GB), -2(LB), 0(R1)).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(index) = RegisterIndex::from_str(s) {
+            return Ok(WatchTarget::Register(index));
+        }
+        match DADR::from_str(s) {
+            Ok(dadr) => Ok(WatchTarget::Memory(dadr)),
+            Err(_) => Err(WatchTargetParseError { from: s.trim().to_string() }),
+        }
+    }
+}
+
+/// The accesses that fire a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// Fire when an instruction writes the target.
+    Write,
+    /// Fire when an instruction reads or writes the target.
+    ReadWrite,
+}
+
+/// How a watched value is compared to the value of a condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Check if the comparison holds, given how the watched value orders against the value of the condition.
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Comparison::Eq => write!(f, "=="),
+            Comparison::Ne => write!(f, "!="),
+            Comparison::Lt => write!(f, "<"),
+            Comparison::Le => write!(f, "<="),
+            Comparison::Gt => write!(f, ">"),
+            Comparison::Ge => write!(f, ">="),
+        }
+    }
+}
+
+/// A condition on the value of the target, for a watchpoint to fire, like `< 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchCondition {
+    pub comparison: Comparison,
+    /// An integer or a float. Integers and floats are compared as floats.
+    pub value: DataType,
+}
+
+impl WatchCondition {
+    /// Check if the condition holds for the value.
+    /// Values that are not numbers, like addresses or undefined values, never match.
+    pub fn holds(&self, value: DataType) -> bool {
+        let ordering = match (value, self.value) {
+            (DataType::Int(value), DataType::Int(expected)) => Some(value.cmp(&expected)),
+            (DataType::Int(value), DataType::Float(expected)) => (value as Float).partial_cmp(&expected),
+            (DataType::Float(value), DataType::Int(expected)) => value.partial_cmp(&(expected as Float)),
+            (DataType::Float(value), DataType::Float(expected)) => value.partial_cmp(&expected),
+            _ => None,
+        };
+        ordering.is_some_and(|ordering| self.comparison.holds(ordering))
+    }
+}
+
+impl Display for WatchCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.comparison, self.value)
+    }
+}
+
+/// Error parsing a watchpoint condition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchConditionParseError {
+    from: String,
+}
+
+impl Display for WatchConditionParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid watchpoint condition: {}, expected a comparison with a number like < 0", self.from)
+    }
+}
+
+impl std::error::Error for WatchConditionParseError {}
+
+impl std::str::FromStr for WatchCondition {
+    type Err = WatchConditionParseError;
+
+    /// Parse a comparison (==, !=, <, <=, >, >=) followed by an integer or a float, with an optional #.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let error = || WatchConditionParseError { from: s.to_string() };
+        // the two characters comparisons first, as < is a prefix of <=
+        let (comparison, value) = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ]
+            .into_iter()
+            .find_map(|(symbol, comparison)| s.strip_prefix(symbol).map(|value| (comparison, value)))
+            .ok_or_else(error)?;
+        let value = value.trim();
+        let value = value.strip_prefix('#').unwrap_or(value);
+        let value = match value.parse::<Int>() {
+            Ok(value) => DataType::Int(value),
+            Err(_) => DataType::Float(value.parse::<Float>().map_err(|_| error())?),
+        };
+        Ok(WatchCondition { comparison, value })
+    }
+}
+
+/// A register or memory word to stop on when it is accessed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub target: WatchTarget,
+    pub mode: WatchMode,
+    /// Condition on the value read or written, if the watchpoint only fires for some values.
+    pub condition: Option<WatchCondition>,
+}
+
+impl Display for Watchpoint {
+    /// Display the watchpoint with the syntax of the debuggers, like `R5 rw < 0`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target)?;
+        if self.mode == WatchMode::ReadWrite {
+            write!(f, " rw")?;
+        }
+        match &self.condition {
+            Some(condition) => write!(f, " {}", condition),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error parsing a watchpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchpointParseError {
+    Target(WatchTargetParseError),
+    Condition(WatchConditionParseError),
+}
+
+impl Display for WatchpointParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchpointParseError::Target(e) => write!(f, "{}", e),
+            WatchpointParseError::Condition(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WatchpointParseError {}
+
+impl std::str::FromStr for Watchpoint {
+    type Err = WatchpointParseError;
+
+    /// Parse a watchpoint with the syntax of the debuggers: the target,
+    /// then `rw` to also fire on reads, then an optional condition (`R5`, `3(GB) rw`, `R5 < 0`, `-2(LB) rw == 3`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, condition) = match s.find(['=', '!', '<', '>']) {
+            Some(index) => {
+                let condition = s[index..].parse().map_err(WatchpointParseError::Condition)?;
+                (&s[..index], Some(condition))
+            },
+            None => (s, None),
+        };
+        // the mode is the last word, after any spacing
+        let target = target.trim();
+        let (target, mode) = match target.rsplit_once(char::is_whitespace) {
+            Some((target, "rw")) => (target.trim_end(), WatchMode::ReadWrite),
+            _ => (target, WatchMode::Write),
+        };
+        let target = target.parse().map_err(WatchpointParseError::Target)?;
+        Ok(Watchpoint { target, mode, condition })
+    }
+}
+
+/// How the instruction accessed the target of a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAccess {
+    Read,
+    Write,
+}
+
+/// A watchpoint that fired on an instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub target: WatchTarget,
+    pub access: WatchAccess,
+    /// Address of the instruction that accessed the target.
+    pub pc: CodeAddr,
+    /// Source line of the instruction, if it is known.
+    pub line: Option<u32>,
+    pub instruction: Instruction,
+    /// Value before the instruction.
+    pub old: DataType,
+    /// Value after the instruction. Same as the old one for a read.
+    pub new: DataType,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.access {
+            WatchAccess::Read => write!(f, "Watchpoint {} read by {} ({}): {}", self.target, self.instruction, DisplayLine(self.line), self.old),
+            WatchAccess::Write => write!(f, "Watchpoint {} written by {} ({}): {} -> {}", self.target, self.instruction, DisplayLine(self.line), self.old, self.new),
+        }
+    }
+}
+
+/// The watchpoints of the machine, and the ones that fired on the last instruction.
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    points: Vec<Watchpoint>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watchpoints {
+    /// Creates a new set of watchpoints, with none set.
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    /// All the watchpoints, in the order they were set.
+    pub fn points(&self) -> &[Watchpoint] {
+        &self.points
+    }
+
+    /// The watchpoints that fired on the last instruction.
+    pub fn hits(&self) -> &[WatchHit] {
+        &self.hits
+    }
+
+    /// Forget the hits of the last instruction.
+    pub fn clear_hits(&mut self) {
+        self.hits.clear();
+    }
+}
+
+/// A watchpoint resolved before an instruction: where its target is, and what it holds.
+pub(super) struct WatchedValue {
+    index: usize,
+    source: ValueSource,
+    old: DataType,
+    /// True if the instruction reads the target, and the watchpoint fires on reads.
+    read: bool,
+}
+
+impl<O: ImaObserver> IMA<O> {
+    /// Watch a register or memory word. Replaces the mode if the target is already watched.
+    pub fn set_watchpoint(&mut self, target: WatchTarget, mode: WatchMode) {
+        self.add_watchpoint(Watchpoint { target, mode, condition: None });
+    }
+
+    /// Add the watchpoint. Replaces the mode and the condition if its target is already watched.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        match self.watchpoints.points.iter_mut().find(|point| point.target == watchpoint.target) {
+            Some(point) => *point = watchpoint,
+            None => self.watchpoints.points.push(watchpoint),
+        }
+    }
+
+    /// Stop watching the target. Returns false if it was not watched.
+    pub fn remove_watchpoint(&mut self, target: &WatchTarget) -> bool {
+        let count = self.watchpoints.points.len();
+        self.watchpoints.points.retain(|watchpoint| watchpoint.target != *target);
+        self.watchpoints.points.len() != count
+    }
+
+    /// Get the watchpoints of the machine, and the ones that fired on the last instruction.
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    /// Resolve the watchpoints before the execution of the instruction.
+    /// This also starts logging the memory and register writes.
+    pub(super) fn watch_before(&mut self, op: &Op) -> Vec<WatchedValue> {
+        if self.watchpoints.points.is_empty() {
+            return Vec::new();
+        }
+        self.memory.start_write_log();
+        self.registers.start_write_log();
+        let registers = op.operands().registers_read();
+        let reads = self.memory_reads(op);
+        self.watchpoints.points.iter()
+            .enumerate()
+            .filter_map(|(index, watchpoint)| {
+                let (source, old, read) = match &watchpoint.target {
+                    WatchTarget::Register(register) if usize::from(register.0) < self.registers.count() => (
+                        ValueSource::Register(*register),
+                        self.registers.get(*register),
+                        registers.contains(register),
+                    ),
+                    WatchTarget::Register(_) => return None,
+                    // the watched word is not reachable for now, like 0(R1) when R1 is not an address
+                    WatchTarget::Memory(dadr) => {
+                        let ptr = self.get_dadr(dadr.into()).ok()?;
+                        (ValueSource::Memory(ptr), self.memory.get(ptr)?, reads.iter().any(|(at, _)| *at == ptr))
+                    },
+                };
+                Some(WatchedValue { index, source, old, read: read && watchpoint.mode == WatchMode::ReadWrite })
+            })
+            .collect()
+    }
+
+    /// Find the watchpoints the instruction at the given address fired.
+    /// `writes` are the memory writes of the instruction.
+    pub(super) fn watch_after(&mut self, watched: Vec<WatchedValue>, pc: CodeAddr, writes: &[(Pointer, DataType)]) {
+        // an instruction that overflows may leave its register untouched, so only the real writes count
+        let written = self.registers.take_write_log();
+        for value in watched {
+            let new = match value.source {
+                ValueSource::Register(register) => written.contains(&register).then(|| self.registers.get(register)),
+                ValueSource::Memory(ptr) => writes.iter().rev().find(|(at, _)| *at == ptr).map(|(_, new)| *new),
+            };
+            let (access, new) = match new {
+                Some(new) => (WatchAccess::Write, new),
+                None if value.read => (WatchAccess::Read, value.old),
+                None => continue,
+            };
+            if self.watchpoints.points[value.index].condition.is_some_and(|condition| !condition.holds(new)) {
+                continue;
+            }
+            self.watchpoints.hits.push(WatchHit {
+                target: self.watchpoints.points[value.index].target.clone(),
+                access,
+                pc,
+                line: self.code.source_line(pc),
+                instruction: self.instruction_at(pc),
+                old: value.old,
+                new,
+            });
+        }
+    }
+}
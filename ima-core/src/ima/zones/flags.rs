@@ -5,6 +5,8 @@ use super::memory::Pointer;
 
 
 /// All flags the ima machine can have.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Flags {
     /// Equality
     eq: bool,
@@ -51,6 +53,18 @@ impl Flags {
     pub fn le(&self) -> bool { self.le }
     /// Fetch the value of the OV flag.
     pub fn ov(&self) -> bool { self.ov }
+    /// Get all the flags with their names.
+    pub fn values(&self) -> [(&'static str, bool); 7] {
+        [
+            ("EQ", self.eq),
+            ("NE", self.ne),
+            ("GT", self.gt),
+            ("GE", self.ge),
+            ("LT", self.lt),
+            ("LE", self.le),
+            ("OV", self.ov),
+        ]
+    }
 
     /// Set the value of the OV flag.
     pub fn set_ov(&mut self, value: bool) { self.ov = value; }
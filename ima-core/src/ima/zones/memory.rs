@@ -8,9 +8,22 @@ pub mod allocator;
 
 /// Pointer to the stack of the IMA machine.
 /// It is only stored on 31 bits.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u32"))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StackPointer(u32);
 
+impl TryFrom<u32> for StackPointer {
+    type Error = String;
+    /// Only values on 31 bits are stack pointers.
+    fn try_from(value: u32) -> Result<StackPointer, String> {
+        match value & 0x8000_0000 {
+            0 => Ok(StackPointer(value)),
+            _ => Err(format!("{} is too large for a stack pointer", value)),
+        }
+    }
+}
+
 impl StackPointer {
     /// Returns a new stack pointer pointing to the first element of the stack.
     pub fn zero() -> StackPointer {
@@ -43,9 +56,22 @@ impl Display for StackPointer {
 
 /// Pointer to the heap of the IMA machine.
 /// It is only stored on 31 bits.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u32"))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct HeapPointer(u32);
 
+impl TryFrom<u32> for HeapPointer {
+    type Error = String;
+    /// Only values on 31 bits are heap pointers.
+    fn try_from(value: u32) -> Result<HeapPointer, String> {
+        match value & 0x8000_0000 {
+            0 => Ok(HeapPointer(value)),
+            _ => Err(format!("{} is too large for a heap pointer", value)),
+        }
+    }
+}
+
 impl HeapPointer {
     /// Returns a new heap pointer with the given offset.
     /// If this fails, the offset caused an overflow.
@@ -73,7 +99,8 @@ impl Display for HeapPointer {
 
 /// Pointer type of the IMA machine.
 /// The inner types are u32, but the first bit is kept for the type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Pointer {
     Stack(StackPointer),
     Heap(HeapPointer),
@@ -125,6 +152,14 @@ pub struct Memory {
     heap: Vec<Option<DataType>>,
     /// Allocator for the heap.
     allocator: Box<dyn allocator::Allocator>,
+    /// Kind of the allocator, to create a new one when the memory is cleared.
+    allocator_kind: allocator::AllocatorKind,
+    /// Statistics on the use of the heap.
+    allocator_stats: allocator::AllocatorStats,
+    /// Writes made since the logging started, if it is enabled.
+    write_log: Option<Vec<(Pointer, DataType)>>,
+    /// Changes made since the undo logging started, if it is enabled.
+    undo_log: Option<Vec<MemoryChange>>,
 }
 
 #[cfg(feature = "public-ima")]
@@ -135,15 +170,69 @@ pub struct Memory {
     pub heap: Vec<Option<DataType>>,
     /// Allocator for the heap.
     pub allocator: Box<dyn allocator::Allocator>,
+    /// Kind of the allocator, to create a new one when the memory is cleared.
+    pub allocator_kind: allocator::AllocatorKind,
+    /// Statistics on the use of the heap.
+    pub allocator_stats: allocator::AllocatorStats,
+    /// Writes made since the logging started, if it is enabled.
+    pub write_log: Option<Vec<(Pointer, DataType)>>,
+    /// Changes made since the undo logging started, if it is enabled.
+    pub undo_log: Option<Vec<MemoryChange>>,
+}
+
+/// Copy of the whole memory of the machine, with the bookkeeping of the allocator.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct MemoryState {
+    pub stack: Vec<DataType>,
+    pub heap: Vec<Option<DataType>>,
+    pub allocator: allocator::AllocatorState,
+    pub allocator_kind: allocator::AllocatorKind,
+    pub allocator_stats: allocator::AllocatorStats,
+}
+
+/// Copy of the used parts of the memory: the stack up to the stack pointer, and the allocated blocks of the heap.
+#[derive(Clone)]
+pub struct UsedMemory {
+    stack: Vec<DataType>,
+    heap: Vec<(HeapPointer, Vec<Option<DataType>>)>,
+}
+
+/// A change made to the memory, with what was there before, to undo it.
+#[derive(Clone)]
+pub enum MemoryChange {
+    /// A word of the stack, with its previous value.
+    Stack(StackPointer, DataType),
+    /// A word of the heap, with its previous value: None if it was free.
+    Heap(HeapPointer, Option<DataType>),
+    /// A block given by the allocator, with the statistics before: undone by freeing it.
+    Allocated(HeapPointer, allocator::AllocatorStats),
+    /// A failed allocation, with the statistics before.
+    FailedAllocation(allocator::AllocatorStats),
+    /// A block freed by a DEL, with its size and the statistics before: undone by allocating it again at the same place.
+    Freed(HeapPointer, usize, allocator::AllocatorStats),
+    /// A freed block kept out of the free space: undone by releasing it.
+    Reserved(HeapPointer, usize),
+    /// A block given back to the free space: undone by reserving it again.
+    Released(HeapPointer, usize),
 }
 
 impl Memory {
     /// Create a new memory with the given sizes for the stack and heap.
     pub fn new(heap_size: usize, stack_size: usize) -> Memory {
+        Memory::with_allocator(heap_size, stack_size, allocator::AllocatorKind::default())
+    }
+
+    /// Create a new memory with the given sizes for the stack and heap, and the given heap allocator.
+    pub fn with_allocator(heap_size: usize, stack_size: usize, allocator_kind: allocator::AllocatorKind) -> Memory {
         Memory {
             stack: vec![DataType::Undefined; stack_size],
             heap: vec![None; heap_size],
-            allocator: Box::new(allocator::LinearAllocator::new()),
+            allocator: allocator_kind.create(heap_size),
+            allocator_kind,
+            allocator_stats: allocator::AllocatorStats::default(),
+            write_log: None,
+            undo_log: None,
         }
     }
 
@@ -178,7 +267,12 @@ impl Memory {
     /// Set a word on the stack with the given stack pointer.
     pub fn set_stack(&mut self, at: StackPointer, value: DataType) -> Result<(), ImaExecutionError> {
         match self.stack.get_mut(at.as_index()) {
-            Some(x) => { *x = value; Ok(()) },
+            Some(x) => {
+                let old = std::mem::replace(x, value);
+                self.log_write(Pointer::Stack(at), value);
+                self.log_undo(MemoryChange::Stack(at, old));
+                Ok(())
+            },
             None => Err(ImaExecutionError::StackOverflow),
         }
     }
@@ -186,32 +280,234 @@ impl Memory {
     /// Set a word on the heap with the given heap pointer.
     pub fn set_heap(&mut self, at: HeapPointer, value: DataType) -> Result<(), ImaExecutionError> {
         match self.heap.get_mut(at.as_index()) {
-            Some(x) => { *x = Some(value); Ok(()) },
+            Some(x) => {
+                let old = x.replace(value);
+                self.log_write(Pointer::Heap(at), value);
+                self.log_undo(MemoryChange::Heap(at, old));
+                Ok(())
+            },
             None => Err(ImaExecutionError::InvalidMemoryAddress(Pointer::Heap(at))),
         }
     }
 
+    /// Start logging all the writes made to the memory, dropping any previous log.
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop logging the writes, and get all the writes made since the logging started.
+    pub fn take_write_log(&mut self) -> Vec<(Pointer, DataType)> {
+        self.write_log.take().unwrap_or_default()
+    }
+
+    /// Record a write, if logging is enabled.
+    fn log_write(&mut self, at: Pointer, value: DataType) {
+        if let Some(log) = self.write_log.as_mut() {
+            log.push((at, value));
+        }
+    }
+
+    /// Start recording the changes made to the memory, with the previous values, dropping any previous record.
+    pub fn start_undo_log(&mut self) {
+        self.undo_log = Some(Vec::new());
+    }
+
+    /// Stop recording the changes, and get all the changes made since the recording started, oldest first.
+    pub fn take_undo_log(&mut self) -> Vec<MemoryChange> {
+        self.undo_log.take().unwrap_or_default()
+    }
+
+    /// Record a change, if undo logging is enabled.
+    fn log_undo(&mut self, change: MemoryChange) {
+        if let Some(log) = self.undo_log.as_mut() {
+            log.push(change);
+        }
+    }
+
+    /// Record the words of the heap block, before the allocator changes them.
+    fn log_undo_block(&mut self, ptr: HeapPointer, size: usize) {
+        if self.undo_log.is_none() {
+            return;
+        }
+        let end = (ptr.as_index() + size).min(self.heap.len());
+        for i in ptr.as_index().min(end)..end {
+            self.log_undo(MemoryChange::Heap(HeapPointer(i as u32), self.heap[i]));
+        }
+    }
+
+    /// Undo the given changes, made in that order.
+    pub fn undo(&mut self, changes: Vec<MemoryChange>) {
+        for change in changes.into_iter().rev() {
+            match change {
+                MemoryChange::Stack(at, old) => self.stack[at.as_index()] = old,
+                MemoryChange::Heap(at, old) => self.heap[at.as_index()] = old,
+                // the words of the block are undone by their own changes
+                MemoryChange::Allocated(ptr, stats) => {
+                    self.allocator.free(&mut self.heap, ptr);
+                    self.allocator_stats = stats;
+                },
+                MemoryChange::FailedAllocation(stats) => self.allocator_stats = stats,
+                MemoryChange::Freed(ptr, size, stats) => {
+                    self.allocator.allocate_at(ptr, size);
+                    self.allocator_stats = stats;
+                },
+                MemoryChange::Reserved(ptr, size) => self.allocator.release(ptr, size),
+                MemoryChange::Released(ptr, size) => self.allocator.reserve(ptr, size),
+            }
+        }
+    }
+
     /// Get the size of the stack.
     pub fn stack_size(&self) -> usize {
         self.stack.len()
     }
 
+    /// Get the size of the heap.
+    pub fn heap_size(&self) -> usize {
+        self.heap.len()
+    }
+
     /// Allocate a new block of memory on the heap and returns a pointer to it.
     /// If the heap is full, this will fail and return None. 
     pub fn allocate(&mut self, size: usize) -> Option<HeapPointer> {
-        self.allocator.allocate(&mut self.heap, size)
+        let previous_stats = self.allocator_stats;
+        let ptr = self.allocator.allocate(&mut self.heap, size);
+        // count the size the allocator actually gave, for empty blocks
+        let size = ptr.and_then(|ptr| self.allocator.get_block(ptr)).map_or(0, |(_, size)| size);
+        let stats = &mut self.allocator_stats;
+        match ptr {
+            Some(_) => {
+                stats.allocations += 1;
+                stats.used += size;
+                stats.peak = stats.peak.max(stats.used);
+            },
+            None => stats.failed_allocations += 1,
+        }
+        // the allocator only gives free words, so freeing the block is enough to undo it
+        self.log_undo(match ptr {
+            Some(ptr) => MemoryChange::Allocated(ptr, previous_stats),
+            None => MemoryChange::FailedAllocation(previous_stats),
+        });
+        ptr
     }
 
     /// Free a block of memory on the heap.
     /// If The pointer does not point to a valid allocation, this will fail and return None. 
     pub fn free(&mut self, ptr: HeapPointer) -> Option<()> {
-        self.allocator.free(&mut self.heap, ptr)
+        let size = self.allocator.get_block(ptr).filter(|(start, _)| *start == ptr).map(|(_, size)| size);
+        self.log_undo_block(ptr, size.unwrap_or(0));
+        self.allocator.free(&mut self.heap, ptr)?;
+        self.log_undo(MemoryChange::Freed(ptr, size.unwrap_or(0), self.allocator_stats));
+        self.allocator_stats.frees += 1;
+        self.allocator_stats.used = self.allocator_stats.used.saturating_sub(size.unwrap_or(0));
+        Some(())
+    }
+
+    /// Get the allocated block containing the pointer, as a (start, size) pair.
+    pub fn get_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, usize)> {
+        self.allocator.get_block(ptr)
+    }
+
+    /// Poison a freed block of the heap: its words are marked as used,
+    /// so the allocator will not give them back until the block is released.
+    pub fn poison(&mut self, ptr: HeapPointer, size: usize) {
+        self.log_undo_block(ptr, size);
+        let end = (ptr.as_index() + size).min(self.heap.len());
+        self.heap[ptr.as_index().min(end)..end].iter_mut().for_each(|v| *v = Some(DataType::Undefined));
+        self.allocator.reserve(ptr, size);
+        self.log_undo(MemoryChange::Reserved(ptr, size));
+    }
+
+    /// Give a poisoned block back to the allocator.
+    pub fn release(&mut self, ptr: HeapPointer, size: usize) {
+        self.log_undo_block(ptr, size);
+        let end = (ptr.as_index() + size).min(self.heap.len());
+        self.heap[ptr.as_index().min(end)..end].iter_mut().for_each(|v| *v = None);
+        self.allocator.release(ptr, size);
+        self.log_undo(MemoryChange::Released(ptr, size));
+    }
+
+    /// Check if an allocation of the given size would succeed.
+    pub fn can_allocate(&self, size: usize) -> bool {
+        self.allocator.can_allocate(&self.heap, size)
+    }
+
+    /// Get the statistics on the use of the heap, with the current fragmentation.
+    pub fn allocator_stats(&self) -> allocator::AllocatorStats {
+        let (total, largest) = self.allocator.free_space(&self.heap);
+        allocator::AllocatorStats {
+            fragmentation: match total {
+                0 => 0.0,
+                total => 1.0 - largest as f64 / total as f64,
+            },
+            ..self.allocator_stats
+        }
+    }
+
+    /// Get all the allocated blocks of the heap, as (start, size) pairs.
+    pub fn allocations(&self) -> Vec<(HeapPointer, usize)> {
+        self.allocator.allocations()
+    }
+
+    /// Copy the used parts of the memory: the stack up to the stack pointer,
+    /// and all the allocated blocks of the heap.
+    pub fn used(&self, sp: StackPointer) -> UsedMemory {
+        UsedMemory {
+            stack: self.stack.iter().take(sp.as_index() + 1).copied().collect(),
+            heap: self.allocator.allocations().into_iter()
+                .map(|(start, size)| (start, self.heap[start.as_index()..start.as_index() + size].to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Check if the used parts of the memory hold exactly the same bits as the copy.
+    pub fn same_used(&self, sp: StackPointer, used: &UsedMemory) -> bool {
+        let stack = self.stack.iter().take(sp.as_index() + 1);
+        if stack.len() != used.stack.len() || !stack.zip(&used.stack).all(|(a, b)| a.same_bits(b)) {
+            return false;
+        }
+        let allocations = self.allocator.allocations();
+        allocations.len() == used.heap.len() && allocations.iter().zip(&used.heap).all(|((start, size), (used_start, words))| {
+            start == used_start && *size == words.len() && self.heap[start.as_index()..start.as_index() + size].iter()
+                .zip(words)
+                .all(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => a.same_bits(b),
+                    (a, b) => a.is_none() && b.is_none(),
+                })
+        })
+    }
+
+    /// Get a copy of the whole memory, with the bookkeeping of the allocator.
+    pub fn state(&self) -> MemoryState {
+        MemoryState {
+            stack: self.stack.clone(),
+            heap: self.heap.clone(),
+            allocator: self.allocator.state(),
+            allocator_kind: self.allocator_kind,
+            allocator_stats: self.allocator_stats,
+        }
+    }
+
+    /// Get the kind of the allocator of the heap.
+    pub fn allocator_kind(&self) -> allocator::AllocatorKind {
+        self.allocator_kind
+    }
+
+    /// Replace the whole memory by the given copy, sizes included.
+    pub fn restore(&mut self, state: MemoryState) {
+        self.stack = state.stack;
+        self.heap = state.heap;
+        self.allocator = state.allocator.restore();
+        self.allocator_kind = state.allocator_kind;
+        self.allocator_stats = state.allocator_stats;
     }
 
-    /// Clear the memory.
+    /// Clear the memory, with a new allocator.
     pub fn clear(&mut self) {
         self.stack.iter_mut().for_each(|v| *v = DataType::Undefined);
         self.heap.iter_mut().for_each(|v| *v = None);
+        self.allocator = self.allocator_kind.create(self.heap.len());
+        self.allocator_stats = allocator::AllocatorStats::default();
     }
 
     /// Display the stack to the given output between start and end.
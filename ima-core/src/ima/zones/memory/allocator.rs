@@ -1,6 +1,10 @@
 /// Created by Virgile HENRY, 2023/09/28
 
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    str::FromStr,
+};
 
 use crate::ima::data_type::DataType;
 
@@ -14,10 +18,184 @@ pub trait Allocator {
     /// Ideally, return an iterator over the allocations,
     /// but not yet available in Rust (https://github.com/rust-lang/rust/issues/91611)
     fn allocations<'a>(&self) -> Vec<(HeapPointer, usize)>;
+    /// Remove a freed range from the free space, until it is released.
+    /// Allocators finding the free space from the memory itself have nothing to do.
+    fn reserve(&mut self, _ptr: HeapPointer, _size: usize) {}
+    /// Give a reserved range back to the free space. Its words are already marked free in the memory.
+    fn release(&mut self, _ptr: HeapPointer, _size: usize) {}
+    /// Allocate a freed block again at the same place, to take back its free. The memory is left as is.
+    fn allocate_at(&mut self, ptr: HeapPointer, size: usize);
+    /// Get the total number of free words, and the size of the largest free block.
+    fn free_space(&self, memory: &[Option<DataType>]) -> (usize, usize);
+    /// Check if there is a free block big enough for an allocation of the given size.
+    fn can_allocate(&self, memory: &[Option<DataType>], size: usize) -> bool {
+        self.free_space(memory).1 >= size.max(1)
+    }
+    /// Get a copy of the whole bookkeeping of the allocator, to restore it later.
+    fn state(&self) -> AllocatorState;
+}
+
+/// Bookkeeping of an allocator, that can be turned back into the allocator.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub enum AllocatorState {
+    Linear(LinearAllocator),
+    FreeList(FreeListAllocator),
+    Buddy(BuddyAllocator),
+}
+
+impl AllocatorState {
+    /// Creates the allocator back from its bookkeeping.
+    pub fn restore(self) -> Box<dyn Allocator> {
+        match self {
+            AllocatorState::Linear(allocator) => Box::new(allocator),
+            AllocatorState::FreeList(allocator) => Box::new(allocator),
+            AllocatorState::Buddy(allocator) => Box::new(allocator),
+        }
+    }
+
+    /// Get the kind of the allocator this bookkeeping belongs to.
+    pub fn kind(&self) -> AllocatorKind {
+        match self {
+            AllocatorState::Linear(_) => AllocatorKind::Linear,
+            AllocatorState::FreeList(FreeListAllocator { fit: Fit::First, .. }) => AllocatorKind::FirstFit,
+            AllocatorState::FreeList(FreeListAllocator { fit: Fit::Best, .. }) => AllocatorKind::BestFit,
+            AllocatorState::Buddy(_) => AllocatorKind::Buddy,
+        }
+    }
+
+    /// Check that the bookkeeping could have been left by the allocator on this heap:
+    /// the blocks are inside the heap and do not overlap, allocated words are used and free ones are not.
+    /// The allocators trust their bookkeeping, so they could panic on one that was tampered with.
+    pub fn is_consistent(&self, memory: &[Option<DataType>]) -> bool {
+        match self {
+            AllocatorState::Linear(allocator) => allocator.is_consistent(memory),
+            AllocatorState::FreeList(allocator) => allocator.is_consistent(memory),
+            AllocatorState::Buddy(allocator) => allocator.is_consistent(memory),
+        }
+    }
+}
+
+/// The allocators the heap can use.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AllocatorKind {
+    /// Scans the heap from the start for a spot big enough. This is the original allocator.
+    #[default]
+    Linear,
+    /// Takes the first free block big enough, in address order.
+    FirstFit,
+    /// Takes the smallest free block big enough.
+    BestFit,
+    /// Splits the heap in blocks of powers of two, merged back with their buddy when freed.
+    Buddy,
+}
+
+impl AllocatorKind {
+    /// Creates an allocator of this kind, for a heap of the given size.
+    pub fn create(self, heap_size: usize) -> Box<dyn Allocator> {
+        match self {
+            AllocatorKind::Linear => Box::new(LinearAllocator::new()),
+            AllocatorKind::FirstFit => Box::new(FreeListAllocator::new(heap_size, Fit::First)),
+            AllocatorKind::BestFit => Box::new(FreeListAllocator::new(heap_size, Fit::Best)),
+            AllocatorKind::Buddy => Box::new(BuddyAllocator::new(heap_size)),
+        }
+    }
+}
+
+impl Display for AllocatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocatorKind::Linear => write!(f, "linear"),
+            AllocatorKind::FirstFit => write!(f, "first-fit"),
+            AllocatorKind::BestFit => write!(f, "best-fit"),
+            AllocatorKind::Buddy => write!(f, "buddy"),
+        }
+    }
+}
+
+impl FromStr for AllocatorKind {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(AllocatorKind::Linear),
+            "first-fit" => Ok(AllocatorKind::FirstFit),
+            "best-fit" => Ok(AllocatorKind::BestFit),
+            "buddy" => Ok(AllocatorKind::Buddy),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Statistics on the use of the heap.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AllocatorStats {
+    /// Number of successful allocations.
+    pub allocations: usize,
+    /// Number of allocations that did not fit in the heap.
+    pub failed_allocations: usize,
+    /// Number of successful frees.
+    pub frees: usize,
+    /// Number of words currently allocated.
+    pub used: usize,
+    /// Highest number of words allocated at once.
+    pub peak: usize,
+    /// External fragmentation of the free space, between 0 and 1:
+    /// 0 when it is a single block, close to 1 when it is scattered in small blocks.
+    pub fragmentation: f64,
+}
+
+impl Display for AllocatorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Heap allocations: {} ({} failed), {} frees", self.allocations, self.failed_allocations, self.frees)?;
+        writeln!(f, "Peak heap usage: {} words", self.peak)?;
+        write!(f, "Heap fragmentation: {:.1}%", self.fragmentation * 100.0)
+    }
+}
+
+/// Find the block containing the pointer, among blocks sorted by address.
+fn find_block(blocks: &BTreeMap<HeapPointer, usize>, ptr: HeapPointer) -> Option<(HeapPointer, usize)> {
+    let (start, size) = blocks.range(..=ptr).next_back()?;
+    match ptr.as_index() < start.as_index() + size {
+        true => Some((*start, *size)),
+        false => None,
+    }
+}
+
+/// Get the blocks as (start, size) pairs of indices.
+fn block_ranges(blocks: &BTreeMap<HeapPointer, usize>) -> Vec<(usize, usize)> {
+    blocks.iter().map(|(start, size)| (start.as_index(), *size)).collect()
+}
+
+/// Check that the blocks, as (start, size) pairs of indices, are inside the heap and do not overlap.
+fn disjoint_blocks(blocks: impl Iterator<Item = (usize, usize)>, heap_size: usize) -> bool {
+    let mut blocks = blocks.collect::<Vec<_>>();
+    blocks.sort_unstable();
+    let mut end = 0;
+    blocks.into_iter().all(|(start, size)| match start.checked_add(size) {
+        Some(block_end) if start >= end && block_end <= heap_size => {
+            end = block_end;
+            true
+        },
+        _ => false,
+    })
+}
+
+/// Check that all the words of the blocks are used, or all free. The blocks must be inside the heap.
+fn blocks_used(memory: &[Option<DataType>], blocks: &[(usize, usize)], used: bool) -> bool {
+    blocks.iter().all(|(start, size)| memory[*start..start + size].iter().all(|word| word.is_some() == used))
+}
+
+/// Set all the words of the block to the value: Some(Undefined) when allocated, None when freed.
+fn fill(memory: &mut [Option<DataType>], ptr: HeapPointer, size: usize, value: Option<DataType>) {
+    memory[ptr.as_index()..ptr.as_index() + size].iter_mut().for_each(|v| *v = value);
 }
 
 
 /// Super naive linear allocator. Finds the next spot in memory big enough to fit the allocation.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Default, Clone)]
 pub struct LinearAllocator {
     allocations: BTreeMap<HeapPointer, usize>,
 }
@@ -29,6 +207,11 @@ impl LinearAllocator {
             allocations: BTreeMap::new(),
         }
     }
+
+    fn is_consistent(&self, memory: &[Option<DataType>]) -> bool {
+        let allocated = block_ranges(&self.allocations);
+        disjoint_blocks(allocated.iter().copied(), memory.len()) && blocks_used(memory, &allocated, true)
+    }
 }
 
 impl Allocator for LinearAllocator {
@@ -37,9 +220,7 @@ impl Allocator for LinearAllocator {
         let mut available_size = 0;
         loop {
             if size == available_size {
-                for i in ptr.as_index()..(ptr.offset(size as i32).unwrap().as_index()) {
-                    memory[i] = Some(DataType::Undefined);
-                }
+                memory[ptr.as_index()..ptr.as_index() + size].fill(Some(DataType::Undefined));
                 self.allocations.insert(ptr, size);
                 return Some(ptr);
             }
@@ -47,10 +228,7 @@ impl Allocator for LinearAllocator {
             match memory.get(ptr.0 as usize + available_size) {
                 Some(None) => available_size += 1,
                 Some(Some(_)) => {
-                    ptr = match ptr.offset(available_size as i32 + 1) {
-                        Some(ptr) => ptr,
-                        None => return None,
-                    };
+                    ptr = ptr.offset(available_size as i32 + 1)?;
                     available_size = 0;
                 },
                 None => return None,
@@ -59,28 +237,374 @@ impl Allocator for LinearAllocator {
     }
 
     fn free(&mut self, memory: &mut [Option<DataType>], ptr: HeapPointer) -> Option<()> {
-        match self.allocations.remove(&ptr) {
-            Some(size) => {
-                // if the allocation is in the map, we can assume that it is valid
-                for i in ptr.as_index()..(ptr.offset(size as i32).unwrap().as_index()) {
-                    memory[i] = None;
-                }
-                Some(())
-            },
-            None => None,
+        let size = self.allocations.remove(&ptr)?;
+        // if the allocation is in the map, we can assume that it is valid
+        memory[ptr.as_index()..ptr.as_index() + size].fill(None);
+        Some(())
+    }
+
+    fn get_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, usize)> {
+        find_block(&self.allocations, ptr)
+    }
+
+    fn allocations(&self) -> Vec<(HeapPointer, usize)> {
+        self.allocations.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    fn allocate_at(&mut self, ptr: HeapPointer, size: usize) {
+        self.allocations.insert(ptr, size);
+    }
+
+    fn free_space(&self, memory: &[Option<DataType>]) -> (usize, usize) {
+        let (mut total, mut largest, mut current) = (0, 0, 0);
+        for word in memory {
+            match word {
+                None => {
+                    total += 1;
+                    current += 1;
+                    largest = largest.max(current);
+                },
+                Some(_) => current = 0,
+            }
+        }
+        (total, largest)
+    }
+
+    fn state(&self) -> AllocatorState {
+        AllocatorState::Linear(self.clone())
+    }
+}
+
+
+/// How a free list allocator picks the free block to allocate from.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// The first block big enough, in address order.
+    First,
+    /// The smallest block big enough, the lowest address first.
+    Best,
+}
+
+/// Allocator keeping a list of the free blocks, merged with their neighbours when freed.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct FreeListAllocator {
+    fit: Fit,
+    allocations: BTreeMap<HeapPointer, usize>,
+    /// Free blocks by address, to merge neighbours.
+    free_blocks: BTreeMap<HeapPointer, usize>,
+    /// Free blocks by size, to find the best fit.
+    free_sizes: BTreeSet<(usize, HeapPointer)>,
+}
+
+impl FreeListAllocator {
+    /// Creates an allocator for a heap of the given size, that is all free.
+    pub fn new(heap_size: usize, fit: Fit) -> FreeListAllocator {
+        let mut allocator = FreeListAllocator {
+            fit,
+            allocations: BTreeMap::new(),
+            free_blocks: BTreeMap::new(),
+            free_sizes: BTreeSet::new(),
+        };
+        if heap_size > 0 {
+            allocator.insert_free(HeapPointer(0), heap_size);
+        }
+        allocator
+    }
+
+    fn insert_free(&mut self, ptr: HeapPointer, size: usize) {
+        self.free_blocks.insert(ptr, size);
+        self.free_sizes.insert((size, ptr));
+    }
+
+    fn remove_free(&mut self, ptr: HeapPointer) -> Option<usize> {
+        let size = self.free_blocks.remove(&ptr)?;
+        self.free_sizes.remove(&(size, ptr));
+        Some(size)
+    }
+
+    fn is_consistent(&self, memory: &[Option<DataType>]) -> bool {
+        let allocated = block_ranges(&self.allocations);
+        let free = block_ranges(&self.free_blocks);
+        // both indices of the free blocks hold the same blocks
+        self.free_sizes.len() == self.free_blocks.len()
+            && self.free_blocks.iter().all(|(ptr, size)| self.free_sizes.contains(&(*size, *ptr)))
+            && disjoint_blocks(allocated.iter().chain(&free).copied(), memory.len())
+            && blocks_used(memory, &allocated, true)
+            && blocks_used(memory, &free, false)
+    }
+
+    /// Find a free block of at least the given size, according to the fit.
+    fn find_free(&self, size: usize) -> Option<(HeapPointer, usize)> {
+        match self.fit {
+            Fit::First => self.free_blocks.iter().find(|(_, free)| **free >= size).map(|(ptr, free)| (*ptr, *free)),
+            Fit::Best => self.free_sizes.range((size, HeapPointer(0))..).next().map(|(free, ptr)| (*ptr, *free)),
+        }
+    }
+}
+
+impl Allocator for FreeListAllocator {
+    fn allocate(&mut self, memory: &mut [Option<DataType>], size: usize) -> Option<HeapPointer> {
+        // empty blocks still take a word, so that every block has its own address
+        let size = size.max(1);
+        let (ptr, free) = self.find_free(size)?;
+        self.remove_free(ptr);
+        if free > size {
+            self.insert_free(HeapPointer(ptr.0 + size as u32), free - size);
         }
+        fill(memory, ptr, size, Some(DataType::Undefined));
+        self.allocations.insert(ptr, size);
+        Some(ptr)
+    }
+
+    fn free(&mut self, memory: &mut [Option<DataType>], ptr: HeapPointer) -> Option<()> {
+        let size = self.allocations.remove(&ptr)?;
+        fill(memory, ptr, size, None);
+        self.insert_merged(ptr, size);
+        Some(())
     }
 
     fn get_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, usize)> {
-        for (start, size) in self.allocations.iter() {
-            if ptr.as_index() >= start.as_index() && ptr.as_index() < start.as_index() + size {
-                return Some((*start, *size));
+        find_block(&self.allocations, ptr)
+    }
+
+    fn allocations(&self) -> Vec<(HeapPointer, usize)> {
+        self.allocations.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    fn reserve(&mut self, ptr: HeapPointer, size: usize) {
+        let (start, free) = match find_block(&self.free_blocks, ptr) {
+            Some(block) => block,
+            None => return,
+        };
+        self.remove_free(start);
+        let end = (ptr.as_index() + size).min(start.as_index() + free);
+        if ptr > start {
+            self.insert_free(start, ptr.as_index() - start.as_index());
+        }
+        if end < start.as_index() + free {
+            self.insert_free(HeapPointer(end as u32), start.as_index() + free - end);
+        }
+    }
+
+    fn release(&mut self, ptr: HeapPointer, size: usize) {
+        self.insert_merged(ptr, size);
+    }
+
+    fn allocate_at(&mut self, ptr: HeapPointer, size: usize) {
+        self.reserve(ptr, size);
+        self.allocations.insert(ptr, size);
+    }
+
+    fn free_space(&self, _memory: &[Option<DataType>]) -> (usize, usize) {
+        let total = self.free_blocks.values().sum();
+        let largest = self.free_sizes.last().map(|(size, _)| *size).unwrap_or(0);
+        (total, largest)
+    }
+
+    fn state(&self) -> AllocatorState {
+        AllocatorState::FreeList(self.clone())
+    }
+}
+
+impl FreeListAllocator {
+    /// Add a free block, merged with the free blocks right before and right after.
+    fn insert_merged(&mut self, ptr: HeapPointer, mut size: usize) {
+        let mut start = ptr;
+        // merge with the free blocks right before and right after
+        if let Some((&before, &before_size)) = self.free_blocks.range(..ptr).next_back() {
+            if before.as_index() + before_size == ptr.as_index() {
+                self.remove_free(before);
+                start = before;
+                size += before_size;
+            }
+        }
+        let after = HeapPointer(start.0 + size as u32);
+        if let Some(after_size) = self.remove_free(after) {
+            size += after_size;
+        }
+        self.insert_free(start, size);
+    }
+}
+
+
+/// Buddy allocator: blocks are powers of two, split in two buddies to allocate smaller ones,
+/// and merged back with their buddy when both are free.
+/// A heap whose size is not a power of two starts as several blocks, one per bit of the size.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct BuddyAllocator {
+    heap_size: usize,
+    /// Allocated blocks, with their requested size.
+    allocations: BTreeMap<HeapPointer, usize>,
+    /// Order of the allocated blocks: the block holds 2^order words.
+    orders: BTreeMap<HeapPointer, u32>,
+    /// Free blocks of each order.
+    free_lists: Vec<BTreeSet<HeapPointer>>,
+}
+
+impl BuddyAllocator {
+    /// Creates an allocator for a heap of the given size, that is all free.
+    pub fn new(heap_size: usize) -> BuddyAllocator {
+        let orders = usize::BITS - heap_size.leading_zeros();
+        let mut free_lists = vec![BTreeSet::new(); orders as usize];
+        // biggest blocks first: each block is aligned on its size
+        let mut start = 0;
+        for order in (0..orders).rev() {
+            if heap_size & (1 << order) != 0 {
+                free_lists[order as usize].insert(HeapPointer(start as u32));
+                start += 1 << order;
             }
         }
-        None
+        BuddyAllocator {
+            heap_size,
+            allocations: BTreeMap::new(),
+            orders: BTreeMap::new(),
+            free_lists,
+        }
+    }
+
+    /// Add a free block of the given order, merged with its buddy as long as the buddy is free too.
+    fn insert_merged(&mut self, ptr: HeapPointer, mut order: usize) {
+        let mut start = ptr;
+        while order + 1 < self.free_lists.len() {
+            let buddy = HeapPointer(start.0 ^ (1 << order));
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            start = start.min(buddy);
+            order += 1;
+        }
+        self.free_lists[order].insert(start);
+    }
+
+    fn is_consistent(&self, memory: &[Option<DataType>]) -> bool {
+        let orders = (usize::BITS - self.heap_size.leading_zeros()) as usize;
+        if self.heap_size != memory.len() || self.free_lists.len() != orders || !self.allocations.keys().eq(self.orders.keys()) {
+            return false;
+        }
+        let allocated = self.orders.iter().map(|(ptr, order)| (ptr.as_index(), *order as usize)).collect::<Vec<_>>();
+        let free = self.free_lists.iter().enumerate()
+            .flat_map(|(order, list)| list.iter().map(move |ptr| (ptr.as_index(), order)))
+            .collect::<Vec<_>>();
+        // each block is aligned on its size, and the requested size fits in its block
+        if !allocated.iter().chain(&free).all(|(start, order)| *order < orders && start.is_multiple_of(1 << order))
+            || !self.allocations.iter().all(|(ptr, size)| (1..=1 << self.orders[ptr]).contains(size)) {
+            return false;
+        }
+        let free = free.into_iter().map(|(start, order)| (start, 1 << order)).collect::<Vec<_>>();
+        disjoint_blocks(allocated.iter().map(|(start, order)| (*start, 1 << order)).chain(free.iter().copied()), memory.len())
+            && blocks_used(memory, &block_ranges(&self.allocations), true)
+            && blocks_used(memory, &free, false)
+    }
+
+    /// Find the free block containing the pointer, with its order.
+    fn free_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, u32)> {
+        self.free_lists.iter().enumerate().find_map(|(order, list)| {
+            let start = list.range(..=ptr).next_back()?;
+            match ptr.as_index() < start.as_index() + (1 << order) {
+                true => Some((*start, order as u32)),
+                false => None,
+            }
+        })
+    }
+}
+
+impl Allocator for BuddyAllocator {
+    fn allocate(&mut self, memory: &mut [Option<DataType>], size: usize) -> Option<HeapPointer> {
+        // empty blocks still take a word, so that every block has its own address
+        let size = size.max(1);
+        if size > self.heap_size {
+            return None;
+        }
+        let order = size.next_power_of_two().trailing_zeros();
+        let mut current = (order as usize..self.free_lists.len()).find(|o| !self.free_lists[*o].is_empty())?;
+        let ptr = self.free_lists[current].pop_first()?;
+        // split the block, keeping the lower half, until it has the right order
+        while current > order as usize {
+            current -= 1;
+            self.free_lists[current].insert(HeapPointer(ptr.0 + (1 << current)));
+        }
+        fill(memory, ptr, size, Some(DataType::Undefined));
+        self.allocations.insert(ptr, size);
+        self.orders.insert(ptr, order);
+        Some(ptr)
+    }
+
+    fn free(&mut self, memory: &mut [Option<DataType>], ptr: HeapPointer) -> Option<()> {
+        let size = self.allocations.remove(&ptr)?;
+        let order = self.orders.remove(&ptr)?;
+        fill(memory, ptr, size, None);
+        self.insert_merged(ptr, order as usize);
+        Some(())
+    }
+
+    fn get_block(&self, ptr: HeapPointer) -> Option<(HeapPointer, usize)> {
+        find_block(&self.allocations, ptr)
     }
 
     fn allocations(&self) -> Vec<(HeapPointer, usize)> {
         self.allocations.iter().map(|(k, v)| (*k, *v)).collect()
     }
-}
\ No newline at end of file
+
+    fn reserve(&mut self, ptr: HeapPointer, size: usize) {
+        let end = ptr.as_index() + size;
+        let mut at = ptr;
+        // split the free blocks over the range, and drop the parts inside it
+        while at.as_index() < end {
+            let (start, order) = match self.free_block(at) {
+                Some(block) => block,
+                None => return,
+            };
+            let block_end = start.as_index() + (1 << order);
+            if start >= at && block_end <= end {
+                self.free_lists[order as usize].remove(&start);
+                at = HeapPointer(block_end as u32);
+            } else {
+                self.free_lists[order as usize].remove(&start);
+                let half = order as usize - 1;
+                self.free_lists[half].insert(start);
+                self.free_lists[half].insert(HeapPointer(start.0 + (1 << half)));
+            }
+        }
+    }
+
+    fn release(&mut self, ptr: HeapPointer, size: usize) {
+        let end = ptr.as_index() + size;
+        let mut at = ptr.as_index();
+        // cut the range in the biggest blocks aligned on their size
+        while at < end {
+            let order = (0..self.free_lists.len())
+                .take_while(|order| at.is_multiple_of(1 << order) && at + (1 << order) <= end)
+                .last()
+                .unwrap_or(0);
+            self.insert_merged(HeapPointer(at as u32), order);
+            at += 1 << order;
+        }
+    }
+
+    fn allocate_at(&mut self, ptr: HeapPointer, size: usize) {
+        let order = size.max(1).next_power_of_two().trailing_zeros();
+        self.reserve(ptr, 1 << order);
+        self.allocations.insert(ptr, size);
+        self.orders.insert(ptr, order);
+    }
+
+    fn can_allocate(&self, memory: &[Option<DataType>], size: usize) -> bool {
+        self.free_space(memory).1 >= size.max(1).next_power_of_two()
+    }
+
+    fn free_space(&self, _memory: &[Option<DataType>]) -> (usize, usize) {
+        let total = self.free_lists.iter().enumerate().map(|(order, list)| list.len() << order).sum();
+        let largest = self.free_lists.iter().enumerate().rev()
+            .find(|(_, list)| !list.is_empty())
+            .map(|(order, _)| 1 << order)
+            .unwrap_or(0);
+        (total, largest)
+    }
+
+    fn state(&self) -> AllocatorState {
+        AllocatorState::Buddy(self.clone())
+    }
+}
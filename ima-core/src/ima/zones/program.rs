@@ -1,222 +1,235 @@
 /// Created by Virgile HENRY, 2023/09/28
 
-use std::io::Write;
+use std::{fmt::Display, io::Write};
 
 use crate::{
     instructions::Instruction,
-    parser::parser::Line
+    parser::label::Label,
 };
 
 /// Address of an instruction in the program.
 pub type CodeAddr = u32;
 
-/// Represent a program in the IMA, in release mode.
-/// All lines of the program have been compacted to keep only the instructions.
-pub struct ReleaseModeProgram(pub Vec<Instruction>);
-
-/// Represent a program in the IMA, in debug mode.
-/// All lines of the program are kept, with the instructions, but also comments and labels.
-/// Every line also has a stop flag, which indicates breakpoints.
-pub struct DebugModeProgram(pub Vec<(Line, bool)>);
-
-/// Trait to abstract the difference between release and debug mode.
-pub trait RunMode {
-    /// Fetch the current instruction at the given program pointer.
-    fn fetch(&self, pc: CodeAddr) -> Option<&Instruction>;
-    /// Increment the given program counter.
-    fn increment_pc(&mut self, pc: &mut CodeAddr);
-    /// Set the given program counter to the given value.
-    fn set_pc(&mut self, pc: &mut CodeAddr, new_pc: CodeAddr);
+/// Location of an instruction in the source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Line of the instruction in the source file, starting at 1.
+    pub line: u32,
+    /// Last label defined at or before the instruction, if any.
+    pub label: Option<Label>,
 }
 
-impl RunMode for ReleaseModeProgram {
-    fn fetch(&self, pc: CodeAddr) -> Option<&Instruction> {
-        self.0.get(pc as usize)
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "line {} (in {})", self.line, label),
+            None => write!(f, "line {}", self.line),
+        }
     }
+}
 
-    fn increment_pc(&mut self, pc: &mut CodeAddr) {
-        *pc += 1;
-    }
+/// Source line of an instruction in a message: "line 4", or "an unknown line" when it is not known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayLine(pub Option<u32>);
 
-    fn set_pc(&mut self, pc: &mut CodeAddr, new_pc: CodeAddr) {
-        *pc = new_pc;
+impl Display for DisplayLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(line) => write!(f, "line {}", line),
+            None => write!(f, "an unknown line"),
+        }
     }
 }
 
-impl RunMode for DebugModeProgram {
-    fn fetch(&self, pc: CodeAddr) -> Option<&Instruction> {
-        match self.0.get(pc as usize) {
-            Some(line) => line.0.instruction.as_ref(),
-            None => None,
+/// A line of the source file, as shown by the debuggers.
+#[derive(Debug, Clone, Default)]
+pub struct SourceLine {
+    /// A line can have any number of labels.
+    pub labels: Vec<Label>,
+    /// Address of the instruction of the line, if it has one.
+    pub instruction: Option<CodeAddr>,
+    /// A line can have up to one comment.
+    pub comment: Option<String>,
+}
+
+/// Debug information of a program, kept aside from the instructions.
+/// Lines are indexed from 0, the source locations count them from 1.
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    /// All the lines of the source file: labels, comments, and empty lines too.
+    pub lines: Vec<SourceLine>,
+    /// The source location of each instruction.
+    pub line_table: Vec<SourceLocation>,
+    /// The breakpoint flag of each instruction.
+    pub breakpoints: Vec<bool>,
+}
+
+impl DebugInfo {
+    /// Creates the debug info of a program from its lines, without any breakpoint.
+    pub fn new(lines: Vec<SourceLine>, line_table: Vec<SourceLocation>) -> DebugInfo {
+        DebugInfo {
+            lines,
+            breakpoints: vec![false; line_table.len()],
+            line_table,
         }
     }
 
-    fn increment_pc(&mut self, pc: &mut CodeAddr) {
-        *pc += 1;
-        while match self.0.get(*pc as usize) {
-            Some(line) => line.0.instruction.is_none(),
-            None => false,
-        } {
-            *pc += 1;
-        }
+    /// Get the address of the first instruction at or after the given line index.
+    pub fn instruction_at_line(&self, line: usize) -> Option<CodeAddr> {
+        self.lines.get(line..)?.iter().find_map(|line| line.instruction)
     }
 
-    fn set_pc(&mut self, pc: &mut CodeAddr, new_pc: CodeAddr) {
-        *pc = new_pc;
-        while match self.0.get(*pc as usize) {
-            Some(line) => line.0.instruction.is_none(),
-            None => false,
-        } {
-            *pc += 1;
-        }
+    /// Get the line index of the instruction at the given address.
+    pub fn line_of(&self, pc: CodeAddr) -> Option<usize> {
+        self.line_table.get(pc as usize).map(|location| location.line as usize - 1)
     }
 }
 
 #[cfg(not(feature = "public-ima"))]
 /// Represent a program in the IMA.
-pub struct Program<RM> {
+/// Release and debug runs execute the same compact instructions,
+/// the source lines only live in the debug info.
+pub struct Program {
     /// The program pointer.
     pc: CodeAddr,
-    /// The code of the program.
-    code: RM,
+    /// The compacted instructions.
+    instructions: Vec<Instruction>,
+    /// The source lines and breakpoints of the program.
+    debug_info: DebugInfo,
 }
 
 #[cfg(feature = "public-ima")]
 /// Represent a program in the IMA.
-pub struct Program<RM> {
+/// Release and debug runs execute the same compact instructions,
+/// the source lines only live in the debug info.
+pub struct Program {
     /// The program pointer.
     pub pc: CodeAddr,
-    /// The code of the program.
-    pub code: RM,
+    /// The compacted instructions.
+    pub instructions: Vec<Instruction>,
+    /// The source lines and breakpoints of the program.
+    pub debug_info: DebugInfo,
 }
 
-impl Program<ReleaseModeProgram> {
-    /// Creates a new program in release mode.
-    pub fn new(code: Vec<Instruction>) -> Program<ReleaseModeProgram> {
-        Program { 
+impl Program {
+    /// Creates a new program, with the debug info of its instructions.
+    pub fn new(instructions: Vec<Instruction>, debug_info: DebugInfo) -> Program {
+        Program {
             pc: 0,
-            code: ReleaseModeProgram(code),
+            instructions,
+            debug_info,
         }
     }
-}
 
-impl Program<DebugModeProgram> {
-    /// Creates a new program in debug mode.
-    pub fn new_debug(code: Vec<Line>) -> Program<DebugModeProgram> {
-        Program { 
-            pc: {
-                let mut pc = 0;
-                while match code.get(pc as usize) {
-                    Some(line) => line.instruction.is_none(),
-                    None => false,
-                } { pc += 1; }
-                pc
-            },
-            code: DebugModeProgram(code.into_iter().map(|line| (line, false)).collect()),
-        }
+    /// Get the current value of the program counter.
+    pub fn pc(&self) -> CodeAddr {
+        self.pc
     }
 
-    /// Toggle the breakpoint of the given line.
-    pub fn toggle_breakpoint(&mut self, at: CodeAddr) {
-        let mut at = at;
-        loop {
-            match self.code.0.get_mut(at as usize) {
-                Some(line) => {
-                    if line.0.instruction.is_some() {
-                        line.1 = !line.1;
-                        break;
-                    }
-                    else {
-                        at += 1;
-                    }
-                },
-                None => break,
-            }
-        }
+    /// Get the current instruction at the program counter.
+    pub fn fetch(&self) -> Option<&Instruction> {
+        self.instruction(self.pc)
     }
 
-    /// Set the breakpoint of the given line.
-    pub fn set_breakpoint(&mut self, at: CodeAddr) {
-        let mut at = at;
-        loop {
-            match self.code.0.get_mut(at as usize) {
-                Some(line) => {
-                    if line.0.instruction.is_some() {
-                        line.1 = true;
-                        break;
-                    }
-                    else {
-                        at += 1;
-                    }
-                },
-                None => break,
-            }
-        }
+    /// Get the instruction at the given program counter.
+    pub fn instruction(&self, pc: CodeAddr) -> Option<&Instruction> {
+        self.instructions.get(pc as usize)
     }
 
-    /// Set the breakpoint of the given line.
-    pub fn remove_breakpoint(&mut self, at: CodeAddr) {
-        let mut at = at;
-        loop {
-            match self.code.0.get_mut(at as usize) {
-                Some(line) => {
-                    if line.0.instruction.is_some() {
-                        line.1 = false;
-                        break;
-                    }
-                    else {
-                        at += 1;
-                    }
-                },
-                None => break,
-            }
-        }
+    /// Get all the instructions of the program.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
     }
 
-    /// Check if the given line has a breakpoint.
-    pub fn is_breakpoint(&mut self) -> bool {
-        match self.code.0.get(self.pc as usize) {
-            Some(line) => line.1,
-            None => false,
-        }
+    /// Get the source lines and the breakpoints of the program.
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+
+    /// Increment the program counter.
+    pub fn increment_pc(&mut self) {
+        self.pc += 1;
+    }
+
+    /// Set the program counter to the given value.
+    pub fn set_pc(&mut self, pc: CodeAddr) {
+        self.pc = pc;
     }
 
     /// Reset the program counter to the first instruction.
     pub fn reset(&mut self) {
-        self.pc = {
-            let mut pc = 0;
-            while match self.code.0.get(pc as usize) {
-                Some(line) => line.0.instruction.is_none(),
-                None => false,
-            } { pc += 1; }
-            pc
-        };
+        self.pc = 0;
+    }
+
+    /// Get the source location of the instruction at the given program counter.
+    pub fn location(&self, pc: CodeAddr) -> Option<SourceLocation> {
+        self.debug_info.line_table.get(pc as usize).cloned()
+    }
+
+    /// Get the source line of the instruction at the given program counter,
+    /// or None if the program counter is out of the program.
+    pub fn source_line(&self, pc: CodeAddr) -> Option<u32> {
+        self.location(pc).map(|location| location.line)
     }
 
+    /// Get the address of the instruction right before the given program counter.
+    pub fn previous_pc(&self, pc: CodeAddr) -> Option<CodeAddr> {
+        pc.checked_sub(1)
+    }
+
+    /// Check if the instruction at the given program counter has a breakpoint.
+    pub fn is_breakpoint(&self, pc: CodeAddr) -> bool {
+        self.debug_info.breakpoints.get(pc as usize).copied().unwrap_or(false)
+    }
+
+    /// Toggle the breakpoint of the first instruction at or after the given line index.
+    pub fn toggle_breakpoint(&mut self, line: u32) {
+        if let Some(pc) = self.debug_info.instruction_at_line(line as usize) {
+            self.debug_info.breakpoints[pc as usize] ^= true;
+        }
+    }
+
+    /// Set the breakpoint of the first instruction at or after the given line index.
+    pub fn set_breakpoint(&mut self, line: u32) {
+        if let Some(pc) = self.debug_info.instruction_at_line(line as usize) {
+            self.debug_info.breakpoints[pc as usize] = true;
+        }
+    }
+
+    /// Remove the breakpoint of the first instruction at or after the given line index.
+    pub fn remove_breakpoint(&mut self, line: u32) {
+        if let Some(pc) = self.debug_info.instruction_at_line(line as usize) {
+            self.debug_info.breakpoints[pc as usize] = false;
+        }
+    }
+
+    /// Display the current instruction, with its source line counted from 1, like the errors.
     pub fn display_inst(&self, output: &mut impl Write) -> Result<(), std::io::Error> {
-        match self.code.0.get(self.pc as usize) {
-            Some((Line { instruction: Some(inst), .. }, _)) => {
-                output.write(format!("{}: {}\n", self.pc, inst).as_bytes())?;
+        match (self.fetch(), self.debug_info.line_of(self.pc)) {
+            (Some(inst), Some(line)) => {
+                output.write(format!("{}: {}\n", line + 1, inst).as_bytes())?;
                 Ok(())
             },
             _ => Ok(()),
         }
     }
 
+    /// Display ten lines of the source, from the line of the current instruction,
+    /// going the given number of lines forward each time. Lines are counted from 1, like the errors.
     pub fn display_program(&mut self, output: &mut impl Write, step: u32) -> Result<(), std::io::Error> {
+        let current = self.debug_info.line_of(self.pc).unwrap_or(self.debug_info.lines.len()) as u32;
         for i in 0..10 {
-            let i = self.pc + i * step;
-            match self.code.0.get(i as usize) {
-                Some((line, bp)) => {
-                    let sp = if self.pc == i { " --> " } else { "     " };
-                    let bp = if *bp { "**" } else { "  " };
-                    output.write(format!("{sp} {bp}{i:>4}| ").as_bytes())?;
+            let i = current + i * step;
+            match self.debug_info.lines.get(i as usize) {
+                Some(line) => {
+                    let sp = if current == i { " --> " } else { "     " };
+                    let bp = if line.instruction.is_some_and(|pc| self.is_breakpoint(pc)) { "**" } else { "  " };
+                    output.write(format!("{sp} {bp}{:>4}| ", i + 1).as_bytes())?;
                     for label in line.labels.iter() {
                         output.write(format!("{}: ", label).as_bytes())?;
                     }
-                    match line.instruction {
-                        Some(ref inst) => {output.write(format!("{}", inst).as_bytes())?;},
-                        None => {},
+                    if let Some(inst) = line.instruction.and_then(|pc| self.instruction(pc)) {
+                        output.write(format!("{}", inst).as_bytes())?;
                     }
                     match line.comment {
                         Some(ref comment) => {output.write(format!(" ; {}", comment).as_bytes())?;},
@@ -230,35 +243,3 @@ impl Program<DebugModeProgram> {
         Ok(())
     }
 }
-
-
-
-impl<RM: RunMode> Program<RM> {
-    /// Get the current value of the program counter.
-    pub fn pc(&self) -> CodeAddr {
-        self.pc
-    }
-
-    /// Get the current instruction at the program counter.
-    pub fn fetch(&self) -> Option<&Instruction> {
-        self.code.fetch(self.pc)
-    }
-
-    /// Increment the program counter.
-    pub fn increment_pc(&mut self) {
-        self.code.increment_pc(&mut self.pc);
-    }
-
-    /// Set the program counter to the given value.
-    pub fn set_pc(&mut self, pc: CodeAddr) {
-        self.code.set_pc(&mut self.pc, pc);
-    }
-
-    pub fn code(&self) -> &RM {
-        &self.code
-    }
-
-    pub fn code_mut(&mut self) -> &mut RM {
-        &mut self.code
-    }
-}
@@ -2,16 +2,33 @@
 
 use crate::ima::{data_type::DataType, address_modes::RegisterIndex};
 
+/// Number of registers of the machine, when not given: R0 to R15.
+pub const DEFAULT_REGISTER_COUNT: usize = 16;
+/// Fewest registers a machine can have: R0 and R1 are used implicitly by some instructions.
+pub const MIN_REGISTER_COUNT: usize = 2;
+/// Most registers a machine can have: register indices are stored on a byte.
+pub const MAX_REGISTER_COUNT: usize = 256;
+
 /// The Register set of the machine.
-/// This only contains the registers from 0 to 15, not LB, GB and SP.
+/// This only contains the registers from R0 to Rn-1, not LB, GB and SP.
 #[cfg(not(feature = "public-ima"))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
 pub struct Registers {
     registers: Vec<DataType>,
+    /// Registers written since the logging started, if it is enabled.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    write_log: Option<Vec<RegisterIndex>>,
 }
 
 #[cfg(feature = "public-ima")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
 pub struct Registers {
     pub registers: Vec<DataType>,
+    /// Registers written since the logging started, if it is enabled.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub write_log: Option<Vec<RegisterIndex>>,
 }
 
 
@@ -20,6 +37,7 @@ impl Registers {
     pub fn new(r_count: usize) -> Registers {
         Registers {
             registers: vec![DataType::Undefined; r_count],
+            write_log: None,
         }
     }
 
@@ -31,14 +49,40 @@ impl Registers {
     /// Set the data type in the given register.
     pub fn set(&mut self, index: RegisterIndex, value: DataType) {
         self.registers[usize::from(index.0)] = value;
+        if let Some(log) = self.write_log.as_mut() {
+            log.push(index);
+        }
+    }
+
+    /// Start logging the registers written, dropping any previous log.
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop logging the writes, and get the registers written since the logging started.
+    pub fn take_write_log(&mut self) -> Vec<RegisterIndex> {
+        self.write_log.take().unwrap_or_default()
+    }
+
+    /// Get the number of registers.
+    pub fn count(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Check if both register sets hold exactly the same bits.
+    pub fn same_bits(&self, other: &Registers) -> bool {
+        self.registers.len() == other.registers.len()
+            && self.registers.iter().zip(&other.registers).all(|(a, b)| a.same_bits(b))
     }
 
     pub fn display(&self, output: &mut impl std::io::Write) -> Result<(), std::io::Error> {
+        // indices are aligned on the widest one
+        let width = (self.registers.len() - 1).to_string().len().max(2);
         let mut new_line = false;
         for (i, r) in self.registers.iter().enumerate() {
             match new_line {
-                true => writeln!(output, "{:>15}R{:<2} : {}", ' ', i, r)?,
-                false => write!(output, "R{:<2} : {}", i, r)?,
+                true => writeln!(output, "{:>15}R{:<width$} : {}", ' ', i, r)?,
+                false => write!(output, "R{:<width$} : {}", i, r)?,
             }
             new_line = !new_line;
         }
@@ -1,20 +1,18 @@
 /// Created by Virgile HENRY, 2023/09/28
 
-use std::{
-    fmt::Display,
-    io::{
-        Write,
-        BufRead
-    }
-};
+use std::fmt::Display;
 
 use crate::ima::{
     address_modes::{
         DVAL,
+        Register,
         RegisterIndex,
-        DADR
+        DADR,
+        Address,
+        Operand,
     },
     error::ImaExecutionError,
+    io::ImaIo,
 };
 
 type Rm = RegisterIndex;
@@ -169,6 +167,80 @@ impl Display for Instruction {
     }
 }
 
+impl Instruction {
+    /// Get the registers Rm named by the operands of the instruction.
+    /// R0 and R1, used implicitly by some instructions, are not included.
+    pub fn registers(&self) -> Vec<RegisterIndex> {
+        match self {
+            Instruction::LOAD(dval, rm) |
+            Instruction::NEW(dval, rm) |
+            Instruction::CMP(dval, rm) |
+            Instruction::ADD(dval, rm) |
+            Instruction::SUB(dval, rm) |
+            Instruction::MUL(dval, rm) |
+            Instruction::OPP(dval, rm) |
+            Instruction::QUO(dval, rm) |
+            Instruction::REM(dval, rm) |
+            Instruction::DIV(dval, rm) |
+            Instruction::FMA(dval, rm) |
+            Instruction::FLOAT(dval, rm) |
+            Instruction::INT(dval, rm) => {
+                let mut registers = dval_registers(dval);
+                registers.push(*rm);
+                registers
+            },
+            Instruction::STORE(rm, dadr) |
+            Instruction::LEA(dadr, rm) => {
+                let mut registers = dadr_registers(dadr);
+                registers.push(*rm);
+                registers
+            },
+            Instruction::PEA(dadr) => dadr_registers(dadr),
+            Instruction::PUSH(rm) |
+            Instruction::POP(rm) |
+            Instruction::DEL(rm) |
+            Instruction::SEQ(rm) |
+            Instruction::SGT(rm) |
+            Instruction::SGE(rm) |
+            Instruction::SOV(rm) |
+            Instruction::SNE(rm) |
+            Instruction::SLT(rm) |
+            Instruction::SLE(rm) |
+            Instruction::SHL(rm) |
+            Instruction::SHR(rm) => vec![*rm],
+            Instruction::BRA(dval) |
+            Instruction::BEQ(dval) |
+            Instruction::BGT(dval) |
+            Instruction::BGE(dval) |
+            Instruction::BOV(dval) |
+            Instruction::BNE(dval) |
+            Instruction::BLT(dval) |
+            Instruction::BLE(dval) |
+            Instruction::BSR(dval) => dval_registers(dval),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Get the registers Rm used to compute the address.
+fn dadr_registers(dadr: &DADR) -> Vec<RegisterIndex> {
+    match dadr {
+        DADR::OffsetIndirect { register: Register::R(index), .. } => vec![*index],
+        DADR::OffsetIndirect { .. } => Vec::new(),
+        DADR::OffsetAndDisplacedIndirect { address_register: Register::R(index), register_offset, .. } => vec![*index, *register_offset],
+        DADR::OffsetAndDisplacedIndirect { register_offset, .. } => vec![*register_offset],
+    }
+}
+
+/// Get the registers Rm used to compute the value.
+fn dval_registers(dval: &DVAL) -> Vec<RegisterIndex> {
+    match dval {
+        DVAL::DADR(dadr) => dadr_registers(dadr),
+        DVAL::Register(index) => vec![*index],
+        DVAL::Immediate(_) | DVAL::Label(_) => Vec::new(),
+    }
+}
+
 /// Trait for any object that can execute IMA instructions.
 pub trait Instructions {
     /// Load the value dval in the register Rm.
@@ -176,13 +248,13 @@ pub trait Instructions {
     /// Rm <- V\[dval\]
     /// 
     /// CC: CP
-    fn load(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn load(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Store the value in the register Rm at the address dadr.
     /// 
     /// A\[dadr\] <- V\[Rm\]
     /// 
     /// CC: CP
-    fn store(&mut self, rm: Rm, dadr: DADR) -> Result<(), ImaExecutionError>;
+    fn store(&mut self, rm: Rm, dadr: Address) -> Result<(), ImaExecutionError>;
     /// Push the value in the register Rm on the stack.
     /// 
     /// V\[SP\]+1 <- V\[Rm\],
@@ -204,16 +276,16 @@ pub trait Instructions {
     /// Rm <- A\[dadr\]
     /// 
     /// CC: CP
-    fn lea(&mut self, dadr: DADR, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn lea(&mut self, dadr: Address, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Push the address dadr on the stack
     /// 
     /// V\[SP\]+1 <- A\[dadr\],
     /// 
     /// SP <- V\[SP\] + 1
-    fn pea(&mut self, dadr: DADR) -> Result<(), ImaExecutionError>;
+    fn pea(&mut self, dadr: Address) -> Result<(), ImaExecutionError>;
     /// Allocate a new memory zone of size dval and store the address in the register Rm.
     /// The address in Rm is the address of the first allocated word.
-    fn new(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn new(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Free the memory zone at the address in the register Rm.
     /// 
     /// CC: OV (invalid address)
@@ -221,49 +293,49 @@ pub trait Instructions {
     /// Compares the values in the register Rm and the value dval.
     /// 
     /// CC: CP
-    fn cmp(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn cmp(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs an addition between the value in the register Rm and the value dval.
     /// This can be done on integers or floats.
     /// 
     /// Rm <- V\[Rm\] + V\[dval\]
     /// 
     /// CC: CP
-    fn add(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn add(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs a subtraction between the value in the register Rm and the value dval.
     /// This can be done on integers or floats.
     /// 
     /// Rm <- V\[Rm\] - V\[dval\]
     /// 
     /// CC: OV, CP
-    fn sub(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn sub(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs a multiplication between the value in the register Rm and the value dval.
     /// This can be done on integers or floats.
     /// 
     /// Rm <- V\[Rm\] * V\[dval\]
     /// 
     /// CC: OV, CP
-    fn mul(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn mul(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs an opposite on the value in the register Rm.
     /// This can be done on integers or floats.
     /// 
     /// Rm <- -V\[Rm\]
     /// 
     /// CC: OV, CP
-    fn opp(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn opp(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs a Euclidian division between the value in the register Rm and the value dval.
     /// This can only be done on integers.
     /// 
     /// Rm <- V\[Rm\] / V\[dval\]
     /// 
     /// CC: CP
-    fn quo(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn quo(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs a Euclidian modulo between the value in the register Rm and the value dval.
     /// This can only be done on integers.
     /// 
     /// Rm <- V\[Rm\] % V\[dval\]
     /// 
     /// CC: CP
-    fn rem(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn rem(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Checks the EQ flag. if EQ is true, the value in the register Rm is set to 1, else 0.
     fn seq(&mut self, rm: Rm);
     /// Checks the GT flag. if GT is true, the value in the register Rm is set to 1, else 0.
@@ -296,29 +368,32 @@ pub trait Instructions {
     /// Rm <- V\[Rm\] / V\[dval\]
     /// 
     /// CC: CP
-    fn div(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn div(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Performs a fused multiply-add between the value in the register Rm and the value dval.
     /// This can only be done on floats.
     /// 
     /// Rm <- V\[Rm\] * V\[dval\] + V\[R1\]
     /// 
     /// CC: CP
-    fn fma(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn fma(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Converts the value in the register Rm to a float.
     /// This can only be done on integers.
     /// 
     /// Rm <- float(V\[dval\])
     /// 
     /// CC: OV (unable to convert to float)
-    fn float(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn float(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Converts the value in the register Rm to an integer.
     /// This can only be done on floats.
     /// 
     /// Rm <- int(V\[dval\])
     /// 
+    /// The conversion is done with the current rounding mode.
+    /// 
     /// CC: OV (unable to convert to int)
-    fn int(&mut self, dval: DVAL, rm: Rm) -> Result<(), ImaExecutionError>;
+    fn int(&mut self, dval: Operand, rm: Rm) -> Result<(), ImaExecutionError>;
     /// Sets the floating point operation rounding mode to nearest.
+    /// This is the default mode, and applies to ADD, SUB, MUL, DIV, FMA, FLOAT and INT.
     fn setround_tonearest(&mut self);
     /// Sets the floating point operation rounding mode to upward.
     fn setround_upward(&mut self);
@@ -329,35 +404,35 @@ pub trait Instructions {
     /// Unconditional branch to the address dval.
     /// 
     /// PC <- V\[dval\]
-    fn bra(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bra(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the EQ flag is true.
     /// 
     /// if EQ: PC <- V\[dval\]
-    fn beq(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn beq(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the GT flag is true.
     /// 
     /// if GT: PC <- V\[dval\]
-    fn bgt(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bgt(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the GE flag is true.
     /// 
     /// if GE: PC <- V\[dval\]
-    fn bge(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bge(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the OV flag is true.
     /// 
     /// if OV: PC <- V\[dval\]
-    fn bov(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bov(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the NE flag is true.
     /// 
     /// if NE: PC <- V\[dval\]
-    fn bne(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bne(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the LT flag is true.
     /// 
     /// if LT: PC <- V\[dval\]
-    fn blt(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn blt(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval if the LE flag is true.
     /// 
     /// if LE: PC <- V\[dval\]
-    fn ble(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn ble(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Branch to the address dval, performing operations for a function call:
     /// 
     /// SP <- V\[SP\] + 2,
@@ -369,7 +444,7 @@ pub trait Instructions {
     /// LB <- V\[SP\],
     /// 
     /// PC <- V\[dval\]
-    fn bsr(&mut self, dval: DVAL) -> Result<(), ImaExecutionError>;
+    fn bsr(&mut self, dval: Operand) -> Result<(), ImaExecutionError>;
     /// Return from a function call:
     /// 
     /// PC <- C\[V\[LB\]-1\],
@@ -380,25 +455,25 @@ pub trait Instructions {
     fn rts(&mut self) -> Result<(), ImaExecutionError>;
     /// Read an integer from the standard input and store it in the register R1.
     /// This wait a newline character before completing.
-    fn rint<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError>;
+    fn rint<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Read a float from the standard input and store it in the register R1.
     /// This wait a newline character before completing.
-    fn rfloat<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError>;
+    fn rfloat<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Write the value in the register R1 to the standard output as an integer.
-    fn wint<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError>;
+    fn wint<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Write the value in the register R1 to the standard output as a float.
-    fn wfloat<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError>;
+    fn wfloat<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Write the value in the register R1 to the standard output as a float, in hexadecimal.
-    fn wfloatx<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError>;
+    fn wfloatx<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Write the string to the standard output.
-    fn wstr<W: Write>(&mut self, output: &mut W, string: String) -> Result<(), ImaExecutionError>;
+    fn wstr<IO: ImaIo>(&mut self, io: &mut IO, string: &str) -> Result<(), ImaExecutionError>;
     /// Write a newline character to the standard output.
-    fn wnl<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError>;
+    fn wnl<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Read a UTF-8 character from the standard input and store it's code in the register R1.
     /// This will consume any unconsumed character and will not wait for a newline character.
-    fn rutf8<R: BufRead>(&mut self, input: &mut R) -> Result<(), ImaExecutionError>;
+    fn rutf8<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Write the UTF-8 character in the register R1 to the standard output.
-    fn wutf8<W: Write>(&mut self, output: &mut W) -> Result<(), ImaExecutionError>;
+    fn wutf8<IO: ImaIo>(&mut self, io: &mut IO) -> Result<(), ImaExecutionError>;
     /// Add the value to the stack pointer.
     /// 
     /// SP <- V\[SP\] + value
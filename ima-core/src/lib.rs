@@ -3,22 +3,41 @@
 
 pub use ima::{
     IMA,
+    control_flow::ImaExitStatus,
+    io::{
+        ImaIo,
+        ImaInput,
+        StreamIo,
+        ScriptedIo,
+    },
+    observer::{
+        ImaObserver,
+        NoObserver,
+    },
+    step::StepOutcome,
+    history::CycleOutcome,
     options::{
         ImaOptions,
         ImaRunMode,
         OptionParsingError,
     },
     error::ImaError,
-    zones::program::{
-        ReleaseModeProgram,
-        DebugModeProgram,
-    }
+    trace_diff::{
+        trace_diff,
+        TracedRun,
+    },
+    zones::program::Program,
+};
+#[cfg(feature = "serde")]
+pub use ima::snapshot::{
+    Snapshot,
+    SnapshotError,
 };
 pub use parser::{
     error::ParserError,
     parser::{
         parse,
-        parse_debug,
+        parse_with_registers,
     },
 };
 
@@ -29,20 +48,110 @@ mod instructions;
 /// export all the types for further use.
 pub mod complete {
     pub use crate::{
-        parser::parser::Line,
+        parser::{
+            parser::Line,
+            label::Label,
+        },
         instructions::{
             Instruction,
             Instructions,
         },
         ima::{
             IMA,
-            control_flow::ImaControlFlow,
+            backtrace::{
+                Backtrace,
+                CallFrame,
+            },
+            callee_saved::{
+                CalleeSavedChecker,
+                ClobberedRegisters,
+            },
+            clock::{
+                ClockMode,
+                DEFAULT_VIRTUAL_FREQUENCY,
+            },
+            control_flow::{
+                ImaControlFlow,
+                ImaExitStatus,
+            },
             options::{
                 ImaOptions,
                 ImaRunMode,
                 OptionParsingError,
             },
             cycles::CycleCost,
+            decoded::{
+                Condition,
+                DecodedInstruction,
+                DecodedProgram,
+                Op,
+                Operands,
+            },
+            limits::{
+                ExecutionLimits,
+                ExecutionCounters,
+            },
+            io::{
+                ImaIo,
+                ImaInput,
+                StreamIo,
+                ScriptedIo,
+            },
+            history::{
+                History,
+                CycleOutcome,
+                DEFAULT_HISTORY,
+            },
+            loop_detection::LoopDetector,
+            observer::{
+                ImaObserver,
+                NoObserver,
+            },
+            profiler::{
+                Profiler,
+                InstructionProfile,
+                FunctionProfile,
+            },
+            rounding::RoundingMode,
+            step::StepOutcome,
+            trace::{
+                TraceFilter,
+                TraceEntry,
+                Tracer,
+                TraceOutput,
+            },
+            sanitizer::HeapSanitizer,
+            shadow_stack::{
+                ShadowStack,
+                FrameCorruption,
+                FrameSlot,
+            },
+            stack_budget::StackBudgetChecker,
+            uninitialized::ValueSource,
+            warning::{
+                CheckMode,
+                ImaWarning,
+                WarningKind,
+            },
+            watchpoint::{
+                Comparison,
+                WatchAccess,
+                WatchCondition,
+                WatchConditionParseError,
+                WatchHit,
+                WatchMode,
+                WatchTarget,
+                WatchTargetParseError,
+                Watchpoint,
+                WatchpointParseError,
+                Watchpoints,
+            },
+            trace_diff::{
+                trace_diff,
+                TracedRun,
+                TraceDivergence,
+                StateDifference,
+            },
             data_type::DataType,
             error::{
                 ImaError,
@@ -50,28 +159,56 @@ pub mod complete {
             },
             zones::{
                 program::{
-                    ReleaseModeProgram,
-                    DebugModeProgram,
                     CodeAddr,
-                    RunMode,
+                    DebugInfo,
                     Program,
+                    DisplayLine,
+                    SourceLine,
+                    SourceLocation,
                 },
                 memory::{
                     Memory,
+                    MemoryState,
                     Pointer,
                     StackPointer,
                     HeapPointer,
+                    UsedMemory,
+                    allocator::{
+                        Allocator,
+                        AllocatorKind,
+                        AllocatorState,
+                        AllocatorStats,
+                        BuddyAllocator,
+                        Fit,
+                        FreeListAllocator,
+                        LinearAllocator,
+                    },
+                },
+                registers::{
+                    Registers,
+                    DEFAULT_REGISTER_COUNT,
+                    MIN_REGISTER_COUNT,
+                    MAX_REGISTER_COUNT,
                 },
-                registers::Registers,
                 flags::Flags,
             },
             address_modes::{
                 DVAL,
                 DADR,
+                Address,
+                Operand,
+                Register,
                 RegisterIndex,
             }
         },
     };
+    #[cfg(feature = "serde")]
+    pub use crate::ima::snapshot::{
+        Snapshot,
+        SnapshotError,
+        SNAPSHOT_VERSION,
+        program_fingerprint,
+    };
 }
 
 #[cfg(test)]
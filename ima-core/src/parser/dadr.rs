@@ -48,7 +48,7 @@ impl Display for DadrParseError {
 
 impl DADR {
     /// Parse a string to a DADR
-    pub(super) fn from_str(s: &str) -> Result<Self, DadrParseError> {
+    pub(crate) fn from_str(s: &str) -> Result<Self, DadrParseError> {
         let s = s.trim();
         // todo : lazy static regex to avoid creating them for each call
 
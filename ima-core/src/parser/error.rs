@@ -2,6 +2,8 @@
 
 use std::{fmt::Display, error::Error};
 
+use crate::ima::address_modes::RegisterIndex;
+
 use super::{
     dadr::DadrParseError,
     dval::DvalParseError,
@@ -44,6 +46,11 @@ pub enum ParserErrorType {
     RegIndexParseError(RegIndexParseError),
     /// The given string can't be parsed as an integer.
     IntParseError(String),
+    /// The register does not exist on a machine with the given register count.
+    RegisterOutOfRange {
+        register: RegisterIndex,
+        count: usize,
+    },
 }
 
 impl From<DadrParseError> for ParserErrorType {
@@ -73,6 +80,7 @@ impl Display for ParserErrorType {
             ParserErrorType::DvalParseError(e) => write!(f, "{}", e),
             ParserErrorType::RegIndexParseError(e) => write!(f, "{}", e),
             ParserErrorType::IntParseError(e) => write!(f, "Invalid integer: {}", e),
+            ParserErrorType::RegisterOutOfRange { register, count } => write!(f, "Register {} does not exist, the machine has {} registers", register, count),
         }
     }
 }
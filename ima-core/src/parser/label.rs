@@ -44,8 +44,8 @@ impl LabelMap {
     }
 
     /// Fill the label map with the labels found in the given lines.
-    /// in debug mode, will also count empty, comment and label-only lines.
-    pub fn scan_labels<'a>(&mut self, lines: &Vec<Vec<Token>>, debug_mode: bool) {
+    /// Only the lines with an instruction are counted, so labels point to the compacted instructions.
+    pub fn scan_labels<'a>(&mut self, lines: &Vec<Vec<Token>>) {
         
         let mut line_number = 0;
         for line in lines.iter() {
@@ -57,7 +57,7 @@ impl LabelMap {
                     _ => {},
                 }
             }
-            if debug_mode || contains_instr {
+            if contains_instr {
                 line_number += 1;
             }
         }
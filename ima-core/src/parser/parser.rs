@@ -2,10 +2,15 @@
 
 use crate::{
     instructions::Instruction,
-    ima::zones::program::{
-        Program,
-        ReleaseModeProgram,
-        DebugModeProgram
+    ima::zones::{
+        program::{
+            CodeAddr,
+            DebugInfo,
+            Program,
+            SourceLine,
+            SourceLocation,
+        },
+        registers::DEFAULT_REGISTER_COUNT,
     }
 };
 use super::{
@@ -47,8 +52,9 @@ impl Line {
 
 impl Line {
     /// Parse a text line into a program line.
-    /// This will attempt to parse labels and instructions.
-    fn from_tokens(tokens: &Vec<Token>, label_map: &LabelMap) -> Result<Self, ParserErrorType> {
+    /// This will attempt to parse labels and instructions,
+    /// and check the instruction only uses registers of a machine with the given register count.
+    fn from_tokens(tokens: &Vec<Token>, label_map: &LabelMap, register_count: usize) -> Result<Self, ParserErrorType> {
         
         let mut line = Line::empty();
 
@@ -60,53 +66,59 @@ impl Line {
             }
         }
 
+        if let Some(instruction) = &line.instruction {
+            if let Some(register) = instruction.registers().into_iter().find(|r| usize::from(r.0) >= register_count) {
+                return Err(ParserErrorType::RegisterOutOfRange { register, count: register_count });
+            }
+        }
+
         Ok(line)
     }
 }
 
 
-/// Parse an input string to a program in release mode.
-pub fn parse(input: &str) -> Result<Program<ReleaseModeProgram>, ParserError> {
-    let mut result = Vec::new();
-    let lines = lex(input).map_err(|_e| ParserError::LexerError)?;
-
-    let mut label_map = LabelMap::new();
-    label_map.scan_labels(&lines, false);
-
-    for (line, tokens) in lines.into_iter().enumerate() {
-        let line = Line::from_tokens(&tokens, &label_map).map_err(|e|
-            ParserError::InnerParserError {
-                line: line + 1, // line number starts at 1, and enumerate starts at 0
-                error: e,
-            }
-        )?;
-        if let Some(ins) = line.instruction {
-            result.push(ins);
-        }
-    }
-
-    let program = Program::new(result);
-    Ok(program)
+/// Parse an input string to a program, for a machine with 16 registers.
+pub fn parse(input: &str) -> Result<Program, ParserError> {
+    parse_with_registers(input, DEFAULT_REGISTER_COUNT)
 }
 
-/// Parse an input string to a program in debug mode.
-pub fn parse_debug(input: &str) -> Result<Program<DebugModeProgram>, ParserError> {
+/// Parse an input string to a program, for a machine with the given number of registers.
+/// The program keeps the debug info of all the source lines, so it can be run or debugged.
+pub fn parse_with_registers(input: &str, register_count: usize) -> Result<Program, ParserError> {
     let mut result = Vec::new();
+    let mut source_lines = Vec::new();
+    let mut line_table = Vec::new();
     let lines = lex(input).map_err(|_e| ParserError::LexerError)?;
 
     let mut label_map = LabelMap::new();
-    label_map.scan_labels(&lines, true);
+    label_map.scan_labels(&lines);
 
-    for (line, tokens) in lines.into_iter().enumerate() {
-        let line = Line::from_tokens(&tokens, &label_map).map_err(|e|
+    let mut last_label = None;
+    for (line_number, tokens) in lines.into_iter().enumerate() {
+        let line = Line::from_tokens(&tokens, &label_map, register_count).map_err(|e|
             ParserError::InnerParserError {
-                line: line + 1, // line number starts at 1, and enumerate starts at 0
+                line: line_number + 1, // line number starts at 1, and enumerate starts at 0
                 error: e,
             }
         )?;
-        result.push(line)
+        if let Some(label) = line.labels.last() {
+            last_label = Some(label.clone());
+        }
+        let address = line.instruction.map(|ins| {
+            result.push(ins);
+            line_table.push(SourceLocation {
+                line: line_number as u32 + 1,
+                label: last_label.clone(),
+            });
+            result.len() as CodeAddr - 1
+        });
+        source_lines.push(SourceLine {
+            labels: line.labels,
+            instruction: address,
+            comment: line.comment,
+        });
     }
 
-    let program = Program::new_debug(result);
+    let program = Program::new(result, DebugInfo::new(source_lines, line_table));
     Ok(program)
-}
\ No newline at end of file
+}
@@ -18,22 +18,19 @@ impl Display for RegIndexParseError {
 }
 
 impl RegisterIndex {
-    /// Parse a string to a register index
-    pub(super) fn from_str(s: &str) -> Result<Self, RegIndexParseError> {
+    /// Parse a string to a register index.
+    /// Any index up to R255 is accepted: whether the machine has the register is checked by the parser.
+    pub(crate) fn from_str(s: &str) -> Result<Self, RegIndexParseError> {
         let s = s.trim();
         if s.is_empty() {
             return Err(RegIndexParseError{ from: s.to_string() });
         }
         let (r, n) = s.split_at(1);
-        if r.to_ascii_uppercase() != "R" {
+        if !r.eq_ignore_ascii_case("R") {
             return Err(RegIndexParseError{ from: s.to_string() });
         }
         let index = n.parse::<u8>().map_err(|_| RegIndexParseError{ from: s.to_string() })?;
-        if index < 16 {
-            Ok(RegisterIndex(index))
-        } else {
-            Err(RegIndexParseError{ from: s.to_string() })
-        }
+        Ok(RegisterIndex(index))
     }
 }
 
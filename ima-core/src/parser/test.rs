@@ -25,7 +25,8 @@ pub fn parse_register_index() {
     assert_eq!(Ok(RegisterIndex(15)), RegisterIndex::from_str("R15"), "Failed to parse register R15");
     assert!(RegisterIndex::from_str("").is_err(), "Parsed empty string as register");
     assert!(RegisterIndex::from_str("R").is_err(), "Parsed invalid register R");
-    assert!(RegisterIndex::from_str("R16").is_err(), "Parsed invalid register R16");
+    assert_eq!(Ok(RegisterIndex(31)), RegisterIndex::from_str("R31"), "Failed to parse register R31");
+    assert!(RegisterIndex::from_str("R256").is_err(), "Parsed invalid register R256");
     assert!(RegisterIndex::from_str("R-1").is_err(), "Parsed invalid register R-1");
 }
 
@@ -117,4 +118,14 @@ fn parse_instruction_add() {
     assert_eq!(Ok(Instruction::ADD(DVAL::DADR(DADR::OffsetIndirect { register: Register::SP, offset: -2 }), RegisterIndex(15))), Instruction::from_str("  ADD -2 ( SP ),   R15  ", &label_map), "Failed to parse ADD -2 ( SP ), R15");
     assert_eq!(Ok(Instruction::ADD(DVAL::Immediate(DataType::Int(12345)), RegisterIndex(7))), Instruction::from_str("ADD #12345, R7", &label_map), "Failed to parse ADD #12345, R7");
     assert_eq!(Ok(Instruction::ADD(DVAL::DADR(DADR::OffsetAndDisplacedIndirect { address_register: Register::R(RegisterIndex(2)), register_offset: RegisterIndex(14), immediate_offset: -167 }), RegisterIndex(1))), Instruction::from_str("ADD -167(R2, R14), R1", &label_map), "Failed to parse ADD -167(R2, R14), R1");
-}
\ No newline at end of file
+}
+#[test]
+fn parse_line_table() {
+    use crate::{parse, ima::zones::program::SourceLocation, parser::label::Label};
+
+    let program = parse("; comment\n    LOAD #1, R1\nlabel:\n\n    WINT\n    HALT").expect("Failed to parse program");
+    assert_eq!(program.location(0), Some(SourceLocation { line: 2, label: None }), "Wrong location for LOAD");
+    assert_eq!(program.location(1), Some(SourceLocation { line: 5, label: Some(Label("label".to_string())) }), "Wrong location for WINT");
+    assert_eq!(program.location(2), Some(SourceLocation { line: 6, label: Some(Label("label".to_string())) }), "Wrong location for HALT");
+    assert_eq!(program.location(3), None, "Location found out of the program");
+}
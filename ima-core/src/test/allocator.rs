@@ -0,0 +1,148 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::collections::BTreeSet;
+
+use crate::{parse, IMA, ImaObserver, ImaOptions, ImaRunMode, ImaExitStatus, complete::{AllocatorKind, HeapPointer, Memory}};
+
+const KINDS: [AllocatorKind; 4] = [AllocatorKind::Linear, AllocatorKind::FirstFit, AllocatorKind::BestFit, AllocatorKind::Buddy];
+
+fn index(ptr: Option<HeapPointer>) -> usize {
+    ptr.expect("Allocation failed").as_index()
+}
+
+#[test]
+fn allocate_and_free() {
+    for kind in KINDS {
+        let mut memory = Memory::with_allocator(64, 0, kind);
+        let a = memory.allocate(3).unwrap();
+        let b = memory.allocate(5).unwrap();
+        assert_ne!(a, b, "{kind:?}");
+        assert_eq!(memory.get_block(b.offset(4).unwrap()), Some((b, 5)), "{kind:?}");
+        assert!(memory.free(b).is_some(), "{kind:?}");
+        assert!(memory.free(b).is_none(), "{kind:?}");
+        assert!(memory.allocate(65).is_none(), "{kind:?}");
+        assert!(memory.free(a).is_some(), "{kind:?}");
+        // everything was merged back
+        assert_eq!(index(memory.allocate(64)), 0, "{kind:?}");
+    }
+}
+
+#[test]
+fn fits() {
+    // free blocks of 4 words at 0 and of 2 words at 6, between live blocks
+    let setup = |kind| {
+        let mut memory = Memory::with_allocator(16, 0, kind);
+        let blocks = [4, 2, 2, 2].map(|size| memory.allocate(size).unwrap());
+        memory.free(blocks[0]).unwrap();
+        memory.free(blocks[2]).unwrap();
+        memory
+    };
+    assert_eq!(index(setup(AllocatorKind::FirstFit).allocate(2)), 0);
+    assert_eq!(index(setup(AllocatorKind::BestFit).allocate(2)), 6);
+    assert_eq!(index(setup(AllocatorKind::Linear).allocate(2)), 0);
+}
+
+#[test]
+fn buddy_splits_and_merges() {
+    let mut memory = Memory::with_allocator(16, 0, AllocatorKind::Buddy);
+    let a = memory.allocate(3).unwrap();
+    // 3 words take a block of 4, so the next one starts right after
+    assert_eq!(index(memory.allocate(1)), 4);
+    assert_eq!(index(memory.allocate(8)), 8);
+    assert!(memory.allocate(4).is_none());
+    memory.free(a).unwrap();
+    // the buddy of the 1 word block was left free, and is the smallest fit
+    assert_eq!(index(memory.allocate(2)), 6);
+    assert_eq!(index(memory.allocate(2)), 0);
+}
+
+#[test]
+fn stats() {
+    for kind in KINDS {
+        let mut memory = Memory::with_allocator(16, 0, kind);
+        let blocks = [4, 4, 4, 4].map(|size| memory.allocate(size).unwrap());
+        memory.free(blocks[0]).unwrap();
+        memory.free(blocks[2]).unwrap();
+        assert!(memory.allocate(32).is_none());
+        let stats = memory.allocator_stats();
+        assert_eq!((stats.allocations, stats.failed_allocations, stats.frees), (4, 1, 2), "{kind:?}");
+        assert_eq!((stats.used, stats.peak), (8, 16), "{kind:?}");
+        // two free blocks of 4 words: the largest is half the free space
+        assert_eq!(stats.fragmentation, 0.5, "{kind:?}");
+    }
+}
+
+/// Allocates and frees in a loop, then writes the address of a last block.
+const CHURN: &str = "\
+    LOAD #0, R3
+loop:
+    NEW #3, R2
+    NEW #5, R4
+    DEL R2
+    DEL R4
+    ADD #1, R3
+    CMP #20, R3
+    BLT loop
+    NEW #2, R2
+    LEA 0(R2), R1
+    HALT
+";
+
+/// Records the address of every block allocated.
+#[derive(Default)]
+struct Blocks(Vec<HeapPointer>);
+
+impl ImaObserver for Blocks {
+    fn allocate(&mut self, block: HeapPointer, _size: usize) {
+        self.0.push(block);
+    }
+}
+
+fn run_churn(kind: AllocatorKind, sanitize_heap: bool) -> IMA<Blocks> {
+    let program = parse(CHURN).expect("Unable to parse test program");
+    let options = ImaOptions {
+        allocator: kind,
+        sanitize_heap,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::with_observer(program, options, Blocks::default());
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    assert_eq!(ima.run(&mut input, &mut output).unwrap(), ImaExitStatus::Halted, "{kind:?}");
+    ima
+}
+
+#[test]
+fn freed_blocks_are_reused() {
+    for kind in KINDS {
+        let ima = run_churn(kind, false);
+        let stats = ima.allocator_stats();
+        assert_eq!((stats.allocations, stats.frees, stats.failed_allocations), (41, 40, 0), "{kind:?}");
+        assert_eq!(stats.used, 2, "{kind:?}");
+        assert!(ima.observer().0.iter().collect::<BTreeSet<_>>().len() < 41, "{kind:?}");
+    }
+}
+
+#[test]
+fn sanitizer_never_reuses_blocks() {
+    for kind in KINDS {
+        let ima = run_churn(kind, true);
+        assert_eq!(ima.observer().0.iter().collect::<BTreeSet<_>>().len(), 41, "{kind:?}");
+    }
+}
+
+#[test]
+fn stats_mode_output() {
+    let program = parse(CHURN).expect("Unable to parse test program");
+    let options = ImaOptions {
+        run_mode: ImaRunMode::Stats,
+        allocator: AllocatorKind::FirstFit,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    ima.run(&mut input, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Heap allocations: 41 (0 failed), 40 frees\nPeak heap usage: 8 words\n"), "{output}");
+}
@@ -0,0 +1,42 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, complete::Label};
+
+#[test]
+fn backtrace_on_stack_overflow() {
+    let source_code = "\
+    BSR main
+    HALT
+main:
+    BSR rec
+    RTS
+rec:
+    PUSH R1
+    BSR rec
+    RTS
+";
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        stack_size: 20,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    match ima.run(&mut input, &mut output) {
+        Err(ImaError::ExecutionError { backtrace, .. }) => {
+            assert!(!backtrace.truncated, "Backtrace should not be truncated");
+            let outermost = &backtrace.frames[backtrace.frames.len() - 1];
+            assert_eq!(outermost.callee, Some(Label("main".to_string())));
+            assert_eq!(outermost.call_site.as_ref().map(|l| l.line), Some(1));
+            let first_recursion = &backtrace.frames[backtrace.frames.len() - 2];
+            assert_eq!(first_recursion.callee, Some(Label("rec".to_string())));
+            assert_eq!(first_recursion.call_site.as_ref().map(|l| l.line), Some(4));
+            for frame in &backtrace.frames[..backtrace.frames.len() - 2] {
+                assert_eq!(frame.callee, Some(Label("rec".to_string())));
+                assert_eq!(frame.call_site.as_ref().map(|l| l.line), Some(8));
+            }
+        },
+        other => panic!("Expected a stack overflow, got {other:?}"),
+    }
+}
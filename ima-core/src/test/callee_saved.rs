@@ -0,0 +1,69 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus, complete::{CheckMode, ImaExecutionError, Label, RegisterIndex, WarningKind}};
+
+fn run(source_code: &str, mode: CheckMode, registers: Vec<RegisterIndex>) -> (IMA, Result<ImaExitStatus, ImaError>) {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        callee_saved_check: mode,
+        callee_saved_registers: registers,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    let result = ima.run(&mut input, &mut output);
+    (ima, result)
+}
+
+const PROGRAM: &str = "\
+    LOAD #1, R2
+    LOAD #2, R3
+    BSR saves
+    BSR clobbers
+    HALT
+saves:
+    PUSH R2
+    LOAD #5, R2
+    LOAD #5, R1
+    POP R2
+    RTS
+clobbers:
+    LOAD #5, R3
+    LOAD #5, R4
+    RTS
+";
+
+#[test]
+fn clobbered_registers() {
+    let (ima, result) = run(PROGRAM, CheckMode::Warn, (2..16).map(RegisterIndex).collect());
+    assert!(result.is_ok());
+    match ima.warnings() {
+        [warning] => {
+            match &warning.kind {
+                WarningKind::RegistersClobbered(clobbered) => {
+                    assert_eq!(clobbered.callee, Some(Label("clobbers".to_string())));
+                    assert_eq!(clobbered.call_site, Some(4));
+                    assert_eq!(clobbered.registers, vec![RegisterIndex(3), RegisterIndex(4)]);
+                },
+                other => panic!("Expected clobbered registers, got {other:?}"),
+            }
+            assert_eq!(warning.line, Some(15));
+        },
+        other => panic!("Expected a single warning, got {other:?}"),
+    }
+}
+
+#[test]
+fn configured_registers() {
+    let (_, result) = run(PROGRAM, CheckMode::Strict, vec![RegisterIndex(2), RegisterIndex(5)]);
+    assert!(result.is_ok());
+    let (_, result) = run(PROGRAM, CheckMode::Strict, vec![RegisterIndex(4)]);
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::RegistersClobbered(clobbered), line, .. }) => {
+            assert_eq!(clobbered.registers, vec![RegisterIndex(4)]);
+            assert_eq!(line, Some(15));
+        },
+        other => panic!("Expected clobbered registers, got {other:?}"),
+    }
+}
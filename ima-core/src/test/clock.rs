@@ -0,0 +1,50 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaExitStatus, complete::ClockMode};
+
+/// Writes the result of CLK, then the one of SCLK.
+const CLOCKS: &str = "\
+    LOAD #1, R2
+    MUL #1, R2
+    CLK
+    LOAD R0, R1
+    WFLOAT
+    WNL
+    SCLK
+    WINT
+    HALT
+";
+
+fn run(source_code: &str, clock: ClockMode) -> String {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        clock,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    assert_eq!(ima.run(&mut input, &mut output).unwrap(), ImaExitStatus::Halted);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn virtual_clock() {
+    // CLK comes after 26 cycles (LOAD #1 and MUL #1), and SCLK after 74
+    assert_eq!(run(CLOCKS, ClockMode::Virtual { frequency: 4 }), "6.5\n18");
+    assert_eq!(run(CLOCKS, ClockMode::Virtual { frequency: 4 }), run(CLOCKS, ClockMode::Virtual { frequency: 4 }));
+}
+
+#[test]
+fn fixed_clock() {
+    assert_eq!(run(CLOCKS, ClockMode::Fixed { epoch: 720_000_000 }), "0\n720000000");
+}
+
+#[test]
+fn parse_clock_mode() {
+    assert_eq!("real".parse(), Ok(ClockMode::Real));
+    assert_eq!("virtual:1000".parse(), Ok(ClockMode::Virtual { frequency: 1000 }));
+    assert_eq!("fixed:-5".parse(), Ok(ClockMode::Fixed { epoch: -5 }));
+    assert!("virtual:0".parse::<ClockMode>().is_err());
+    assert!("fixed".parse::<ClockMode>().is_err());
+}
@@ -0,0 +1,87 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::parse;
+use crate::complete::{
+    Address, CycleCost, DataType, DecodedInstruction, DecodedProgram, Flags, Op, Operand, Operands, Register, RegisterIndex,
+};
+
+const PROGRAM: &str = "\
+start:
+    LOAD 2(GB), R1
+    LOAD -1(R2, R3), R1
+    ; a comment line
+    LOAD start, R2
+    STORE R1, 0(LB)
+    WSTR \"hello\"
+    BEQ start
+    SGT R0
+    HALT
+";
+
+#[test]
+fn operands_are_flattened() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let decoded = DecodedProgram::decode(&program);
+    let op = |pc| decoded.get(pc).map(|decoded| decoded.op.clone());
+
+    assert_eq!(op(0), Some(Op::LOAD(
+        Operand::Memory(Address { base: Register::GB, index: None, offset: 2 }),
+        RegisterIndex(1),
+    )));
+    assert_eq!(op(1), Some(Op::LOAD(
+        Operand::Memory(Address { base: Register::R(RegisterIndex(2)), index: Some(RegisterIndex(3)), offset: -1 }),
+        RegisterIndex(1),
+    )));
+    assert_eq!(op(2), Some(Op::LOAD(Operand::Immediate(DataType::CodeAddr(0)), RegisterIndex(2))));
+    assert_eq!(op(3), Some(Op::STORE(RegisterIndex(1), Address { base: Register::LB, index: None, offset: 0 })));
+    assert_eq!(op(4), Some(Op::WSTR("HELLO".to_string())));
+    assert_eq!(op(8), None);
+}
+
+#[test]
+fn operands_list_the_accesses() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let decoded = DecodedProgram::decode(&program);
+    let operands = |pc| decoded.get(pc).map(|decoded| decoded.op.operands()).unwrap_or_default();
+
+    let indexed = operands(1);
+    assert_eq!(indexed.written, Some(RegisterIndex(1)));
+    assert_eq!(indexed.registers_read(), vec![RegisterIndex(2), RegisterIndex(3)]);
+    assert!(!indexed.computes);
+    let store = operands(3);
+    assert_eq!(store.accessed(), Some(Address { base: Register::LB, index: None, offset: 0 }));
+    assert_eq!(store.registers_read(), vec![RegisterIndex(1)]);
+    assert!(store.computes);
+    assert_eq!(operands(4), Operands::default());
+    assert_eq!(operands(6).written, Some(RegisterIndex(0)));
+
+    let fma = Op::FMA(Operand::Register(RegisterIndex(2)), RegisterIndex(3)).operands();
+    assert_eq!(fma.registers_read(), vec![RegisterIndex(2), RegisterIndex(3), RegisterIndex(0)]);
+    let lea = Op::LEA(Address { base: Register::R(RegisterIndex(4)), index: None, offset: 1 }, RegisterIndex(5)).operands();
+    assert_eq!(lea.accessed(), None);
+    assert_eq!(lea.registers_read(), vec![RegisterIndex(4)]);
+}
+
+#[test]
+fn cycle_costs_match_the_instructions() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let decoded = DecodedProgram::decode(&program);
+    let mut all_flags = vec![Flags::new()];
+    for (dval, rm) in [(0, 0), (1, 0), (0, 1)] {
+        let mut flags = Flags::new();
+        flags.set_cmp_int(dval, rm);
+        all_flags.push(flags.clone());
+        flags.set_ov(true);
+        all_flags.push(flags);
+    }
+
+    for pc in 0..8 {
+        let decoded = decoded.get(pc).expect("Missing decoded instruction");
+        let instruction = program.instruction(pc).expect("Missing instruction");
+        for flags in all_flags.iter() {
+            assert_eq!(decoded.cycle_cost(flags), instruction.cycle_cost(flags), "{}", instruction);
+        }
+    }
+    let beq = DecodedInstruction::decode(program.instruction(5).unwrap());
+    assert_eq!(beq.cycles, 6);
+}
@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 
-use crate::{parse, IMA, ImaOptions};
+use crate::{parse, IMA, ImaExitStatus, ImaOptions};
 
 #[test]
 fn full() {
@@ -41,5 +41,9 @@ fn run(path: &PathBuf) -> Result<(), String> {
     
     let res = ima.run(&mut input, &mut output);
     
-    res.map_err(|e| format!("{e:?}"))
+    match res {
+        Ok(ImaExitStatus::Halted) => Ok(()),
+        Ok(status) => Err(format!("{status:?}")),
+        Err(e) => Err(format!("{e:?}")),
+    }
 }
\ No newline at end of file
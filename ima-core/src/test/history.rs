@@ -0,0 +1,182 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaRunMode, ImaExitStatus, ScriptedIo, StepOutcome, CycleOutcome};
+use crate::complete::{AllocatorKind, AllocatorStats, DEFAULT_HISTORY};
+use crate::ima::loop_detection::LoopState;
+
+const PROGRAM: &str = "\
+    LOAD #0, R3
+boucle:
+    NEW #4, R1
+    STORE R3, 2(R1)
+    PUSH R1
+    BSR double
+    POP R2
+    LOAD R0, R1
+    WINT
+    DEL R2
+    ADD #1, R3
+    CMP #4, R3
+    BLT boucle
+    HALT
+double:
+    TSTO #1
+    LOAD -2(LB), R2
+    LOAD 2(R2), R0
+    MUL #2, R0
+    RTS
+";
+
+fn machine(allocator: AllocatorKind, history: usize) -> IMA {
+    let options = ImaOptions {
+        allocator,
+        history: Some(history),
+        sanitize_heap: true,
+        ..ImaOptions::default()
+    };
+    IMA::new(parse(PROGRAM).expect("Unable to parse test program"), options)
+}
+
+/// The state of the machine that stepping back must restore.
+struct State {
+    machine: LoopState,
+    counters: (usize, usize),
+    /// The statistics hold the free space of the allocator, so they tell if its bookkeeping is restored.
+    heap: AllocatorStats,
+}
+
+fn state(ima: &IMA) -> State {
+    let counters = ima.counters();
+    State {
+        machine: ima.loop_state(0),
+        counters: (counters.instructions, counters.cycles),
+        heap: ima.allocator_stats(),
+    }
+}
+
+/// Check that the machine is exactly in the given state.
+fn is_state(ima: &IMA, expected: &State) -> bool {
+    let counters = ima.counters();
+    ima.is_loop_state(&expected.machine, 0)
+        && (counters.instructions, counters.cycles) == expected.counters
+        && ima.allocator_stats() == expected.heap
+}
+
+#[test]
+fn step_back_undoes_every_step() {
+    for kind in [AllocatorKind::Linear, AllocatorKind::FirstFit, AllocatorKind::BestFit, AllocatorKind::Buddy] {
+        let mut ima = machine(kind, 1000);
+        let mut io = ScriptedIo::default();
+        let mut states = vec![state(&ima)];
+        while ima.step(&mut io).unwrap() == StepOutcome::Continued {
+            states.push(state(&ima));
+        }
+        let last = state(&ima);
+        assert_eq!(io.take_output(), b"0246", "{kind:?}");
+
+        // back to the start, through every step
+        assert!(ima.step_back(), "{kind:?}");
+        while let Some(expected) = states.pop() {
+            assert!(is_state(&ima, &expected), "{kind:?}");
+            ima.step_back();
+        }
+        assert!(ima.history().unwrap().is_empty(), "{kind:?}");
+        assert!(!ima.step_back(), "{kind:?}");
+
+        // and forward again: the heap gives the same blocks
+        assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted), "{kind:?}");
+        assert!(is_state(&ima, &last), "{kind:?}");
+        assert_eq!(io.output(), b"0246", "{kind:?}");
+        assert!(ima.warnings().is_empty(), "{kind:?}");
+    }
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut ima = machine(AllocatorKind::Linear, 5);
+    let mut io = ScriptedIo::default();
+    ima.run_for(20, &mut io).unwrap();
+
+    assert_eq!(ima.history().unwrap().len(), 5);
+    for _ in 0..5 {
+        assert!(ima.step_back());
+    }
+    assert!(!ima.step_back());
+    assert_eq!(ima.counters().instructions, 15);
+}
+
+#[test]
+fn reverse_continue_to_breakpoints() {
+    let mut ima = machine(AllocatorKind::Linear, 1000);
+    let mut io = ScriptedIo::default();
+    // the breakpoint is on WINT
+    ima.set_breakpoint(8);
+    ima.set_breakpoint(15);
+    assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Breakpoint);
+    ima.remove_breakpoint(15);
+    assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Breakpoint);
+    assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Breakpoint);
+    let second = ima.counters().instructions;
+    assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Breakpoint);
+
+    assert!(ima.reverse_continue());
+    assert_eq!(ima.counters().instructions, second);
+    ima.remove_breakpoint(8);
+    assert!(!ima.reverse_continue());
+    assert_eq!(ima.counters().instructions, 0);
+}
+
+#[test]
+fn go_to_cycle() {
+    let mut ima = machine(AllocatorKind::Buddy, 1000);
+    let mut io = ScriptedIo::default();
+    let mut cycles = vec![0];
+    while ima.step(&mut io).unwrap() == StepOutcome::Continued {
+        cycles.push(ima.counters().cycles);
+    }
+    let end = ima.counters().cycles;
+
+    // the first instruction boundary at or after the cycle
+    let target = cycles[10] + 1;
+    assert_eq!(ima.go_to_cycle(target, &mut io).unwrap(), CycleOutcome::Reached);
+    assert_eq!(ima.counters().cycles, cycles[11]);
+    assert_eq!(ima.go_to_cycle(0, &mut io).unwrap(), CycleOutcome::Reached);
+    assert_eq!(ima.counters().instructions, 0);
+    assert_eq!(ima.go_to_cycle(target, &mut io).unwrap(), CycleOutcome::Reached);
+    assert_eq!(ima.counters().cycles, cycles[11]);
+    assert_eq!(ima.go_to_cycle(end + 1, &mut io).unwrap(), CycleOutcome::Unreachable);
+    assert_eq!(ima.counters().cycles, end);
+}
+
+#[test]
+fn history_depends_on_run_mode() {
+    let ima = IMA::new(parse(PROGRAM).unwrap(), ImaOptions::default());
+    assert!(ima.history().is_none());
+
+    let options = ImaOptions { run_mode: ImaRunMode::Debug, ..ImaOptions::default() };
+    let ima = IMA::new(parse(PROGRAM).unwrap(), options);
+    assert_eq!(ima.history().map(|history| history.capacity()), Some(DEFAULT_HISTORY));
+
+    let options = ImaOptions { run_mode: ImaRunMode::Debug, history: Some(0), ..ImaOptions::default() };
+    let ima = IMA::new(parse(PROGRAM).unwrap(), options);
+    assert!(ima.history().is_none());
+}
+
+#[test]
+fn step_back_from_an_error() {
+    let source = "\
+    LOAD #1, R1
+    PUSH R1
+    LOAD 0(R2), R1
+    HALT
+";
+    let options = ImaOptions { history: Some(10), ..ImaOptions::default() };
+    let mut ima = IMA::new(parse(source).unwrap(), options);
+    let mut io = ScriptedIo::default();
+    ima.run_for(2, &mut io).unwrap();
+    let before = state(&ima);
+
+    assert!(ima.step(&mut io).is_err());
+    assert!(ima.step_back());
+    assert!(is_state(&ima, &before));
+}
@@ -0,0 +1,90 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaExitStatus, ScriptedIo, StepOutcome, StreamIo};
+
+/// Reads an integer and writes it back, or writes -1 if the input is not one.
+const ECHO_INT: &str = "\
+    RINT
+    BOV invalid
+    WINT
+    HALT
+invalid:
+    LOAD #-1, R1
+    WINT
+    HALT
+";
+
+#[test]
+fn scripted_input_resumes() {
+    let program = parse(ECHO_INT).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = ScriptedIo::new("12");
+
+    // the line is not complete yet, so the instruction waits
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::NeedsInput);
+    assert_eq!(ima.counters().instructions, 0);
+
+    io.push_input("3\n");
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.take_output(), b"123");
+    assert!(io.output().is_empty());
+}
+
+#[test]
+fn invalid_input_sets_overflow() {
+    let program = parse(ECHO_INT).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = StreamIo::new(&b"twelve\n"[..], Vec::new());
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.into_inner().1, b"-1");
+}
+
+#[test]
+fn end_of_stream_is_invalid() {
+    let program = parse(ECHO_INT).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut output = Vec::new();
+    let status = ima.run(&mut &b""[..], &mut output).unwrap();
+    assert_eq!(status, ImaExitStatus::Halted);
+    assert_eq!(output, b"-1");
+}
+
+/// Reads a word and writes it back, or writes -1 if the input ends before the word.
+const ECHO_WORD: &str = "\
+    RUTF8
+    BOV invalid
+    WUTF8
+    HALT
+invalid:
+    LOAD #-1, R1
+    WINT
+    HALT
+";
+
+#[test]
+fn rutf8_reads_raw_words() {
+    let program = parse(ECHO_WORD).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = StreamIo::new(&b"abcd"[..], Vec::new());
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    let word = i32::from_ne_bytes(*b"abcd");
+    assert_eq!(io.into_inner().1, (word as u32).to_be_bytes());
+
+    // the word is pending until its 4 bytes are there
+    let program = parse(ECHO_WORD).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = ScriptedIo::new("ab");
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::NeedsInput);
+    io.push_input("cd");
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.output(), (word as u32).to_be_bytes());
+}
+
+#[test]
+fn truncated_word_is_invalid() {
+    let program = parse(ECHO_WORD).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = StreamIo::new(&b"ab"[..], Vec::new());
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.into_inner().1, b"-1");
+}
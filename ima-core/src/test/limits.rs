@@ -0,0 +1,53 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus, complete::ExecutionLimits};
+
+const WRITE_LOOP: &str = "\
+loop:
+    WSTR \"Hello\"
+    BRA loop
+";
+
+fn run(source_code: &str, limits: ExecutionLimits) -> (Result<ImaExitStatus, ImaError>, Vec<u8>) {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        limits,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    let result = ima.run(&mut input, &mut output);
+    (result, output)
+}
+
+#[test]
+fn max_instructions() {
+    let limits = ExecutionLimits { max_instructions: Some(10), ..ExecutionLimits::default() };
+    match run(WRITE_LOOP, limits) {
+        (Err(ImaError::InstructionLimitReached { counters, .. }), _) => assert_eq!(counters.instructions, 11),
+        (other, _) => panic!("Expected the instruction limit, got {other:?}"),
+    }
+}
+
+#[test]
+fn max_cycles() {
+    let limits = ExecutionLimits { max_cycles: Some(1000), ..ExecutionLimits::default() };
+    match run(WRITE_LOOP, limits) {
+        (Err(ImaError::CycleLimitReached { counters, .. }), _) => assert!(counters.cycles > 1000),
+        (other, _) => panic!("Expected the cycle limit, got {other:?}"),
+    }
+}
+
+#[test]
+fn max_output() {
+    let limits = ExecutionLimits { max_output_bytes: Some(12), ..ExecutionLimits::default() };
+    match run(WRITE_LOOP, limits) {
+        (Err(ImaError::OutputLimitReached { line, counters }), output) => {
+            assert_eq!(line, Some(2), "Output limit reached on the wrong line");
+            assert_eq!(counters.output_bytes, 15);
+            assert_eq!(output, b"HELLOHELLOHE", "Output should be capped to the limit");
+        },
+        (other, _) => panic!("Expected the output limit, got {other:?}"),
+    }
+}
@@ -0,0 +1,84 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus};
+
+fn run(source_code: &str, input: &[u8]) -> Result<ImaExitStatus, ImaError> {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        detect_infinite_loops: true,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(input);
+    let mut output = Vec::new();
+    ima.run(&mut input, &mut output)
+}
+
+#[test]
+fn detect_infinite_loop() {
+    let source_code = "\
+    LOAD #0, R1
+loop:
+    LOAD #1, R2
+    BRA loop
+";
+    match run(source_code, b"") {
+        Err(ImaError::InfiniteLoop { line, .. }) => assert_eq!(line, Some(4), "Loop detected on the wrong line"),
+        other => panic!("Expected an infinite loop, got {other:?}"),
+    }
+}
+
+#[test]
+fn finite_loop_is_not_detected() {
+    let source_code = "\
+    LOAD #0, R1
+loop:
+    ADD #1, R1
+    CMP #100, R1
+    BLT loop
+    HALT
+";
+    assert_eq!(run(source_code, b"").ok(), Some(ImaExitStatus::Halted), "Finite loop detected as infinite");
+}
+
+#[test]
+fn input_resets_loop_detection() {
+    let source_code = "\
+loop:
+    RINT
+    CMP #0, R1
+    BEQ loop
+    HALT
+";
+    assert_eq!(run(source_code, b"0\n0\n0\n1\n").ok(), Some(ImaExitStatus::Halted), "Loop waiting for input detected as infinite");
+}
+
+#[test]
+fn detect_loop_with_a_long_period() {
+    // R1 cycles through 0, 1, 2, 3, 4 after a few iterations to settle
+    let source_code = "\
+    LOAD #-20, R1
+loop:
+    ADD #1, R1
+    CMP #5, R1
+    BLT loop
+    LOAD #0, R1
+    BRA loop
+";
+    assert!(matches!(run(source_code, b""), Err(ImaError::InfiniteLoop { .. })), "Loop with a long period not detected");
+}
+
+#[test]
+fn long_finite_loop_is_not_detected() {
+    let source_code = "\
+    ADDSP #1
+    LOAD #0, R1
+loop:
+    ADD #1, R1
+    STORE R1, 1(GB)
+    CMP #100000, R1
+    BLT loop
+    HALT
+";
+    assert_eq!(run(source_code, b"").ok(), Some(ImaExitStatus::Halted), "Long finite loop detected as infinite");
+}
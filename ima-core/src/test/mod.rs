@@ -1,4 +1,28 @@
 /// Created by Virgile HENRY, 2023/09/28
 
 
-mod full;
\ No newline at end of file
+mod allocator;
+mod backtrace;
+mod callee_saved;
+mod clock;
+mod decoded;
+mod full;
+mod history;
+mod io;
+mod limits;
+mod loop_detection;
+mod observer;
+mod profiler;
+mod program;
+mod registers;
+mod rounding;
+mod sanitizer;
+mod shadow_stack;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod stack_budget;
+mod step;
+mod trace;
+mod trace_diff;
+mod uninitialized;
+mod watchpoint;
\ No newline at end of file
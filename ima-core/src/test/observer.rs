@@ -0,0 +1,108 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::collections::BTreeSet;
+
+use crate::{parse, IMA, ImaOptions, ImaObserver, complete::{CodeAddr, DataType, HeapPointer, Instruction, Pointer}};
+
+/// Records everything the machine reports.
+#[derive(Default)]
+struct Recorder {
+    executed: BTreeSet<CodeAddr>,
+    cycles: usize,
+    reads: Vec<(Pointer, DataType)>,
+    writes: Vec<(Pointer, DataType)>,
+    allocations: Vec<usize>,
+    frees: usize,
+    inputs: Vec<DataType>,
+    output: Vec<u8>,
+}
+
+impl ImaObserver for Recorder {
+    fn after_instruction(&mut self, pc: CodeAddr, _instruction: &Instruction, cycles: usize) {
+        self.executed.insert(pc);
+        self.cycles += cycles;
+    }
+    fn memory_read(&mut self, at: Pointer, value: DataType) {
+        self.reads.push((at, value));
+    }
+    fn memory_write(&mut self, at: Pointer, value: DataType) {
+        self.writes.push((at, value));
+    }
+    fn allocate(&mut self, _block: HeapPointer, size: usize) {
+        self.allocations.push(size);
+    }
+    fn free(&mut self, _block: HeapPointer) {
+        self.frees += 1;
+    }
+    fn input(&mut self, value: DataType) {
+        self.inputs.push(value);
+    }
+    fn output(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+    }
+}
+
+#[test]
+fn observed_run() {
+    let program = parse("\
+    RINT
+    PUSH R1
+    LOAD 1(GB), R2
+    NEW #3, R3
+    DEL R3
+    CMP #0, R1
+    BEQ skipped
+    WINT
+    HALT
+skipped:
+    HALT
+").expect("Unable to parse test program");
+    let mut ima = IMA::with_observer(program, ImaOptions::default(), Recorder::default());
+    let mut input = std::io::Cursor::new(b"7\n");
+    let mut output = Vec::new();
+    assert!(ima.run(&mut input, &mut output).is_ok());
+
+    let recorder = ima.observer();
+    assert_eq!(recorder.executed, (0..9).collect());
+    assert_eq!(recorder.cycles, ima.counters().cycles);
+    assert_eq!(recorder.inputs, vec![DataType::Int(7)]);
+    assert_eq!(recorder.writes.len(), 1);
+    assert_eq!(recorder.reads, recorder.writes);
+    assert_eq!(recorder.allocations, vec![3]);
+    assert_eq!(recorder.frees, 1);
+    assert_eq!(recorder.output, b"7");
+    assert_eq!(output, b"7");
+}
+
+#[test]
+fn several_observers() {
+    let program = parse("\
+    WSTR \"a\"
+    HALT
+").expect("Unable to parse test program");
+    let observers = (Recorder::default(), vec![Recorder::default(), Recorder::default()]);
+    let mut ima = IMA::with_observer(program, ImaOptions::default(), observers);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    assert!(ima.run(&mut input, &mut output).is_ok());
+    let (first, others) = ima.observer();
+    assert_eq!(first.executed.len(), 2);
+    assert!(others.iter().all(|other| other.output == first.output && other.executed == first.executed));
+}
+
+#[test]
+fn failed_free_is_not_observed() {
+    let program = parse("\
+    NEW #2, R3
+    DEL R3
+    DEL R3
+    HALT
+").expect("Unable to parse test program");
+    let mut ima = IMA::with_observer(program, ImaOptions::default(), Recorder::default());
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    assert!(ima.run(&mut input, &mut output).is_ok());
+    // the second DEL only sets OV
+    assert_eq!(ima.observer().frees, 1);
+    assert_eq!(ima.allocator_stats().frees, 1);
+}
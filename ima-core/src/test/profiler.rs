@@ -0,0 +1,44 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions};
+
+#[test]
+fn profile_subroutines() {
+    let source_code = "\
+    BSR f
+    BSR f
+    HALT
+f:
+    BSR g
+    RTS
+g:
+    LOAD #1, R1
+    RTS
+";
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        profile: true,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    ima.run(&mut input, &mut output).expect("Program should halt");
+
+    let profiler = ima.profiler().expect("Profiling should be enabled");
+    let functions = profiler.functions();
+    let main = functions[&None];
+    let f = functions[&Some(3)];
+    let g = functions[&Some(5)];
+    assert_eq!(f.calls, 2);
+    assert_eq!(g.calls, 2);
+    assert_eq!(main.inclusive_cycles, ima.counters().cycles);
+    assert_eq!(f.inclusive_cycles, f.exclusive_cycles + g.inclusive_cycles);
+    assert_eq!(g.inclusive_cycles, g.exclusive_cycles);
+    assert_eq!(profiler.instruction(5).executions, 2);
+
+    let mut folded = Vec::new();
+    ima.write_folded_stacks(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded.lines().any(|line| line.starts_with("<main>;f;g ")), "{folded}");
+}
@@ -0,0 +1,82 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaExitStatus, ScriptedIo, StepOutcome};
+use crate::complete::{DVAL, DisplayLine, Instruction, Label, SourceLocation};
+
+const PROGRAM: &str = "\
+; compte jusqu'a 3
+
+    LOAD #0, R1
+boucle:
+    ; un commentaire
+    ADD #1, R1
+    CMP #3, R1
+    BLT boucle
+    WINT
+    HALT
+";
+
+#[test]
+fn labels_point_to_compacted_instructions() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+
+    assert_eq!(program.instructions().len(), 6);
+    assert_eq!(program.instruction(3), Some(&Instruction::BLT(DVAL::Label(1))));
+    assert_eq!(program.location(1), Some(SourceLocation { line: 6, label: Some(Label("boucle".to_string())) }));
+}
+
+#[test]
+fn source_line_is_unknown_out_of_the_program() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+
+    assert_eq!(program.source_line(1), Some(6));
+    assert_eq!(program.source_line(6), None);
+    assert_eq!(DisplayLine(program.source_line(1)).to_string(), "line 6");
+    assert_eq!(DisplayLine(program.source_line(6)).to_string(), "an unknown line");
+}
+
+#[test]
+fn debug_info_keeps_all_lines() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let debug_info = program.debug_info();
+
+    assert_eq!(debug_info.lines.len(), 11);
+    assert_eq!(debug_info.lines[0].comment.as_deref(), Some("; compte jusqu'a 3"));
+    assert_eq!(debug_info.lines[3].labels, vec![Label("boucle".to_string())]);
+    assert_eq!(debug_info.lines[3].instruction, None);
+    assert_eq!(debug_info.lines[5].instruction, Some(1));
+    assert_eq!(debug_info.instruction_at_line(3), Some(1));
+    assert_eq!(debug_info.instruction_at_line(10), None);
+    assert_eq!(debug_info.line_of(1), Some(5));
+}
+
+#[test]
+fn breakpoints_on_lines() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    // the breakpoint is set on the next instruction, ADD
+    ima.set_breakpoint(4);
+    let mut io = ScriptedIo::default();
+
+    assert_eq!(ima.run_for(100, &mut io).unwrap(), StepOutcome::Breakpoint);
+    assert_eq!(ima.counters().instructions, 1);
+    assert_eq!(ima.run_for(100, &mut io).unwrap(), StepOutcome::Breakpoint);
+    assert_eq!(ima.counters().instructions, 4);
+
+    ima.remove_breakpoint(5);
+    assert_eq!(ima.run_for(100, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.output(), b"3");
+}
+
+#[test]
+fn debugger_counts_lines_from_one() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut output = Vec::new();
+    // the breakpoint on line 7 stops before CMP
+    ima.run_debug(&mut std::io::Cursor::new(b"s\nx\na 7\nc\np\nq\n"), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("3: LOAD #0, R1\n"), "{output}");
+    assert!(output.contains("6: ADD #1, R1\n"), "{output}");
+    assert!(output.contains(" -->  **   7| CMP #3, R1\n"), "{output}");
+}
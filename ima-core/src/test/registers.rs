@@ -0,0 +1,59 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, parse_with_registers, IMA, ImaOptions, ImaExitStatus, ParserError, parser::error::ParserErrorType};
+
+const HIGH_REGISTER: &str = "\
+    LOAD #20, R20
+    ADD #22, R20
+    LOAD R20, R1
+    WINT
+    HALT
+";
+
+fn run(source_code: &str, register_count: usize) -> Vec<u8> {
+    let program = parse_with_registers(source_code, register_count).expect("Unable to parse test program");
+    let options = ImaOptions {
+        register_count,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    assert_eq!(ima.run(&mut input, &mut output).unwrap(), ImaExitStatus::Halted);
+    output
+}
+
+#[test]
+fn more_registers() {
+    assert_eq!(run(HIGH_REGISTER, 32), b"42");
+}
+
+#[test]
+fn default_rejects_high_register() {
+    match parse(HIGH_REGISTER) {
+        Err(ParserError::InnerParserError { line: 1, error: ParserErrorType::RegisterOutOfRange { register, count: 16 } }) => assert_eq!(register.0, 20),
+        Err(other) => panic!("Expected R20 to be out of range, got {other}"),
+        Ok(_) => panic!("Expected R20 to be out of range"),
+    }
+}
+
+#[test]
+fn fewer_registers() {
+    assert_eq!(run("\
+    LOAD #3, R3
+    LOAD R3, R1
+    WINT
+    HALT
+", 4), b"3");
+    // registers used in addresses are checked too
+    assert!(parse_with_registers("    LOAD 1(R4), R1\n", 4).is_err());
+    assert!(parse_with_registers("    STORE R1, 0(GB, R4)\n", 4).is_err());
+}
+
+#[test]
+fn register_count_option() {
+    let args = |count: &str| ["ima", "--registers", count, "file.ass"].map(String::from).into_iter();
+    assert_eq!(ImaOptions::new(args("32")).unwrap().register_count, 32);
+    assert!(ImaOptions::new(args("1")).is_err());
+    assert!(ImaOptions::new(args("257")).is_err());
+}
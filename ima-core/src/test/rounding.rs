@@ -0,0 +1,69 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ScriptedIo};
+use crate::complete::RoundingMode;
+
+#[test]
+fn rounding_add() {
+    // 1 + 2^-30 is not representable, and lies between 1 and the next float
+    let tiny = 2.0f32.powi(-30);
+    assert_eq!(RoundingMode::ToNearest.add(1.0, tiny), 1.0);
+    assert_eq!(RoundingMode::Upward.add(1.0, tiny), 1.0 + f32::EPSILON);
+    assert_eq!(RoundingMode::Downward.add(1.0, tiny), 1.0);
+    assert_eq!(RoundingMode::TowardZero.add(-1.0, -tiny), -1.0);
+    assert_eq!(RoundingMode::Downward.add(-1.0, -tiny), -1.0 - f32::EPSILON);
+}
+
+#[test]
+fn rounding_mul_div() {
+    let third_down = RoundingMode::Downward.div(1.0, 3.0);
+    let third_up = RoundingMode::Upward.div(1.0, 3.0);
+    assert!(third_down < third_up, "Directed roundings of 1/3 should differ");
+    assert_eq!(RoundingMode::TowardZero.div(1.0, 3.0), third_down);
+    assert_eq!(RoundingMode::Upward.div(1.0, 4.0), 0.25, "Exact results should not be rounded");
+    // 3 times the rounded up third is 1 + 2^-25, between 1 and the next float
+    assert_eq!(RoundingMode::ToNearest.mul(third_up, 3.0), 1.0);
+    assert_eq!(RoundingMode::Downward.mul(third_up, 3.0), 1.0);
+    assert_eq!(RoundingMode::Upward.mul(third_up, 3.0), 1.0 + f32::EPSILON);
+}
+
+#[test]
+fn rounding_overflow() {
+    assert_eq!(RoundingMode::ToNearest.mul(f32::MAX, 2.0), f32::INFINITY);
+    assert_eq!(RoundingMode::TowardZero.mul(f32::MAX, 2.0), f32::MAX);
+    assert_eq!(RoundingMode::Downward.mul(f32::MAX, 2.0), f32::MAX);
+    assert_eq!(RoundingMode::Upward.mul(f32::MAX, -2.0), f32::MIN);
+}
+
+#[test]
+fn rounding_conversions() {
+    assert_eq!(RoundingMode::ToNearest.int(2.5), 2);
+    assert_eq!(RoundingMode::ToNearest.int(3.5), 4);
+    assert_eq!(RoundingMode::Upward.int(2.1), 3);
+    assert_eq!(RoundingMode::Downward.int(-2.1), -3);
+    assert_eq!(RoundingMode::TowardZero.int(-2.9), -2);
+    // out of range values saturate
+    assert_eq!(RoundingMode::TowardZero.int(1e10), i32::MAX);
+    assert_eq!(RoundingMode::Upward.int(-1e10), i32::MIN);
+    // 2^24 + 1 is not representable as a float
+    assert_eq!(RoundingMode::Upward.float(16_777_217), 16_777_218.0);
+    assert_eq!(RoundingMode::Downward.float(16_777_217), 16_777_216.0);
+}
+
+#[test]
+fn int_follows_the_mode() {
+    let modes = [
+        ("", "3-3"),
+        ("SETROUND_TONEAREST", "3-3"),
+        ("SETROUND_UPWARD", "3-2"),
+        ("SETROUND_DOWNWARD", "2-3"),
+        ("SETROUND_TOWARDZERO", "2-2"),
+    ];
+    for (setround, expected) in modes {
+        let source = format!("    {setround}\n    LOAD #2.75, R2\n    INT R2, R1\n    WINT\n    LOAD #-2.75, R2\n    INT R2, R1\n    WINT\n    HALT\n");
+        let mut ima = IMA::new(parse(&source).unwrap(), ImaOptions::default());
+        let mut io = ScriptedIo::default();
+        ima.run_for(100, &mut io).unwrap();
+        assert_eq!(io.output(), expected.as_bytes(), "{setround}");
+    }
+}
@@ -0,0 +1,110 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus, complete::{AllocatorKind, ImaExecutionError, WarningKind}};
+
+fn run(source_code: &str) -> (IMA, Result<ImaExitStatus, ImaError>) {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        sanitize_heap: true,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    let result = ima.run(&mut input, &mut output);
+    (ima, result)
+}
+
+#[test]
+fn use_after_free() {
+    let (_, result) = run("\
+    NEW #2, R1
+    DEL R1
+    NEW #2, R2
+    LOAD 1(R1), R3
+    HALT
+");
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::UseAfterFree { freed_at, .. }, line, .. }) => {
+            assert_eq!(freed_at, Some(2));
+            assert_eq!(line, Some(4));
+        },
+        other => panic!("Expected a use after free, got {other:?}"),
+    }
+}
+
+#[test]
+fn double_free() {
+    let (_, result) = run("\
+    NEW #2, R1
+    DEL R1
+    NEW #2, R2
+    DEL R1
+    HALT
+");
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::DoubleFree { freed_at, .. }, line, .. }) => {
+            assert_eq!(freed_at, Some(2));
+            assert_eq!(line, Some(4));
+        },
+        other => panic!("Expected a double free, got {other:?}"),
+    }
+}
+
+#[test]
+fn leaks() {
+    let (ima, result) = run("\
+    NEW #2, R1
+    NEW #3, R2
+    DEL R1
+    HALT
+");
+    assert!(result.is_ok());
+    match ima.warnings() {
+        [warning] => {
+            assert!(matches!(warning.kind, WarningKind::MemoryLeak { size: 3, .. }));
+            assert_eq!(warning.line, Some(2));
+        },
+        other => panic!("Expected a single leak, got {other:?}"),
+    }
+}
+
+#[test]
+fn quarantine_does_not_exhaust_the_heap() {
+    // 3000 blocks of 400 words: far more than the heap, but a single one is live at a time
+    let source_code = "\
+    LOAD #0, R3
+loop:
+    NEW #400, R1
+    BOV plein
+    STORE R3, 399(R1)
+    DEL R1
+    ADD #1, R3
+    CMP #3000, R3
+    BLT loop
+    HALT
+plein:
+    ERROR
+";
+    for allocator in [AllocatorKind::Linear, AllocatorKind::FirstFit, AllocatorKind::BestFit, AllocatorKind::Buddy] {
+        let program = parse(source_code).unwrap();
+        let options = ImaOptions { sanitize_heap: true, heap_size: 1000, allocator, ..ImaOptions::default() };
+        let mut ima = IMA::new(program, options);
+        let result = ima.run(&mut std::io::Cursor::new(b""), &mut Vec::new());
+        assert_eq!(result.ok(), Some(ImaExitStatus::Halted), "{allocator:?}");
+    }
+
+    // the last freed blocks are still caught
+    let (_, result) = run("\
+    LOAD #0, R3
+loop:
+    NEW #400, R1
+    DEL R1
+    ADD #1, R3
+    CMP #100, R3
+    BLT loop
+    LOAD 0(R1), R2
+    HALT
+");
+    assert!(matches!(result, Err(ImaError::ExecutionError { error: ImaExecutionError::UseAfterFree { .. }, .. })), "{result:?}");
+}
@@ -0,0 +1,74 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus, complete::{FrameSlot, ImaExecutionError, Label}};
+
+fn run(source_code: &str) -> Result<ImaExitStatus, ImaError> {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    ima.run(&mut input, &mut output)
+}
+
+#[test]
+fn intact_frames() {
+    let result = run("\
+    BSR f
+    HALT
+f:
+    LOAD #3, R1
+    STORE R1, 1(LB)
+    BSR g
+    RTS
+g:
+    RTS
+");
+    assert!(result.is_ok());
+}
+
+#[test]
+fn corrupted_return_address() {
+    let result = run("\
+    BSR f
+    HALT
+f:
+    LOAD #3, R1
+    STORE R1, -1(LB)
+    RTS
+");
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::CorruptedFrame(corruption), line, .. }) => {
+            assert_eq!(corruption.slot, FrameSlot::ReturnAddress);
+            assert_eq!(corruption.callee, Some(Label("f".to_string())));
+            assert_eq!(corruption.call_site, Some(1));
+            assert_eq!(corruption.written_at, Some(5));
+            assert_eq!(line, Some(6));
+        },
+        other => panic!("Expected a corrupted frame, got {other:?}"),
+    }
+}
+
+#[test]
+fn corrupted_caller_frame() {
+    let result = run("\
+    BSR f
+    HALT
+f:
+    BSR g
+    RTS
+g:
+    LOAD #0, R1
+    LEA -2(LB), R2
+    STORE R1, 0(R2)
+    RTS
+");
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::CorruptedFrame(corruption), line, .. }) => {
+            assert_eq!(corruption.slot, FrameSlot::SavedLb);
+            assert_eq!(corruption.callee, Some(Label("f".to_string())));
+            assert_eq!(corruption.written_at, Some(9));
+            assert_eq!(line, Some(5));
+        },
+        other => panic!("Expected a corrupted frame, got {other:?}"),
+    }
+}
@@ -0,0 +1,145 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaExitStatus, ScriptedIo, StepOutcome};
+use crate::complete::{AllocatorKind, CheckMode, DataType, RegisterIndex, Snapshot, SnapshotError, SNAPSHOT_VERSION};
+
+const PROGRAM: &str = "\
+    LOAD #0, R3
+boucle:
+    NEW #3, R1
+    NEW #5, R2
+    STORE R3, 0(R2)
+    DEL R1
+    LOAD 0(R2), R1
+    WINT
+    ADD #1, R3
+    CMP #6, R3
+    BLT boucle
+    HALT
+";
+
+fn machine(source: &str, allocator: AllocatorKind) -> IMA {
+    let options = ImaOptions { allocator, ..ImaOptions::default() };
+    IMA::new(parse(source).expect("Unable to parse test program"), options)
+}
+
+/// Save the snapshot to JSON and read it back.
+fn round_trip(snapshot: &Snapshot) -> Result<Snapshot, SnapshotError> {
+    let mut buffer = Vec::new();
+    snapshot.write(&mut buffer)?;
+    Snapshot::read(buffer.as_slice())
+}
+
+#[test]
+fn restored_machine_runs_the_same() {
+    for kind in [AllocatorKind::Linear, AllocatorKind::FirstFit, AllocatorKind::BestFit, AllocatorKind::Buddy] {
+        let mut reference = machine(PROGRAM, kind);
+        let mut io = ScriptedIo::default();
+        assert_eq!(reference.run_for(1000, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+
+        let mut first = machine(PROGRAM, kind);
+        let mut first_io = ScriptedIo::default();
+        assert_eq!(first.run_for(25, &mut first_io).unwrap(), StepOutcome::Continued);
+        let snapshot = round_trip(&first.snapshot()).expect("Unable to save and read the snapshot");
+
+        let mut second = machine(PROGRAM, kind);
+        second.restore(snapshot).expect("Unable to restore the snapshot");
+        assert_eq!(second.counters().instructions, 25);
+        let mut second_io = ScriptedIo::default();
+        assert_eq!(second.run_for(1000, &mut second_io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+
+        assert_eq!([first_io.output(), second_io.output()].concat(), io.output(), "{kind:?}");
+        assert_eq!(second.counters().cycles, reference.counters().cycles, "{kind:?}");
+        assert_eq!(second.allocator_stats(), reference.allocator_stats(), "{kind:?}");
+    }
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let ima = machine(PROGRAM, AllocatorKind::Buddy);
+    let mut buffer = Vec::new();
+    ima.snapshot().write(&mut buffer).unwrap();
+    let json = String::from_utf8(buffer).unwrap();
+    let json = json.replace(&format!("\"version\":{}", SNAPSHOT_VERSION), "\"version\":0");
+
+    assert!(matches!(Snapshot::read(json.as_bytes()), Err(SnapshotError::UnsupportedVersion(0))));
+    assert!(matches!(Snapshot::read(&b"{}"[..]), Err(SnapshotError::Format(_))));
+}
+
+#[test]
+fn other_programs_are_rejected() {
+    let snapshot = machine(PROGRAM, AllocatorKind::Linear).snapshot();
+    let mut other = machine("    WINT\n    HALT\n", AllocatorKind::Linear);
+
+    assert!(matches!(other.restore(snapshot), Err(SnapshotError::ProgramMismatch)));
+}
+
+#[test]
+fn other_sizes_are_rejected() {
+    let mut first = machine(PROGRAM, AllocatorKind::Linear);
+    first.run_for(25, &mut ScriptedIo::default()).unwrap();
+    let snapshot = first.snapshot();
+    let sized = |options: ImaOptions| IMA::new(parse(PROGRAM).expect("Unable to parse test program"), options);
+
+    // the callee-saved checker reads registers the snapshot does not have
+    let mut more_registers = sized(ImaOptions {
+        register_count: 32,
+        callee_saved_check: CheckMode::Strict,
+        callee_saved_registers: (2..=20).map(RegisterIndex).collect(),
+        ..ImaOptions::default()
+    });
+    assert!(matches!(
+        more_registers.restore(snapshot.clone()),
+        Err(SnapshotError::ConfigurationMismatch { setting: "registers", snapshot, machine }) if snapshot == "16" && machine == "32",
+    ));
+    assert_eq!(more_registers.run_for(1000, &mut ScriptedIo::default()).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+
+    let mut smaller_stack = sized(ImaOptions { stack_size: 100, ..ImaOptions::default() });
+    assert!(matches!(smaller_stack.restore(snapshot.clone()), Err(SnapshotError::ConfigurationMismatch { setting: "stack words", .. })));
+    let mut larger_heap = sized(ImaOptions { heap_size: 20000, ..ImaOptions::default() });
+    assert!(matches!(larger_heap.restore(snapshot.clone()), Err(SnapshotError::ConfigurationMismatch { setting: "heap words", .. })));
+    let mut buddy = machine(PROGRAM, AllocatorKind::Buddy);
+    assert!(matches!(
+        buddy.restore(snapshot),
+        Err(SnapshotError::ConfigurationMismatch { setting: "allocator", snapshot, machine }) if snapshot == "linear" && machine == "buddy",
+    ));
+}
+
+/// Change a field of the snapshot in its JSON, and read it back.
+fn tampered(snapshot: &Snapshot, field: &str, value: serde_json::Value) -> Result<Snapshot, SnapshotError> {
+    let mut json = serde_json::to_value(snapshot).unwrap();
+    json[field] = value;
+    Snapshot::read(json.to_string().as_bytes())
+}
+
+#[test]
+fn tampered_snapshots_are_rejected() {
+    for kind in [AllocatorKind::Linear, AllocatorKind::FirstFit, AllocatorKind::BestFit, AllocatorKind::Buddy] {
+        let mut first = machine(PROGRAM, kind);
+        first.run_for(25, &mut ScriptedIo::default()).unwrap();
+        let snapshot = first.snapshot();
+        let mut ima = machine(PROGRAM, kind);
+
+        // pointers use 31 bits
+        assert!(matches!(tampered(&snapshot, "sp", 4_000_000_000u32.into()), Err(SnapshotError::Format(_))), "{kind:?}");
+        let outside = tampered(&snapshot, "lb", 10_000.into()).unwrap();
+        assert!(matches!(ima.restore(outside), Err(SnapshotError::InvalidState(_))), "{kind:?}");
+        let past_program = tampered(&snapshot, "pc", 12.into()).unwrap();
+        assert!(matches!(ima.restore(past_program), Err(SnapshotError::InvalidState(_))), "{kind:?}");
+
+        // the live blocks are no longer in use
+        let mut freed = snapshot.clone();
+        freed.memory.heap.iter_mut().for_each(|word| *word = None);
+        assert!(matches!(ima.restore(freed), Err(SnapshotError::InvalidState(_))), "{kind:?}");
+        // the blocks overlap the free space
+        let mut used = snapshot.clone();
+        used.memory.heap.iter_mut().for_each(|word| *word = Some(DataType::Int(0)));
+        // the linear allocator finds the free space in the heap itself
+        assert_eq!(ima.restore(used).is_ok(), kind == AllocatorKind::Linear, "{kind:?}");
+
+        // the checks leave a valid snapshot alone: the last instruction is HALT
+        let pc = tampered(&snapshot, "pc", 10.into()).unwrap();
+        ima.restore(pc).expect("Unable to restore the snapshot");
+        assert_eq!(ima.run_for(10, &mut ScriptedIo::default()).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted), "{kind:?}");
+    }
+}
@@ -0,0 +1,72 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, ImaExitStatus, complete::{CheckMode, ImaExecutionError, WarningKind}};
+
+fn run(source_code: &str, mode: CheckMode) -> (IMA, Result<ImaExitStatus, ImaError>) {
+    let program = parse(source_code).expect("Unable to parse test program");
+    let options = ImaOptions {
+        tsto_check: mode,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    let result = ima.run(&mut input, &mut output);
+    (ima, result)
+}
+
+#[test]
+fn within_budget() {
+    let (ima, result) = run("\
+    TSTO #5
+    ADDSP #2
+    BSR f
+    PUSH R1
+    HALT
+f:
+    TSTO #1
+    PUSH R1
+    POP R1
+    RTS
+", CheckMode::Strict);
+    assert!(result.is_ok());
+    assert!(ima.warnings().is_empty());
+}
+
+#[test]
+fn past_budget() {
+    let (ima, result) = run("\
+    TSTO #2
+    ADDSP #2
+    BSR f
+    HALT
+f:
+    TSTO #1
+    PUSH R1
+    PUSH R1
+    SUBSP #2
+    RTS
+", CheckMode::Warn);
+    assert!(result.is_ok());
+    match ima.warnings() {
+        [caller, callee] => {
+            assert_eq!(caller.kind, WarningKind::StackBudgetExceeded { declared: Some(2) });
+            assert_eq!(caller.line, Some(3));
+            assert_eq!(callee.kind, WarningKind::StackBudgetExceeded { declared: Some(1) });
+            assert_eq!(callee.line, Some(8));
+        },
+        other => panic!("Expected two warnings, got {other:?}"),
+    }
+}
+
+#[test]
+fn missing_tsto() {
+    let (_, result) = run("\
+    PUSH R1
+    HALT
+", CheckMode::Strict);
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::StackBudgetExceeded { declared: None }, line, .. }) => assert_eq!(line, Some(1)),
+        other => panic!("Expected a missing TSTO, got {other:?}"),
+    }
+}
@@ -0,0 +1,91 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaExitStatus, ScriptedIo, StepOutcome};
+
+const PROGRAM: &str = "\
+    LOAD #1, R1
+    RINT
+    ADD #1, R1
+    WINT
+    HALT
+";
+
+#[test]
+fn step_until_halt() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = ScriptedIo::default();
+
+    assert_eq!(ima.step(&mut io).unwrap(), StepOutcome::Continued);
+    assert_eq!(ima.step(&mut io).unwrap(), StepOutcome::NeedsInput);
+    assert_eq!(ima.step(&mut io).unwrap(), StepOutcome::NeedsInput);
+
+    io.push_input("41\n");
+    assert_eq!(ima.run_for(2, &mut io).unwrap(), StepOutcome::Continued);
+    assert!(io.output().is_empty());
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.output(), b"42");
+    assert_eq!(ima.step(&mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+}
+
+#[test]
+fn run_until_predicate() {
+    let program = parse("\
+    LOAD #0, R1
+loop:
+    ADD #1, R1
+    CMP #10, R1
+    BLT loop
+    HALT
+").expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let mut io = ScriptedIo::default();
+    let outcome = ima.run_until(&mut io, |ima| ima.counters().instructions == 7).unwrap();
+    assert_eq!(outcome, StepOutcome::Continued);
+    assert_eq!(ima.counters().instructions, 7);
+}
+
+#[test]
+fn step_to_breakpoint() {
+    let program = parse(PROGRAM).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    ima.set_breakpoint(3);
+    let mut io = ScriptedIo::new("1\n");
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Breakpoint);
+    assert!(io.output().is_empty());
+    assert_eq!(ima.run_for(10, &mut io).unwrap(), StepOutcome::Halted(ImaExitStatus::Halted));
+    assert_eq!(io.output(), b"2");
+}
+
+#[test]
+fn debug_reports_the_exit_status() {
+    let source = "\
+    LOAD #1, R1
+    ERROR
+";
+    let mut ima = IMA::new(parse(source).unwrap(), ImaOptions::default());
+    let mut output = Vec::new();
+    let outcome = ima.run_until_breakpoint(&mut std::io::Cursor::new(b""), &mut output).unwrap();
+    assert_eq!(outcome, StepOutcome::Halted(ImaExitStatus::ErrorInstruction));
+
+    let mut ima = IMA::new(parse(source).unwrap(), ImaOptions::default());
+    let status = ima.run_debug(&mut std::io::Cursor::new(b"c\n"), &mut output).unwrap();
+    assert_eq!(status, ImaExitStatus::ErrorInstruction);
+
+    let mut ima = IMA::new(parse(source).unwrap(), ImaOptions::default());
+    let status = ima.run_debug(&mut std::io::Cursor::new(b"q\n"), &mut output).unwrap();
+    assert_eq!(status, ImaExitStatus::Halted);
+}
+
+#[test]
+fn debug_keeps_going_after_a_step_error() {
+    let source = "\
+    LOAD 0(R2), R1
+    HALT
+";
+    let mut ima = IMA::new(parse(source).unwrap(), ImaOptions::default());
+    let mut output = Vec::new();
+    let status = ima.run_debug(&mut std::io::Cursor::new(b"x\nx\nq\n"), &mut output).unwrap();
+    assert_eq!(status, ImaExitStatus::Halted);
+    assert_eq!(String::from_utf8(output).unwrap().matches("Error").count(), 2);
+}
@@ -0,0 +1,62 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use crate::{parse, IMA, ImaOptions, complete::{Label, TraceFilter}};
+
+/// Writer shared between the test and the machine.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn trace(filter: TraceFilter) -> Vec<String> {
+    let source_code = "\
+    LOAD #3, R1
+    PUSH R1
+    BSR f
+    HALT
+f:
+    CMP #5, R1
+    STORE R1, 1(GB)
+    RTS
+";
+    let program = parse(source_code).expect("Unable to parse test program");
+    let mut ima = IMA::new(program, ImaOptions::default());
+    let buffer = SharedBuffer::default();
+    ima.set_tracer(buffer.clone(), filter);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    ima.run(&mut input, &mut output).expect("Program should halt");
+    let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+    trace.lines().map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn trace_changes() {
+    let lines = trace(TraceFilter::default());
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], r#"{"step":0,"pc":0,"line":1,"label":null,"instruction":"LOAD #3, R1","cycles":4,"registers":{"R1":3},"flags":{},"memory":[],"output_bytes":0}"#);
+    assert!(lines[1].contains(r#""registers":{"SP":"@ Stack 1"}"#), "{}", lines[1]);
+    assert!(lines[1].contains(r#""memory":[{"addr":"@ Stack 1","value":3}]"#), "{}", lines[1]);
+    assert!(lines[3].contains(r#""flags":{"GT":false,"GE":false,"LT":true,"LE":true}"#), "{}", lines[3]);
+}
+
+#[test]
+fn trace_filters() {
+    let by_label = trace(TraceFilter { lines: vec![], labels: vec![Label("f".to_string())] });
+    assert_eq!(by_label.len(), 3);
+    assert!(by_label.iter().all(|line| line.contains(r#""label":"f""#)));
+
+    let by_lines = trace(TraceFilter { lines: vec![(2, 3)], labels: vec![] });
+    assert_eq!(by_lines.len(), 2);
+    assert!(by_lines[0].starts_with(r#"{"step":1,"pc":1,"line":2,"#));
+}
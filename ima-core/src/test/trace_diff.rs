@@ -0,0 +1,56 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, trace_diff, ImaOptions, TracedRun, complete::StateDifference};
+
+fn traced_run(source_code: &str) -> TracedRun {
+    let program = parse(source_code).expect("Unable to parse test program");
+    TracedRun::new(program, ImaOptions::default(), b"")
+}
+
+#[test]
+fn same_runs() {
+    let source_code = "\
+    LOAD #3, R1
+    WINT
+    HALT
+";
+    assert!(trace_diff(&traced_run(source_code), &traced_run(source_code), 3).is_none());
+}
+
+#[test]
+fn diverging_output() {
+    let first = traced_run("\
+    LOAD #1, R1
+    LOAD #2, R2
+    WSTR \"A\"
+    HALT
+");
+    let second = traced_run("\
+    LOAD #1, R1
+    LOAD #2, R2
+    WSTR \"B\"
+    HALT
+");
+    let divergence = trace_diff(&first, &second, 1).expect("Runs should diverge");
+    assert_eq!(divergence.step, 2);
+    assert_eq!(divergence.context.len(), 2);
+    match &divergence.differences[..] {
+        [StateDifference::Output { first, second }] => assert_eq!((first.as_str(), second.as_str()), ("A", "B")),
+        other => panic!("Expected an output difference, got {other:?}"),
+    }
+}
+
+#[test]
+fn diverging_end() {
+    let first = traced_run("\
+    LOAD #1, R1
+    HALT
+");
+    let second = traced_run("\
+    LOAD #1, R1
+    ERROR
+");
+    let divergence = trace_diff(&first, &second, 5).expect("Runs should diverge");
+    assert_eq!(divergence.step, 2);
+    assert!(matches!(divergence.differences[..], [StateDifference::End { .. }]));
+}
@@ -0,0 +1,85 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ImaError, complete::{
+    ImaExecutionError,
+    CheckMode,
+    RegisterIndex,
+    ValueSource,
+    WarningKind,
+}};
+
+const SOURCE_CODE: &str = "\
+    ADDSP #1
+    LOAD #0, R3
+loop:
+    STORE R2, 1(GB)
+    ADD #1, R3
+    CMP #3, R3
+    BLT loop
+    HALT
+";
+
+fn run(mode: CheckMode) -> (IMA, Result<crate::ImaExitStatus, ImaError>) {
+    let program = parse(SOURCE_CODE).expect("Unable to parse test program");
+    let options = ImaOptions {
+        uninitialized_mode: mode,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    let result = ima.run(&mut input, &mut output);
+    (ima, result)
+}
+
+#[test]
+fn uninitialized_allowed() {
+    let (ima, result) = run(CheckMode::Off);
+    assert!(result.is_ok());
+    assert!(ima.warnings().is_empty());
+}
+
+#[test]
+fn uninitialized_warn() {
+    let (ima, result) = run(CheckMode::Warn);
+    assert!(result.is_ok());
+    match ima.warnings() {
+        [warning] => {
+            assert_eq!(warning.kind, WarningKind::UninitializedRead(ValueSource::Register(RegisterIndex(2))));
+            assert_eq!(warning.line, Some(4));
+            assert_eq!(warning.count, 3);
+        },
+        other => panic!("Expected a single warning, got {other:?}"),
+    }
+}
+
+#[test]
+fn uninitialized_strict() {
+    let (_, result) = run(CheckMode::Strict);
+    match result {
+        Err(ImaError::ExecutionError { error: ImaExecutionError::UninitializedRead(source), line, .. }) => {
+            assert_eq!(source, ValueSource::Register(RegisterIndex(2)));
+            assert_eq!(line, Some(4));
+        },
+        other => panic!("Expected an uninitialized read, got {other:?}"),
+    }
+}
+
+#[test]
+fn uninitialized_strict_keeps_the_pc() {
+    let program = parse("    ADDSP #1\n    STORE R2, 1(GB)\n    HALT\n").expect("Unable to parse test program");
+    let options = ImaOptions {
+        uninitialized_mode: CheckMode::Strict,
+        ..ImaOptions::default()
+    };
+    let mut ima = IMA::new(program, options);
+    let mut input = std::io::Cursor::new(b"");
+    let mut output = Vec::new();
+    // the pc stays on the failing instruction, so running again fails again instead of reaching the HALT
+    for _ in 0..2 {
+        match ima.run(&mut input, &mut output) {
+            Err(ImaError::ExecutionError { error: ImaExecutionError::UninitializedRead(_), line: Some(2), .. }) => {},
+            other => panic!("Expected an uninitialized read on line 2, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+/// Created by Virgile HENRY, 2023/09/28
+
+use crate::{parse, IMA, ImaOptions, ScriptedIo, StepOutcome};
+use crate::complete::{Comparison, DataType, Instruction, RegisterIndex, WatchAccess, WatchCondition, WatchMode, WatchTarget, Watchpoint};
+
+const PROGRAM: &str = "\
+    TSTO #2
+    ADDSP #2
+    LOAD #1, R5
+    STORE R5, 1(GB)
+    LOAD 1(GB), R2
+    ADD #2, R5
+    STORE R5, 1(GB)
+    PUSH R5
+    BSR affiche
+    SUBSP #1
+    HALT
+affiche:
+    LOAD -2(LB), R1
+    WINT
+    RTS
+";
+
+fn machine() -> IMA {
+    IMA::new(parse(PROGRAM).expect("Unable to parse test program"), ImaOptions::default())
+}
+
+fn target(s: &str) -> WatchTarget {
+    s.parse().expect("Unable to parse watchpoint")
+}
+
+/// Run until the next watchpoint, and get the instruction, access, old and new value of its first hit.
+fn next_hit(ima: &mut IMA, io: &mut ScriptedIo) -> Option<(String, WatchAccess, DataType, DataType)> {
+    match ima.run_for(1000, io).unwrap() {
+        StepOutcome::Watchpoint => {
+            let hit = &ima.watchpoints().hits()[0];
+            Some((hit.instruction.to_string(), hit.access, hit.old, hit.new))
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn parse_targets() {
+    assert_eq!(target("R5"), WatchTarget::Register(RegisterIndex(5)));
+    for dadr in ["3(GB)", "-2(LB)", "0(R1)"] {
+        assert!(matches!(target(dadr), WatchTarget::Memory(_)), "{dadr}");
+        assert_eq!(target(dadr).to_string(), dadr);
+    }
+    assert!("GB".parse::<WatchTarget>().is_err());
+    assert!("3(XX)".parse::<WatchTarget>().is_err());
+}
+
+#[test]
+fn memory_write() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    ima.set_watchpoint(target("1(GB)"), WatchMode::Write);
+
+    // the LOAD only reads the word
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("STORE R5, 1(GB)".to_string(), WatchAccess::Write, DataType::Undefined, DataType::Int(1))));
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("STORE R5, 1(GB)".to_string(), WatchAccess::Write, DataType::Int(1), DataType::Int(3))));
+    assert_eq!(next_hit(&mut ima, &mut io), None);
+    assert_eq!(io.output(), b"3");
+}
+
+#[test]
+fn memory_read_or_write() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    ima.set_watchpoint(target("1(GB)"), WatchMode::ReadWrite);
+
+    assert!(next_hit(&mut ima, &mut io).is_some());
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD 1(GB), R2".to_string(), WatchAccess::Read, DataType::Int(1), DataType::Int(1))));
+    let hit = &ima.watchpoints().hits()[0];
+    assert_eq!(hit.pc, 4);
+    assert_eq!(hit.target, target("1(GB)"));
+    assert!(matches!(hit.instruction, Instruction::LOAD(..)));
+    assert_eq!(next_hit(&mut ima, &mut io).map(|hit| hit.1), Some(WatchAccess::Write));
+}
+
+#[test]
+fn address_follows_registers() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    // the parameter of the function, only reachable once LB is set by BSR
+    ima.set_watchpoint(target("-2(LB)"), WatchMode::ReadWrite);
+
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD -2(LB), R1".to_string(), WatchAccess::Read, DataType::Int(3), DataType::Int(3))));
+    assert_eq!(next_hit(&mut ima, &mut io), None);
+}
+
+#[test]
+fn register_write_and_implicit_read() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    ima.set_watchpoint(target("R5"), WatchMode::Write);
+    ima.set_watchpoint(target("R1"), WatchMode::ReadWrite);
+
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD #1, R5".to_string(), WatchAccess::Write, DataType::Undefined, DataType::Int(1))));
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("ADD #2, R5".to_string(), WatchAccess::Write, DataType::Int(1), DataType::Int(3))));
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD -2(LB), R1".to_string(), WatchAccess::Write, DataType::Undefined, DataType::Int(3))));
+    // WINT reads R1 without naming it
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("WINT".to_string(), WatchAccess::Read, DataType::Int(3), DataType::Int(3))));
+    assert_eq!(next_hit(&mut ima, &mut io), None);
+}
+
+#[test]
+fn register_write_with_overflow() {
+    let program = "\
+        LOAD #0, R2
+        LOAD #2147483647, R3
+        ADD #1, R3
+        LOAD #0, R2
+        QUO #0, R2
+        HALT
+    ";
+    let mut ima = IMA::new(parse(program).expect("Unable to parse test program"), ImaOptions::default());
+    let mut io = ScriptedIo::default();
+    ima.set_watchpoint(target("R2"), WatchMode::Write);
+
+    assert!(next_hit(&mut ima, &mut io).is_some());
+    // OV is still set by the ADD, but the LOAD writes R2, even with the same value
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD #0, R2".to_string(), WatchAccess::Write, DataType::Int(0), DataType::Int(0))));
+    assert_eq!(ima.watchpoints().hits()[0].pc, 3);
+    // the division by zero leaves R2 untouched
+    assert_eq!(next_hit(&mut ima, &mut io), None);
+}
+
+#[test]
+fn remove_watchpoint() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    ima.set_watchpoint(target("R5"), WatchMode::Write);
+    // setting it again only changes the mode
+    ima.set_watchpoint(target("R5"), WatchMode::ReadWrite);
+    assert_eq!(ima.watchpoints().points().len(), 1);
+
+    assert!(next_hit(&mut ima, &mut io).is_some());
+    assert!(ima.remove_watchpoint(&target("R5")));
+    assert!(!ima.remove_watchpoint(&target("R5")));
+    assert_eq!(ima.run_for(1000, &mut io).unwrap(), StepOutcome::Halted(crate::ImaExitStatus::Halted));
+    assert!(ima.watchpoints().hits().is_empty());
+}
+
+#[test]
+fn parse_watchpoints() {
+    let watchpoint = |s: &str| s.parse::<Watchpoint>().expect("Unable to parse watchpoint");
+
+    assert_eq!(watchpoint("R5"), Watchpoint { target: target("R5"), mode: WatchMode::Write, condition: None });
+    assert_eq!(watchpoint("3(GB) rw").mode, WatchMode::ReadWrite);
+    // any spacing before the mode
+    assert_eq!(watchpoint("3(GB)  rw"), watchpoint("3(GB) rw"));
+    assert_eq!(watchpoint("3(GB)\trw < 0"), watchpoint("3(GB) rw < 0"));
+    assert_eq!(watchpoint("R5 < 0").condition, Some(WatchCondition { comparison: Comparison::Lt, value: DataType::Int(0) }));
+    assert_eq!(watchpoint("-2(LB) rw >= #1.5").condition, Some(WatchCondition { comparison: Comparison::Ge, value: DataType::Float(1.5) }));
+    for s in ["R5", "3(GB) rw", "R5 < 0", "-2(LB) rw != -3"] {
+        assert_eq!(watchpoint(s).to_string(), s);
+    }
+    assert!("R5 < x".parse::<Watchpoint>().is_err());
+    assert!("R5 =< 0".parse::<Watchpoint>().is_err());
+    assert!("GB < 0".parse::<Watchpoint>().is_err());
+}
+
+#[test]
+fn conditions_compare_numbers() {
+    let condition = |s: &str| s.parse::<WatchCondition>().expect("Unable to parse condition");
+
+    assert!(condition("< 0").holds(DataType::Int(-1)));
+    assert!(!condition("< 0").holds(DataType::Int(0)));
+    assert!(condition("<= 0").holds(DataType::Int(0)));
+    assert!(condition("== 2").holds(DataType::Float(2.0)));
+    assert!(condition("> 1.5").holds(DataType::Int(2)));
+    assert!(condition("!= 3").holds(DataType::Int(2)));
+    // values that are not numbers never match
+    assert!(!condition("!= 3").holds(DataType::Undefined));
+    assert!(!condition("!= 0").holds(DataType::Float(f32::NAN)));
+}
+
+#[test]
+fn conditional_watchpoint() {
+    let mut ima = machine();
+    let mut io = ScriptedIo::default();
+    ima.add_watchpoint("R5 > 2".parse().unwrap());
+    ima.add_watchpoint("1(GB) rw == 1".parse().unwrap());
+
+    // LOAD #1, R5 does not match
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("STORE R5, 1(GB)".to_string(), WatchAccess::Write, DataType::Undefined, DataType::Int(1))));
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("LOAD 1(GB), R2".to_string(), WatchAccess::Read, DataType::Int(1), DataType::Int(1))));
+    assert_eq!(next_hit(&mut ima, &mut io), Some(("ADD #2, R5".to_string(), WatchAccess::Write, DataType::Int(1), DataType::Int(3))));
+    // the STORE of 3 does not match the condition of 1(GB), but reads R5
+    assert_eq!(next_hit(&mut ima, &mut io), None);
+}
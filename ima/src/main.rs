@@ -1,21 +1,22 @@
 /// Created by Virgile HENRY, 2023/09/28
 
-use std::{fmt::Display, error::Error};
+use std::{fmt::Display, error::Error, io::{Read, Write}};
 
 pub use ima_core::*;
 
 #[derive(Debug)]
 pub enum ImaInterpreterError {
     FileNotFound(std::io::Error),
-    ImaError(ImaError),
+    ImaError {
+        file: String,
+        error: ImaError,
+    },
     ParserError(ParserError),
     OptionParsingError(OptionParsingError),
-}
-
-impl From<ImaError> for ImaInterpreterError {
-    fn from(e: ImaError) -> Self {
-        ImaInterpreterError::ImaError(e)
-    }
+    ProfileWriteError(std::io::Error),
+    TraceFileError(std::io::Error),
+    InputReadError(std::io::Error),
+    SnapshotError(SnapshotError),
 }
 
 impl From<ParserError> for ImaInterpreterError {
@@ -24,6 +25,12 @@ impl From<ParserError> for ImaInterpreterError {
     }
 }
 
+impl From<SnapshotError> for ImaInterpreterError {
+    fn from(e: SnapshotError) -> Self {
+        ImaInterpreterError::SnapshotError(e)
+    }
+}
+
 impl From<OptionParsingError> for ImaInterpreterError {
     fn from(e: OptionParsingError) -> Self {
         ImaInterpreterError::OptionParsingError(e)
@@ -34,28 +41,93 @@ impl Display for ImaInterpreterError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ImaInterpreterError::FileNotFound(e) => write!(f, "[IO Error]: File not found ({e})"),
-            ImaInterpreterError::ImaError(e) => write!(f, "{}", e),
+            ImaInterpreterError::ImaError{file, error} => match error.line() {
+                Some(line) => write!(f, "{}:{}: {}", file, line, error),
+                None => write!(f, "{}: {}", file, error),
+            },
             ImaInterpreterError::ParserError(e) => write!(f, "{}", e),
             ImaInterpreterError::OptionParsingError(e) => write!(f, "{}", e),
+            ImaInterpreterError::ProfileWriteError(e) => write!(f, "[IO Error]: Unable to write the profile ({e})"),
+            ImaInterpreterError::TraceFileError(e) => write!(f, "[IO Error]: Unable to create the trace file ({e})"),
+            ImaInterpreterError::InputReadError(e) => write!(f, "[IO Error]: Unable to read the input ({e})"),
+            ImaInterpreterError::SnapshotError(e) => write!(f, "[Snapshot Error]: {e}"),
         }
     }
 }
 
 impl Error for ImaInterpreterError {}
 
+/// Exit codes of the ima process:
+/// - 0: the program executed a HALT instruction (or the debug session ended).
+/// - 1: the program executed an ERROR instruction.
+/// - 2: the program stopped on a runtime error.
+/// - 3: the program was stopped by an execution limit or an infinite loop detection.
+/// - 4: the program could not be parsed.
+/// - 5: the options were invalid, the file could not be read, or a snapshot could not be read or written.
+/// 
+/// With `ima trace-diff`, 0 means both runs are the same, and 1 that they diverge.
+pub mod exit_code {
+    pub const HALT: i32 = 0;
+    pub const ERROR_INSTRUCTION: i32 = 1;
+    pub const RUNTIME_ERROR: i32 = 2;
+    pub const STOPPED: i32 = 3;
+    pub const PARSER_ERROR: i32 = 4;
+    pub const USAGE_ERROR: i32 = 5;
+    pub const SAME_RUNS: i32 = 0;
+    pub const DIVERGING_RUNS: i32 = 1;
+}
+
+/// Number of steps shown before the divergence with `ima trace-diff`.
+const TRACE_DIFF_CONTEXT: usize = 5;
+
+impl ImaInterpreterError {
+    /// Get the process exit code for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ImaInterpreterError::FileNotFound(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::OptionParsingError(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::ProfileWriteError(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::TraceFileError(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::InputReadError(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::SnapshotError(_) => exit_code::USAGE_ERROR,
+            ImaInterpreterError::ParserError(_) => exit_code::PARSER_ERROR,
+            ImaInterpreterError::ImaError{error, ..} => match error {
+                ImaError::InfiniteLoop { .. } |
+                ImaError::CycleLimitReached { .. } |
+                ImaError::InstructionLimitReached { .. } |
+                ImaError::TimeoutReached { .. } |
+                ImaError::OutputLimitReached { .. } => exit_code::STOPPED,
+                _ => exit_code::RUNTIME_ERROR,
+            },
+        }
+    }
+}
+
 fn main() {
-    let res = run();
+    let res = match std::env::args().nth(1).as_deref() {
+        Some("trace-diff") => run_trace_diff().map(|same| match same {
+            true => exit_code::SAME_RUNS,
+            false => exit_code::DIVERGING_RUNS,
+        }),
+        _ => run().map(|status| match status {
+            ImaExitStatus::Halted => exit_code::HALT,
+            ImaExitStatus::ErrorInstruction => exit_code::ERROR_INSTRUCTION,
+        }),
+    };
+
+    // process::exit does not flush the output
+    let _ = std::io::stdout().flush();
 
     match res {
-        Ok(_) => {},
+        Ok(code) => std::process::exit(code),
         Err(e) => {
             eprintln!("[Error] {}", e);
-            std::process::exit(1);
+            std::process::exit(e.exit_code());
         }
     }
 }
 
-fn run() -> Result<(), ImaInterpreterError> {
+fn run() -> Result<ImaExitStatus, ImaInterpreterError> {
     let options = ImaOptions::new(std::env::args())?;
 
     let file = match std::fs::read_to_string(&options.file) {
@@ -63,22 +135,118 @@ fn run() -> Result<(), ImaInterpreterError> {
         Err(e) => return Err(ImaInterpreterError::FileNotFound(e)),
     };
     
+    let file_name = options.file.clone();
+    let profile_output = options.profile_output.clone();
+    let snapshot_on_exit = options.snapshot_on_exit.clone();
+    let snapshot_on_error = options.snapshot_on_error.clone();
+    let restore_snapshot = options.restore_snapshot.clone();
+    let trace = match &options.trace_output {
+        Some(path) => Some((
+            std::fs::File::create(path).map_err(ImaInterpreterError::TraceFileError)?,
+            options.trace_filter.clone(),
+        )),
+        None => None,
+    };
+    let ima_error = |error| ImaInterpreterError::ImaError { file: file_name.clone(), error };
+
     let stdio = std::io::stdin();
     let mut input = stdio.lock();
     let mut output = std::io::stdout();
     
-    match options.run_mode {
-        ImaRunMode::Debug => {
-            let program = parse_debug(&file)?;
-            let mut ima = IMA::new(program, options);
-            ima.run_debug(&mut input, &mut output)?;
+    // release and debug runs execute the same program
+    let program = parse_with_registers(&file, options.register_count)?;
+    let run_mode = options.run_mode.clone();
+    let mut ima = IMA::new(program, options);
+    if let Some((trace_file, filter)) = trace {
+        ima.set_tracer(std::io::BufWriter::new(trace_file), filter);
+    }
+    if let Some(path) = restore_snapshot {
+        let snapshot_file = std::fs::File::open(path).map_err(SnapshotError::Io)?;
+        ima.restore(Snapshot::read(std::io::BufReader::new(snapshot_file))?)?;
+    }
+    let result = match run_mode {
+        ImaRunMode::Debug => ima.run_debug(&mut input, &mut output),
+        _ => ima.run(&mut input, &mut output),
+    }.map_err(ima_error);
+    print_warnings(&ima, &file_name);
+    write_profile(&ima, &file, profile_output)?;
+    let snapshot_path = match result {
+        Ok(_) => snapshot_on_exit,
+        Err(_) => snapshot_on_error,
+    };
+    write_snapshot(&ima, snapshot_path)?;
+    result
+}
+
+/// Run two programs with the same options and input, and print the first point where they diverge.
+/// Usage: `ima trace-diff [options] first.ass second.ass`.
+/// Returns true if both runs are the same.
+fn run_trace_diff() -> Result<bool, ImaInterpreterError> {
+    let mut args = std::env::args().collect::<Vec<_>>();
+    // remove the subcommand, and take the second file: the options end with the first one
+    args.remove(1);
+    let second_file = match args.len() {
+        0..=2 => return Err(ImaInterpreterError::OptionParsingError(OptionParsingError::NoFileProvided)),
+        _ => args.pop().unwrap(),
+    };
+    let options = ImaOptions::new(args.into_iter())?;
+
+    let read = |path: &str| std::fs::read_to_string(path).map_err(ImaInterpreterError::FileNotFound);
+    let first_program = parse_with_registers(&read(&options.file)?, options.register_count)?;
+    let second_program = parse_with_registers(&read(&second_file)?, options.register_count)?;
+
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input).map_err(ImaInterpreterError::InputReadError)?;
+
+    let first = TracedRun::new(first_program, options.clone(), &input);
+    let second = TracedRun::new(second_program, options, &input);
+
+    match trace_diff(&first, &second, TRACE_DIFF_CONTEXT) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            Ok(false)
         },
-        _ => {
-            let program = parse(&file)?;
-            let mut ima = IMA::new(program, options);
-            ima.run(&mut input, &mut output)?;
-        },  
+        None => {
+            println!("Both runs are the same ({} steps).", first.entries.len());
+            Ok(true)
+        }
+    }
+}
+
+/// Print the warnings raised by the machine on the error output.
+fn print_warnings(ima: &IMA, file_name: &str) {
+    for warning in ima.warnings() {
+        match warning.line {
+            Some(line) => eprintln!("{}:{}: {}", file_name, line, warning),
+            None => eprintln!("{}: {}", file_name, warning),
+        }
+    }
+}
+
+/// Write the profile report to the given path, and the folded stacks next to it.
+fn write_profile(ima: &IMA, source: &str, path: Option<String>) -> Result<(), ImaInterpreterError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let write = || -> std::io::Result<()> {
+        let mut report = std::fs::File::create(&path)?;
+        ima.write_profile_report(source, &mut report)?;
+        let mut folded = std::fs::File::create(format!("{}.folded", path))?;
+        ima.write_folded_stacks(&mut folded)
     };
+    write().map_err(ImaInterpreterError::ProfileWriteError)
+}
 
+/// Write a snapshot of the machine to the given path.
+fn write_snapshot(ima: &IMA, path: Option<String>) -> Result<(), ImaInterpreterError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let snapshot_file = std::fs::File::create(path).map_err(SnapshotError::Io)?;
+    let mut output = std::io::BufWriter::new(snapshot_file);
+    ima.snapshot().write(&mut output)?;
+    output.flush().map_err(SnapshotError::Io)?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -6,7 +6,6 @@ pub enum VimaError {
     OptionParsing(ima_core::OptionParsingError),
     IO(std::io::Error),
     ImaParser(ima_core::ParserError),
-    ImaExecution(ima_core::complete::ImaExecutionError)
 }
 
 impl From<ima_core::OptionParsingError> for VimaError {
@@ -27,19 +26,12 @@ impl From<ima_core::ParserError> for VimaError {
     }
 }
 
-impl From<ima_core::complete::ImaExecutionError> for VimaError {
-    fn from(e: ima_core::complete::ImaExecutionError) -> Self {
-        VimaError::ImaExecution(e)
-    }
-}
-
 impl Display for VimaError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             VimaError::OptionParsing(e) => write!(f, "{}", e),
             VimaError::IO(e) => write!(f, "{}", e),
             VimaError::ImaParser(e) => write!(f, "{}", e),
-            VimaError::ImaExecution(e) => write!(f, "{}", e),
         }
     }
 }
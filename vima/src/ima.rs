@@ -1,5 +1,5 @@
 use crossterm::event;
-use ima_core::{*, complete::ImaControlFlow};
+use ima_core::{*, complete::{ImaControlFlow, WatchTarget, Watchpoint}};
 
 use ratatui::{
     Terminal,
@@ -10,13 +10,14 @@ use ratatui::{
 
 use crate::{io::IO, error::VimaError};
 
-mod instructions;
+mod input;
 mod ui;
 
 /// Wrapper around a real IMA, that will intercept I/O.
 pub struct VisualIMA<'a, B: Backend> {
-    ima: IMA<DebugModeProgram>,
+    ima: IMA,
     terminal: &'a mut Terminal<B>,
+    io: ScriptedIo,
     debug_io: IO,
     ima_io: IO,
     ima_io_mode: bool,
@@ -24,10 +25,11 @@ pub struct VisualIMA<'a, B: Backend> {
 
 impl<'a, B: Backend> VisualIMA<'a, B> {
 
-    pub fn new(ima: IMA<DebugModeProgram>, terminal: &'a mut Terminal<B>) -> Self {
+    pub fn new(ima: IMA, terminal: &'a mut Terminal<B>) -> Self {
         Self {
             ima,
             terminal,
+            io: ScriptedIo::default(),
             debug_io: IO::default(),
             ima_io: IO::default(),
             ima_io_mode: false,
@@ -105,11 +107,51 @@ impl<'a, B: Backend> VisualIMA<'a, B> {
         let (c, args) = command.split_at(1);
 
         match (c, args) {
-            ("x", "") => self.execute_instr()?,
+            ("x", "") => { self.execute_instr()?; },
             ("c", "") => self.execute_until_breakpoint()?,
+            ("u", "") => if !self.ima.step_back() {
+                self.debug_io.concat_line("No instruction to step back.");
+                self.debug_io.new_line();
+            },
+            ("v", "") => if !self.ima.reverse_continue() {
+                self.debug_io.concat_line("Reached the start of the history.");
+                self.debug_io.new_line();
+            },
+            ("g", arg) => {
+                match arg.trim().parse::<usize>() {
+                    Ok(cycle) => {
+                        let result = loop {
+                            match self.ima.go_to_cycle(cycle, &mut self.io) {
+                                Ok(CycleOutcome::NeedsInput) => self.prompt_ima_input()?,
+                                result => break result,
+                            }
+                        };
+                        // show what the instructions wrote on the way
+                        let output = String::from_utf8_lossy(&self.io.take_output()).into_owned();
+                        self.display_ima_output(&output);
+                        match result {
+                            Ok(CycleOutcome::Reached) => {},
+                            Ok(_) => {
+                                self.debug_io.concat_line(&format!("Unable to reach cycle {cycle}."));
+                                self.debug_io.new_line();
+                            },
+                            Err(e) => {
+                                self.display_ima_output(&format!("{e}"));
+                                self.ima_io.flush_input();
+                                self.ima_io.new_line();
+                            },
+                        }
+                    },
+                    Err(_) => {
+                        self.debug_io.concat_line("Invalid argument: exepected usize");
+                        self.debug_io.new_line();
+                    }
+                }
+            }
             ("a", arg) => {
                 match arg.trim().parse::<u32>() {
-                    Ok(n) => self.ima.code.set_breakpoint(n),
+                    // the program is shown with the lines counted from 1
+                    Ok(n) => self.ima.code.set_breakpoint(n.saturating_sub(1)),
                     Err(_) => {
                         self.debug_io.concat_line("Invalid argument: exepected u32");
                         self.debug_io.new_line();
@@ -118,13 +160,34 @@ impl<'a, B: Backend> VisualIMA<'a, B> {
             }
             ("e", arg) => {
                 match arg.trim().parse::<u32>() {
-                    Ok(n) => self.ima.code.remove_breakpoint(n),
+                    Ok(n) => self.ima.code.remove_breakpoint(n.saturating_sub(1)),
                     Err(_) => {
                         self.debug_io.concat_line("Invalid argument: exepected u32");
                         self.debug_io.new_line();
                     }
                 }
             }
+            ("w", arg) => {
+                match arg.parse::<Watchpoint>() {
+                    Ok(watchpoint) => self.ima.add_watchpoint(watchpoint),
+                    Err(e) => {
+                        self.debug_io.concat_line(&format!("{e}"));
+                        self.debug_io.new_line();
+                    }
+                }
+            }
+            ("k", arg) => {
+                match arg.parse::<WatchTarget>() {
+                    Ok(target) => if !self.ima.remove_watchpoint(&target) {
+                        self.debug_io.concat_line(&format!("No watchpoint on {target}."));
+                        self.debug_io.new_line();
+                    },
+                    Err(e) => {
+                        self.debug_io.concat_line(&format!("{e}"));
+                        self.debug_io.new_line();
+                    }
+                }
+            }
 
 
             _ => {
@@ -136,22 +199,40 @@ impl<'a, B: Backend> VisualIMA<'a, B> {
         Ok(())
     }
 
-    fn execute_instr(&mut self) -> Result<(), VimaError> {
-        let instruction = match self.ima.code.fetch() {
-            Some(ins) => ins.clone(),
-            None => return Ok(()),
+    /// Execute the next instruction, prompting the user if it waits for input.
+    /// Ima errors are displayed, as they are not fatal errors in debug mode: None is returned then.
+    fn execute_instr(&mut self) -> Result<Option<StepOutcome>, VimaError> {
+        let result = loop {
+            match self.ima.step(&mut self.io) {
+                Ok(StepOutcome::NeedsInput) => self.prompt_ima_input()?,
+                result => break result,
+            }
         };
-        self.ima.code.increment_pc();
 
-        // input is hijacked anyway, so we can just pass an empty one
-        let mut input = std::io::Cursor::new(b"");
-        let mut output = Vec::new();
+        // read what the instruction wrote to output
+        let output = String::from_utf8_lossy(&self.io.take_output()).into_owned();
+        self.display_ima_output(&output);
+
+        // show the old and new values of the watchpoints that fired
+        let hits = self.ima.watchpoints().hits().iter().map(ToString::to_string).collect::<Vec<_>>();
+        for hit in hits {
+            self.debug_io.concat_line(&hit);
+            self.debug_io.new_line();
+        }
 
-        let result = self.execute(instruction.clone(), &mut input, &mut output);
+        match result {
+            Ok(outcome) => Ok(Some(outcome)),
+            Err(e) => {
+                self.display_ima_output(&format!("{e}"));
+                self.ima_io.flush_input();
+                self.ima_io.new_line();
+                Ok(None)
+            }
+        }
+    }
 
-        // read what the instruction wrote to output
-        // safety: we know utf8 chars have been written anyway
-        let output = String::from_utf8(output).unwrap();
+    /// Display text in the ima I/O area.
+    fn display_ima_output(&mut self, output: &str) {
         for c in output.chars() {
             match c {
                 '\n' => {
@@ -162,44 +243,17 @@ impl<'a, B: Backend> VisualIMA<'a, B> {
                 _ => self.ima_io.enter_char(c),
             }
         }
-
-        match result {
-            Err(VimaError::ImaExecution(e)) => {
-                // catch ima execution errors, as we have to display them, they are not fatal errors in debug mode.
-                let result_output = format!("{e}");
-                for c in result_output.chars() {
-                    match c {
-                        '\n' => {
-                            self.ima_io.flush_input();
-                            self.ima_io.new_line();
-                            self.ima_io.reset_cursor();
-                        }
-                        _ => self.ima_io.enter_char(c),
-                    }
-                }
-                self.ima_io.flush_input();
-                self.ima_io.new_line();
-                Ok(())
-            }
-            _ => result
-        }
-
     }
 
     fn execute_until_breakpoint(&mut self) -> Result<(), VimaError> {
         loop {
-            self.execute_instr()?;
+            let outcome = self.execute_instr()?;
 
             self.render()?;
 
-            if self.ima.code.is_breakpoint() {
-                break Ok(());
-            }
-
-            match self.ima.control_flow {
-                ImaControlFlow::Continue => (),
-                ImaControlFlow::Halt => break Ok(()),
-                ImaControlFlow::Error => break Ok(()),
+            match outcome {
+                Some(StepOutcome::Continued) => (),
+                _ => break Ok(()),
             }
 
             // safeguard: if there is a key press, stop
@@ -214,7 +268,7 @@ impl<'a, B: Backend> VisualIMA<'a, B> {
     }
 }
 
-fn draw_ima<B: Backend>(frame: &mut Frame<B>, ima: &IMA<DebugModeProgram>, debug_io: &IO, ima_io: &IO, ima_io_mode: bool) {
+fn draw_ima<B: Backend>(frame: &mut Frame<B>, ima: &IMA, debug_io: &IO, ima_io: &IO, ima_io_mode: bool) {
     let (
         debug_area,
         ima_io_area,
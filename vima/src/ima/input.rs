@@ -0,0 +1,53 @@
+use ima_core::complete::Instruction;
+use crate::error::VimaError;
+
+use super::VisualIMA;
+
+use ratatui::prelude::Backend;
+
+impl<'a, B: Backend> VisualIMA<'a, B> {
+
+    /// Ask the user for the input the next instruction is waiting for, and give it to the ima.
+    /// RUTF8 takes each key as it is typed, until the 4 bytes of its word are there, RINT and RFLOAT wait for a whole line.
+    pub fn prompt_ima_input(&mut self) -> Result<(), VimaError> {
+        let single_char = matches!(self.ima.code.fetch(), Some(Instruction::RUTF8));
+
+        // any things in input is flushed in the lines
+        self.ima_io.flush_input();
+        self.ima_io_mode = true;
+
+        let result: String = loop {
+            self.render()?;
+
+            let event = crossterm::event::read()?;
+
+            match event {
+                crossterm::event::Event::Key(k) if k.kind == crossterm::event::KeyEventKind::Press => match k.code {
+                    crossterm::event::KeyCode::Char(c) if single_char => {
+                        self.ima_io.enter_char(c);
+                        self.ima_io.flush_input();
+                        self.ima_io.new_line();
+                        break String::from(c);
+                    },
+                    crossterm::event::KeyCode::Char(c) => self.ima_io.enter_char(c),
+                    crossterm::event::KeyCode::Backspace => self.ima_io.delete_char(),
+                    crossterm::event::KeyCode::Left => self.ima_io.move_cursor_left(),
+                    crossterm::event::KeyCode::Right => self.ima_io.move_cursor_right(),
+                    crossterm::event::KeyCode::Enter if !single_char => {
+                        let input = self.ima_io.input();
+                        self.ima_io.flush_input();
+                        self.ima_io.new_line();
+                        break input + "\n";
+                    },
+                    _ => {},
+                }
+                _ => {},
+            }
+        };
+
+        self.ima_io_mode = false;
+        self.io.push_input(&result);
+
+        Ok(())
+    }
+}
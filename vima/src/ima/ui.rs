@@ -1,4 +1,4 @@
-use ima_core::{IMA, DebugModeProgram};
+use ima_core::IMA;
 use ratatui::{
     prelude::{
         Backend,
@@ -109,7 +109,7 @@ pub fn draw_io(frame: &mut Frame<impl Backend>, area: Rect, debug_io: &IO, focus
     }
 }
 
-pub fn draw_program(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_program(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title("Program");
@@ -117,24 +117,29 @@ pub fn draw_program(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<Debug
     let available_space = area.height as u32 - 2;
     // somewhat it's nice to have the pc at the first third ?
 
-    let mut program_start = ima.code.pc.saturating_sub(available_space / 3);
-    let mut program_end = program_start + available_space.min(ima.code.code.0.len() as u32 - program_start);
+    let debug_info = ima.code.debug_info();
+    let line_count = debug_info.lines.len() as u32;
+    let pc_line = debug_info.line_of(ima.code.pc).map(|line| line as u32).unwrap_or(line_count);
+
+    let mut program_start = pc_line.saturating_sub(available_space / 3).min(line_count);
+    let mut program_end = program_start + available_space.min(line_count - program_start);
 
     if program_end - program_start < available_space {
         // we have to center the program
         program_start = program_start.saturating_sub(available_space - (program_end - program_start));
-        program_end = program_start + available_space.min(ima.code.code.0.len() as u32 - program_start);
+        program_end = program_start + available_space.min(line_count - program_start);
     }
 
-    let lines = ima.code.code.0[program_start as usize..program_end as usize].iter().enumerate().map(|(i, (line, bp))| {
+    let lines = debug_info.lines[program_start as usize..program_end as usize].iter().enumerate().map(|(i, line)| {
         let line_n = i + program_start as usize;
-        let pc = if ima.code.pc as usize == line_n { "PC>" } else { "   " };
-        let bpc = if *bp { "⬤" } else { " " };
+        let pc = if pc_line as usize == line_n { "PC>" } else { "   " };
+        let bp = line.instruction.is_some_and(|addr| ima.code.is_breakpoint(addr));
+        let bpc = if bp { "⬤" } else { " " };
         let mut line_disp = Vec::new();
         for label in line.labels.iter() {
             line_disp.push(Span::styled(format!("{}: ", label.0), Style::default().fg(Color::Yellow)))
         }
-        match &line.instruction {
+        match line.instruction.and_then(|addr| ima.code.instruction(addr)) {
             Some(instr) => line_disp.push(Span::styled(format!("{}", instr), Style::default().fg(Color::White))),
             None => {},
         }
@@ -143,7 +148,7 @@ pub fn draw_program(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<Debug
             None => {},
         }
         let mut spans = vec![
-            Span::styled(format!("{line_n:<4}"), Style::default().fg(if *bp {Color::Red} else {Color::LightBlue})),
+            Span::styled(format!("{:<4}", line_n + 1), Style::default().fg(if bp {Color::Red} else {Color::LightBlue})),
             Span::styled(format!("{pc}"), Style::default().fg(Color::LightGreen)),
             Span::styled(format!("{bpc}"), Style::default().fg(Color::Red)),
         ];
@@ -156,16 +161,30 @@ pub fn draw_program(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<Debug
     frame.render_widget(program, area);
 }
 
-pub fn draw_registers(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_registers(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title("Registers");
 
-    let registers = ima.registers.registers.iter().enumerate().map(|(i, d)| {
-        let spans = vec![
-            Span::styled(format!(" R{i:<2} : "), Style::default()),
-            Span::styled(format!("{d}"), Style::default().fg(if d.is_undefined() { Color::DarkGray } else { Color::White })),
-        ];
+    // when the registers don't fit in the height, they are laid out in columns
+    let count = ima.registers.registers.len();
+    let rows = (area.height as usize).saturating_sub(2).max(1);
+    let columns = count.div_ceil(rows);
+    let index_width = (count - 1).to_string().len().max(2);
+    let value_width = (area.width as usize).saturating_sub(2) / columns.max(1);
+
+    let registers = (0..rows.min(count)).map(|row| {
+        let spans = (row..count).step_by(rows).flat_map(|i| {
+            let d = &ima.registers.registers[i];
+            let label = format!(" R{i:<index_width$} : ");
+            let value = format!("{d}");
+            let padding = value_width.saturating_sub(label.len() + value.len());
+            vec![
+                Span::styled(label, Style::default()),
+                Span::styled(value, Style::default().fg(if d.is_undefined() { Color::DarkGray } else { Color::White })),
+                Span::raw(" ".repeat(padding)),
+            ]
+        }).collect::<Vec<_>>();
         Line::from(spans)
     }).collect::<Vec<_>>();
 
@@ -174,7 +193,7 @@ pub fn draw_registers(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<Deb
     frame.render_widget(registers, area);
 }
 
-pub fn draw_stack(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_stack(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title("Stack");
@@ -215,7 +234,7 @@ pub fn draw_stack(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugMo
     frame.render_widget(stack, area);
 }
 
-pub fn draw_heap(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_heap(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title("Heap");
@@ -243,7 +262,7 @@ pub fn draw_heap(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugMod
     frame.render_widget(heap, area);
 }
 
-pub fn draw_energy(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_energy(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     use ima_core::complete::CycleCost;
 
     let block = Block::default()
@@ -270,7 +289,7 @@ pub fn draw_energy(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugM
     frame.render_widget(energy, area);
 }
 
-pub fn draw_flags(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA<DebugModeProgram>) {
+pub fn draw_flags(frame: &mut Frame<impl Backend>, area: Rect, ima: &IMA) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title("Flags");
@@ -10,7 +10,7 @@ use crossterm::{
 };
 use error::VimaError;
 use ima::VisualIMA;
-use ima_core::{IMA, ImaOptions, parse_debug};
+use ima_core::{IMA, ImaOptions, parse_with_registers, complete::DEFAULT_HISTORY};
 use ratatui::prelude::*;
 
 mod io;
@@ -30,13 +30,15 @@ fn main() {
 
 fn run() -> Result<(), VimaError> {
     // setup ima
-    let ima_options = ImaOptions::new(std::env::args())?;
+    let mut ima_options = ImaOptions::new(std::env::args())?;
+    // vima is always debugging, so it can always step back
+    ima_options.history.get_or_insert(DEFAULT_HISTORY);
     let file = match std::fs::read_to_string(&ima_options.file) {
         Ok(s) => s,
         Err(e) => return Err(e.into()),
     };
 
-    let program = parse_debug(&file)?;
+    let program = parse_with_registers(&file, ima_options.register_count)?;
     let ima = IMA::new(program, ima_options);
 
     // setup terminal